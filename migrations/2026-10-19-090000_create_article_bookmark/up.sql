@@ -0,0 +1,12 @@
+-- Your SQL goes here
+
+CREATE TABLE IF NOT EXISTS "article_bookmark" (
+	"id" INTEGER NOT NULL PRIMARY KEY AUTOINCREMENT,
+	"user_id" VARCHAR(255) NOT NULL  ,
+	"article_id" VARCHAR(255) NOT NULL  ,
+	"anchor" VARCHAR(255) NOT NULL  ,
+	"label" VARCHAR(255) NOT NULL DEFAULT '' ,
+	"created_at" BIGINT NOT NULL  ,
+	FOREIGN KEY("article_id") REFERENCES "article" ("id") ON UPDATE RESTRICT ON DELETE RESTRICT
+);
+CREATE INDEX IF NOT EXISTS "article_bookmark_user_id_article_id_IDX" ON "article_bookmark" ("user_id", "article_id");
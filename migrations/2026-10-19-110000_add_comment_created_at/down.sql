@@ -0,0 +1,3 @@
+-- This file should undo anything in `up.sql`
+DROP INDEX IF EXISTS "article_comment_articleId_parentCommentId_IDX";
+ALTER TABLE "article_comment" DROP COLUMN "createdAt";
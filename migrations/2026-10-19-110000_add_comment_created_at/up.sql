@@ -0,0 +1,5 @@
+-- Your SQL goes here
+
+-- seconds since the epoch, 0 when the source didn't tell
+ALTER TABLE "article_comment" ADD COLUMN "createdAt" BIGINT NOT NULL DEFAULT 0;
+CREATE INDEX IF NOT EXISTS "article_comment_articleId_parentCommentId_IDX" ON "article_comment" ("articleId", "parentCommentId");
@@ -0,0 +1,36 @@
+-- Your SQL goes here
+
+-- comments written by readers, listed along with the imported article_comment
+CREATE TABLE IF NOT EXISTS "user_comment" (
+	"id" VARCHAR(255) NOT NULL PRIMARY KEY,
+	"article_id" VARCHAR(255) NOT NULL  ,
+	-- an article_comment or a user_comment
+	"parent_id" VARCHAR(255) NULL  ,
+	"user_id" VARCHAR(255) NOT NULL  ,
+	"nick_name" VARCHAR(255) NOT NULL  ,
+	"content" TEXT NOT NULL  ,
+	"like_count" INTEGER NOT NULL DEFAULT '0' ,
+	"created_at" BIGINT NOT NULL  ,
+	"updated_at" BIGINT NOT NULL DEFAULT '0' ,
+	-- removed by its author while it had replies, kept without content
+	"deleted" TINYINT NOT NULL DEFAULT '0' ,
+	FOREIGN KEY("article_id") REFERENCES "article" ("id") ON UPDATE RESTRICT ON DELETE RESTRICT
+);
+CREATE INDEX IF NOT EXISTS "user_comment_article_id_parent_id_IDX" ON "user_comment" ("article_id", "parent_id");
+CREATE INDEX IF NOT EXISTS "user_comment_parent_id_IDX" ON "user_comment" ("parent_id");
+CREATE INDEX IF NOT EXISTS "user_comment_user_id_created_at_IDX" ON "user_comment" ("user_id", "created_at");
+CREATE TABLE IF NOT EXISTS "comment_like" (
+	"comment_id" VARCHAR(255) NOT NULL  ,
+	"user_id" VARCHAR(255) NOT NULL  ,
+	"created_at" BIGINT NOT NULL  ,
+	PRIMARY KEY ("comment_id", "user_id")
+);
+CREATE INDEX IF NOT EXISTS "comment_like_user_id_IDX" ON "comment_like" ("user_id");
+-- reports of comments for the moderators, one per user and comment
+CREATE TABLE IF NOT EXISTS "comment_flag" (
+	"comment_id" VARCHAR(255) NOT NULL  ,
+	"user_id" VARCHAR(255) NOT NULL  ,
+	"reason" VARCHAR(255) NOT NULL DEFAULT '' ,
+	"created_at" BIGINT NOT NULL  ,
+	PRIMARY KEY ("comment_id", "user_id")
+);
@@ -0,0 +1,3 @@
+-- This file should undo anything in `up.sql`
+DROP TABLE "course_article_count";
+DROP TABLE "notification";
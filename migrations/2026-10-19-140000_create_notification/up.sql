@@ -0,0 +1,27 @@
+-- Your SQL goes here
+
+-- kind is "reply" or "new_articles", the columns which don't apply to it
+-- are left empty
+CREATE TABLE IF NOT EXISTS "notification" (
+	"id" INTEGER NOT NULL PRIMARY KEY AUTOINCREMENT,
+	"user_id" VARCHAR(255) NOT NULL  ,
+	"kind" VARCHAR(255) NOT NULL  ,
+	"course_id" VARCHAR(255) NOT NULL DEFAULT '' ,
+	"article_id" VARCHAR(255) NOT NULL DEFAULT '' ,
+	"comment_id" VARCHAR(255) NOT NULL DEFAULT '' ,
+	-- nick name of who replied, or title of the course
+	"title" VARCHAR(255) NOT NULL DEFAULT '' ,
+	-- start of the reply
+	"excerpt" TEXT NOT NULL DEFAULT '' ,
+	"article_count" INTEGER NOT NULL DEFAULT '0' ,
+	"created_at" BIGINT NOT NULL  ,
+	-- 0 until read
+	"read_at" BIGINT NOT NULL DEFAULT '0'
+);
+CREATE INDEX IF NOT EXISTS "notification_user_id_IDX" ON "notification" ("user_id", "read_at");
+-- articles per course as of the last import, to tell which courses got new ones
+CREATE TABLE IF NOT EXISTS "course_article_count" (
+	"course_id" VARCHAR(255) NOT NULL PRIMARY KEY,
+	"article_count" INTEGER NOT NULL  ,
+	"updated_at" BIGINT NOT NULL
+);
@@ -0,0 +1 @@
+-- the indexes belong to the first migration
@@ -0,0 +1,11 @@
+-- databases made by the crawler already have the tables of the first
+-- migration, which is then only recorded as applied, not run
+CREATE INDEX IF NOT EXISTS "FK_3a0b4db0ebe5e37bc9b09c4129c" ON "article" ("sectionId");
+CREATE INDEX IF NOT EXISTS "FK_4d5ab30629a42bad659fe1d4da6" ON "article_comment" ("articleId");
+CREATE INDEX IF NOT EXISTS "FK_58ace9f389e6aecc2e8fd78c488" ON "article_comment" ("parentCommentId");
+CREATE INDEX IF NOT EXISTS "user_role_user_id_IDX" ON "user_role" ("user_id");
+CREATE UNIQUE INDEX IF NOT EXISTS "uniq_key" ON "user_study_info" ("user_id", "article_id");
+CREATE INDEX IF NOT EXISTS "user_study_info_FK" ON "user_study_info" ("course_id");
+CREATE INDEX IF NOT EXISTS "user_study_info_FK_1" ON "user_study_info" ("article_id");
+CREATE INDEX IF NOT EXISTS "user_study_info_user_id_IDX" ON "user_study_info" ("user_id", "last_study_at");
+CREATE INDEX IF NOT EXISTS "ws_connect_info_user_id_start_at_index" ON "ws_connect_info" ("user_id", "start_at");
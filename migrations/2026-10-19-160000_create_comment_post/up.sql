@@ -0,0 +1,12 @@
+-- Your SQL goes here
+
+-- every comment posted, kept after the comment is deleted so that deleting
+-- doesn't give back room under the rate limit
+CREATE TABLE IF NOT EXISTS "comment_post" (
+	"id" INTEGER NOT NULL PRIMARY KEY AUTOINCREMENT,
+	"user_id" VARCHAR(255) NOT NULL  ,
+	"created_at" BIGINT NOT NULL
+);
+CREATE INDEX IF NOT EXISTS "comment_post_user_id_created_at_IDX" ON "comment_post" ("user_id", "created_at");
+INSERT INTO "comment_post" ("user_id", "created_at")
+	SELECT "user_id", "created_at" FROM "user_comment";
@@ -0,0 +1,69 @@
+"""
+文章内容文件的读写
+
+`just compress-contents` 会把 storage/contents/{id}.html 替换为 zstd 压缩的
+{id}.html.zst（使用 storage/contents.dict 字典）。两种文件可以同时存在，
+未压缩的优先，与服务端的读取方式一致。
+
+写入总是生成未压缩的 {id}.html 并删除旧的 .zst，之后再次运行
+`just compress-contents` 即可重新压缩。
+"""
+
+import subprocess
+from pathlib import Path
+from typing import List, Optional
+
+SUFFIX = ".html"
+ZST_SUFFIX = ".html.zst"
+
+
+def content_file(contents_dir: Path, article_id: str) -> Optional[Path]:
+    """文章内容文件，压缩与否，不存在时返回 None"""
+    for suffix in (SUFFIX, ZST_SUFFIX):
+        path = contents_dir / f"{article_id}{suffix}"
+        if path.is_file():
+            return path
+    return None
+
+
+def article_id(path: Path) -> str:
+    """内容文件对应的文章 ID"""
+    name = path.name
+    for suffix in (ZST_SUFFIX, SUFFIX):
+        if name.endswith(suffix):
+            return name[: -len(suffix)]
+    return path.stem
+
+
+def list_content_files(contents_dir: Path) -> List[Path]:
+    """所有文章内容文件，每篇文章一个，按文章 ID 排序"""
+    files = {}
+    for path in contents_dir.glob(f"*{ZST_SUFFIX}"):
+        files[article_id(path)] = path
+    # 未压缩的优先
+    for path in contents_dir.glob(f"*{SUFFIX}"):
+        files[article_id(path)] = path
+    return [files[key] for key in sorted(files)]
+
+
+def read_content(path: Path) -> str:
+    """读取文章内容，.zst 文件用 zstd 命令解压"""
+    if not path.name.endswith(ZST_SUFFIX):
+        return path.read_text(encoding="utf-8")
+
+    cmd = ["zstd", "-d", "-c", "-q"]
+    dict_path = path.parent.parent / "contents.dict"
+    if dict_path.is_file():
+        cmd += ["-D", str(dict_path)]
+    result = subprocess.run(cmd + [str(path)], capture_output=True, check=True)
+    return result.stdout.decode("utf-8")
+
+
+def write_content(path: Path, content: str) -> Path:
+    """写入未压缩的内容，替换同一篇文章的 .zst 文件，返回写入的文件"""
+    plain = path.parent / f"{article_id(path)}{SUFFIX}"
+    plain.write_text(content, encoding="utf-8")
+    compressed = path.parent / f"{article_id(path)}{ZST_SUFFIX}"
+    if compressed.exists():
+        compressed.unlink()
+    return plain
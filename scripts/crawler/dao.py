@@ -52,6 +52,10 @@ class DAO:
                 await self._conn.close()
                 self._conn = None
 
+    def mark_imported(self):
+        """通知服务端导入完成，清空内存中的课程和文章缓存"""
+        (self.db_dir / "last_import").touch()
+
     async def init_cache(self):
         """初始化缓存"""
         conn = await self._get_connection()
@@ -63,9 +67,11 @@ class DAO:
 
     def has_article_content(self, article_id: str) -> bool:
         """检查文章内容是否已存在"""
-        # 检查文件是否存在
-        content_file = self.storage_contents / f"{article_id}.html"
-        return content_file.exists()
+        # 检查文件是否存在，`just compress-contents` 之后是 {id}.html.zst
+        return any(
+            (self.storage_contents / f"{article_id}{suffix}").exists()
+            for suffix in (".html", ".html.zst")
+        )
 
     async def save_course(self, course: dict) -> bool:
         """保存课程（只新增不更新，避免覆盖 done 标记）"""
@@ -183,8 +189,8 @@ class DAO:
             await cursor.execute(
                 """
                 INSERT INTO article_comment
-                (id, content, likeCount, nickName, articleId, parentCommentId)
-                VALUES (?, ?, ?, ?, ?, ?)
+                (id, content, likeCount, nickName, articleId, parentCommentId, createdAt)
+                VALUES (?, ?, ?, ?, ?, ?, ?)
             """,
                 (
                     comment["id"],
@@ -193,6 +199,7 @@ class DAO:
                     comment.get("nickName", ""),
                     comment.get("articleId"),
                     comment.get("parentCommentId"),
+                    comment.get("createdAt", 0),
                 ),
             )
             await conn.commit()
@@ -219,6 +226,10 @@ class DAO:
             content_file = self.storage_contents / f"{article_id}.html"
             with open(content_file, "w", encoding="utf-8") as f:
                 f.write(content)
+            # 旧的压缩版本已过期，下次 `just compress-contents` 会重新压缩
+            stale = self.storage_contents / f"{article_id}.html.zst"
+            if stale.exists():
+                stale.unlink()
 
         loop = asyncio.get_event_loop()
         await loop.run_in_executor(None, _save)
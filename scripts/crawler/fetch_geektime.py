@@ -242,6 +242,7 @@ async def save_comments(requester: Requester, article: dict, raw_id: str):
             "likeCount": v.get("like_count", 0),
             "content": v.get("comment_content", ""),
             "articleId": article["id"],
+            "createdAt": v.get("comment_ctime", 0),
         }
 
         await dao.save_article_comment(comment)
@@ -255,6 +256,7 @@ async def save_comments(requester: Requester, article: dict, raw_id: str):
                 "likeCount": reply.get("like_count", 0),
                 "articleId": article["id"],
                 "parentCommentId": comment["id"],
+                "createdAt": reply.get("ctime", 0),
             }
             await dao.save_article_comment(reply_comment)
 
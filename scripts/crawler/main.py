@@ -76,6 +76,8 @@ def main():
         print(__doc__)
         sys.exit(1)
 
+    dao.mark_imported()
+
 
 if __name__ == "__main__":
     main()
@@ -36,6 +36,8 @@ pkgs.mkShell {
     cargo-watch
     rust-analyzer
     python3
+    # scripts/contents.py reads compressed articles
+    zstd
 
     (with pkgs.python3.pkgs; [
       venvShellHook
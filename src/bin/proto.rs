@@ -1,6 +1,9 @@
 fn main() {
-    prost_build::Config::new()
+    tonic_build::configure()
+        .build_client(false)
         .out_dir("src/pb")
-        .compile_protos(&["abi.proto"], &["."])
+        .type_attribute(".", "#[derive(serde::Serialize, utoipa::ToSchema)]")
+        .type_attribute(".", "#[serde(rename_all = \"camelCase\")]")
+        .compile(&["abi.proto", "hackbook/v1/hackbook.proto"], &["."])
         .unwrap();
 }
@@ -0,0 +1,82 @@
+use {
+    anyhow::Result,
+    lru::LruCache,
+    std::{
+        fmt::Write,
+        hash::Hash,
+        num::NonZeroUsize,
+        sync::{
+            atomic::{AtomicU64, Ordering},
+            Mutex,
+        },
+        time::{Duration, Instant},
+    },
+};
+
+/// Bounded map of recently used values, each kept for at most `ttl`.
+pub struct Cache<K, V> {
+    name: &'static str,
+    /// `None` when the cache is disabled by a capacity of 0.
+    entries: Option<Mutex<LruCache<K, (Instant, V)>>>,
+    ttl: Duration,
+    hits: AtomicU64,
+    misses: AtomicU64,
+}
+
+impl<K: Hash + Eq, V: Clone> Cache<K, V> {
+    pub fn new(name: &'static str, capacity: usize, ttl: Duration) -> Self {
+        Cache {
+            name,
+            entries: NonZeroUsize::new(capacity).map(|c| Mutex::new(LruCache::new(c))),
+            ttl,
+            hits: AtomicU64::new(0),
+            misses: AtomicU64::new(0),
+        }
+    }
+
+    /// The cached value of `key`, or the one `load` returns, which is cached
+    /// when it's `Ok`. The lock isn't held while loading, two concurrent
+    /// misses both load.
+    pub fn get_or_load(&self, key: K, load: impl FnOnce() -> Result<V>) -> Result<V> {
+        let Some(entries) = &self.entries else {
+            return load();
+        };
+        if let Some((at, value)) = entries.lock().unwrap().get(&key) {
+            if at.elapsed() < self.ttl {
+                self.hits.fetch_add(1, Ordering::Relaxed);
+                return Ok(value.clone());
+            }
+        }
+
+        self.misses.fetch_add(1, Ordering::Relaxed);
+        let value = load()?;
+        entries
+            .lock()
+            .unwrap()
+            .put(key, (Instant::now(), value.clone()));
+        Ok(value)
+    }
+
+    pub fn clear(&self) {
+        if let Some(entries) = &self.entries {
+            entries.lock().unwrap().clear();
+        }
+    }
+
+    /// Appends the counters in the Prometheus text format.
+    pub fn write_metrics(&self, out: &mut String) {
+        let len = self.entries.as_ref().map_or(0, |e| e.lock().unwrap().len());
+        let name = self.name;
+        let _ = writeln!(
+            out,
+            "hackbook_cache_hits_total{{cache=\"{name}\"}} {}",
+            self.hits.load(Ordering::Relaxed)
+        );
+        let _ = writeln!(
+            out,
+            "hackbook_cache_misses_total{{cache=\"{name}\"}} {}",
+            self.misses.load(Ordering::Relaxed)
+        );
+        let _ = writeln!(out, "hackbook_cache_entries{{cache=\"{name}\"}} {len}");
+    }
+}
@@ -0,0 +1,312 @@
+use {
+    crate::{error::AppError, models},
+    anyhow::anyhow,
+    serde::Deserialize,
+    std::{cmp::Ordering, fmt, str::FromStr},
+    utoipa::ToSchema,
+};
+
+pub const DEFAULT_LIMIT: i64 = 20;
+pub const MAX_LIMIT: i64 = 100;
+/// In characters.
+pub const MAX_CONTENT_LEN: usize = 2000;
+pub const MAX_NICK_NAME_LEN: usize = 32;
+
+/// Imported or written by a reader, as listed.
+#[derive(Debug, Clone)]
+pub struct Comment {
+    pub id: String,
+    pub article_id: Option<String>,
+    pub parent_id: Option<String>,
+    pub content: String,
+    pub nick_name: String,
+    pub like_count: i64,
+    pub created_at: i64,
+    /// Author of a reader's comment, `None` for an imported one.
+    pub user_id: Option<String>,
+    pub updated_at: i64,
+    pub deleted: bool,
+    /// By the user the comment is listed for.
+    pub liked: bool,
+    pub hidden: bool,
+    pub pinned: bool,
+}
+
+impl Comment {
+    /// Applies the state set by the moderators.
+    pub fn moderate(&mut self, moderation: &models::CommentModeration) {
+        self.hidden = moderation.hidden;
+        self.pinned = moderation.pinned_at > 0;
+    }
+}
+
+impl From<models::ArticleComment> for Comment {
+    fn from(comment: models::ArticleComment) -> Self {
+        Comment {
+            id: comment.id,
+            article_id: comment.article_id,
+            parent_id: comment.parent_comment_id,
+            content: comment.content,
+            nick_name: comment.nick_name,
+            like_count: comment.like_count as i64,
+            created_at: comment.created_at,
+            user_id: None,
+            updated_at: 0,
+            deleted: false,
+            liked: false,
+            hidden: false,
+            pinned: false,
+        }
+    }
+}
+
+impl From<models::UserComment> for Comment {
+    fn from(comment: models::UserComment) -> Self {
+        Comment {
+            id: comment.id,
+            article_id: Some(comment.article_id),
+            parent_id: comment.parent_id,
+            content: comment.content,
+            nick_name: comment.nick_name,
+            like_count: comment.like_count as i64,
+            created_at: comment.created_at,
+            user_id: Some(comment.user_id),
+            updated_at: comment.updated_at,
+            deleted: comment.deleted,
+            liked: false,
+            hidden: false,
+            pinned: false,
+        }
+    }
+}
+
+/// Trimmed `content`, unless it's empty or too long.
+pub fn validate_content(content: &str) -> Result<String, AppError> {
+    let content = content.trim();
+    if content.is_empty() {
+        return Err(AppError::BadRequest("content is required".to_owned()));
+    }
+    if content.chars().count() > MAX_CONTENT_LEN {
+        return Err(AppError::BadRequest(format!(
+            "content is longer than {MAX_CONTENT_LEN} characters"
+        )));
+    }
+    Ok(content.to_owned())
+}
+
+/// Trimmed `nick_name`, or the start of the user id when it's empty.
+pub fn validate_nick_name(nick_name: &str, user_id: &str) -> Result<String, AppError> {
+    let nick_name = nick_name.trim();
+    if nick_name.chars().count() > MAX_NICK_NAME_LEN {
+        return Err(AppError::BadRequest(format!(
+            "nick name is longer than {MAX_NICK_NAME_LEN} characters"
+        )));
+    }
+    if nick_name.is_empty() {
+        return Ok(user_id.chars().take(8).collect());
+    }
+    Ok(nick_name.to_owned())
+}
+
+/// Order of the top level comments of an article, replies are always listed
+/// oldest first.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize, ToSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum CommentSort {
+    /// Most liked first.
+    #[default]
+    Likes,
+    Newest,
+    Oldest,
+}
+
+impl CommentSort {
+    /// Value of `comment` the listing is ordered by, ties are broken by id.
+    pub fn key(&self, comment: &Comment) -> i64 {
+        match self {
+            CommentSort::Likes => comment.like_count,
+            CommentSort::Newest | CommentSort::Oldest => comment.created_at,
+        }
+    }
+
+    /// Order of the listing, the same as the `ORDER BY` of the queries.
+    pub fn compare(&self, a: &Comment, b: &Comment) -> Ordering {
+        let by_key = match self {
+            CommentSort::Likes | CommentSort::Newest => self.key(b).cmp(&self.key(a)),
+            CommentSort::Oldest => self.key(a).cmp(&self.key(b)),
+        };
+        by_key.then_with(|| a.id.cmp(&b.id))
+    }
+}
+
+/// Position after the last comment of a page, the sort key and id of that
+/// comment. Clients treat it as an opaque string.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Cursor {
+    pub key: i64,
+    pub id: String,
+}
+
+impl Cursor {
+    pub fn parse(s: &str) -> Result<Self, AppError> {
+        s.split_once('.')
+            .and_then(|(key, id)| {
+                Some(Cursor {
+                    key: key.parse().ok()?,
+                    id: id.to_owned(),
+                })
+            })
+            .ok_or_else(|| AppError::BadRequest(format!("invalid cursor {s:?}")))
+    }
+}
+
+impl fmt::Display for Cursor {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}.{}", self.key, self.id)
+    }
+}
+
+/// A page of the top level comments of an article along with all of their
+/// replies, at any depth.
+#[derive(Debug)]
+pub struct CommentPage {
+    pub comments: Vec<Comment>,
+    pub replies: Vec<Comment>,
+    pub next_cursor: Option<Cursor>,
+}
+
+/// What a moderator does to a comment, every action but `Dismiss` also
+/// resolves its reports.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ModerationAction {
+    /// Resolves the reports only.
+    Dismiss,
+    Hide,
+    Restore,
+    Pin,
+    Unpin,
+    Delete,
+}
+
+impl ModerationAction {
+    /// Name in the moderation log.
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            ModerationAction::Dismiss => "dismiss",
+            ModerationAction::Hide => "hide",
+            ModerationAction::Restore => "restore",
+            ModerationAction::Pin => "pin",
+            ModerationAction::Unpin => "unpin",
+            ModerationAction::Delete => "delete",
+        }
+    }
+}
+
+impl FromStr for ModerationAction {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "dismiss" => Ok(ModerationAction::Dismiss),
+            "hide" => Ok(ModerationAction::Hide),
+            "restore" => Ok(ModerationAction::Restore),
+            "pin" => Ok(ModerationAction::Pin),
+            "unpin" => Ok(ModerationAction::Unpin),
+            "delete" => Ok(ModerationAction::Delete),
+            _ => Err(anyhow!("unknown moderation action {s}")),
+        }
+    }
+}
+
+/// Comments waiting for a moderator.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize, ToSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum ModerationQueue {
+    /// Reported comments, most recently reported first.
+    #[default]
+    Flagged,
+    /// Comments of readers, newest first.
+    Recent,
+}
+
+#[derive(Debug)]
+pub struct ModerationItem {
+    pub comment: Comment,
+    pub flag_count: i64,
+    pub flag_reasons: Vec<String>,
+    /// Of the latest report, 0 if none.
+    pub flagged_at: i64,
+}
+
+#[derive(Debug)]
+pub struct ModerationPage {
+    pub items: Vec<ModerationItem>,
+    pub next_cursor: Option<Cursor>,
+}
+
+#[cfg(test)]
+impl Comment {
+    /// An imported comment of article `a1`.
+    pub fn for_test(id: &str, parent_id: Option<&str>, like_count: i64, created_at: i64) -> Self {
+        Comment {
+            id: id.to_owned(),
+            article_id: Some("a1".to_owned()),
+            parent_id: parent_id.map(str::to_owned),
+            content: format!("comment {id}"),
+            nick_name: "reader".to_owned(),
+            like_count,
+            created_at,
+            user_id: None,
+            updated_at: 0,
+            deleted: false,
+            liked: false,
+            hidden: false,
+            pinned: false,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn cursor_round_trips() {
+        for cursor in [
+            Cursor {
+                key: 42,
+                id: "c1".to_owned(),
+            },
+            Cursor {
+                key: -1,
+                id: "0698edd5-1ea8.4493".to_owned(),
+            },
+        ] {
+            assert_eq!(Cursor::parse(&cursor.to_string()).unwrap(), cursor);
+        }
+    }
+
+    #[test]
+    fn rejects_invalid_cursors() {
+        for s in ["", "42", "x.c1", "1e3.c1", ".c1"] {
+            assert!(
+                matches!(Cursor::parse(s), Err(AppError::BadRequest(_))),
+                "{s:?}"
+            );
+        }
+    }
+
+    #[test]
+    fn ties_are_broken_by_id() {
+        let a = Comment::for_test("a", None, 5, 100);
+        let b = Comment::for_test("b", None, 5, 100);
+        let c = Comment::for_test("c", None, 3, 200);
+        for sort in [CommentSort::Likes, CommentSort::Newest, CommentSort::Oldest] {
+            assert_eq!(sort.compare(&a, &b), Ordering::Less);
+            assert_eq!(sort.compare(&b, &a), Ordering::Greater);
+        }
+        assert_eq!(CommentSort::Likes.compare(&b, &c), Ordering::Less);
+        assert_eq!(CommentSort::Newest.compare(&c, &a), Ordering::Less);
+        assert_eq!(CommentSort::Oldest.compare(&a, &c), Ordering::Less);
+    }
+}
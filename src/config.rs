@@ -0,0 +1,228 @@
+use {
+    anyhow::{anyhow, Context, Result},
+    std::{env, path::PathBuf, str::FromStr, time::Duration},
+};
+
+/// Server settings, read once from the environment at startup.
+#[derive(Debug, Clone)]
+pub struct Config {
+    pub host: String,
+    pub port: u16,
+    /// Number of HTTP worker threads.
+    pub workers: usize,
+    /// Maximum size in bytes of a JSON request body.
+    pub json_limit: usize,
+    /// Images and caches, and the content files with the local storage.
+    pub storage_path: PathBuf,
+    pub storage: StorageConfig,
+    /// `DATABASE_URL` as given, `database.url` is the user database when
+    /// the user data is kept apart.
+    pub content_url: String,
+    pub database: DatabaseConfig,
+    /// Read-only connection to a replica of the content tables, so that
+    /// course and article reads don't queue behind user data writes.
+    pub content_database: Option<DatabaseConfig>,
+    pub grpc: Option<GrpcConfig>,
+    /// Apply pending migrations at startup, to the user database only when
+    /// the content one is separate.
+    pub auto_migrate: bool,
+    pub cache: CacheConfig,
+    pub comments: CommentConfig,
+}
+
+/// Readers may post at most `rate_limit` comments per `rate_window`.
+#[derive(Debug, Clone)]
+pub struct CommentConfig {
+    pub rate_limit: i64,
+    pub rate_window: Duration,
+}
+
+/// Sizes of the in-memory caches in entries, 0 disables a cache.
+#[derive(Debug, Clone)]
+pub struct CacheConfig {
+    pub courses: usize,
+    pub articles: usize,
+    pub ttl: Duration,
+}
+
+#[derive(Debug, Clone)]
+pub struct DatabaseConfig {
+    pub url: String,
+    pub pool_size: u32,
+    /// How long to wait for a free connection of the pool.
+    pub connection_timeout: Duration,
+    pub busy_timeout: Option<Duration>,
+    pub enable_wal: bool,
+    pub enable_foreign_keys: bool,
+    /// Value of `PRAGMA synchronous`, e.g. `NORMAL` or `FULL`.
+    pub synchronous: String,
+    pub read_only: bool,
+    /// Content database attached as `content`, when `url` only holds the
+    /// user data.
+    pub attach_content: Option<String>,
+}
+
+/// Backend holding the course descriptions and article bodies.
+#[derive(Debug, Clone)]
+pub enum StorageConfig {
+    /// Plain files under `STORAGE_PATH`.
+    Local,
+    /// A single archive written by `hackbook-server pack`.
+    Pack {
+        path: PathBuf,
+    },
+    S3(S3Config),
+}
+
+#[derive(Debug, Clone)]
+pub struct S3Config {
+    /// e.g. `https://s3.us-east-1.amazonaws.com` or `http://localhost:9000`
+    pub endpoint: String,
+    pub bucket: String,
+    pub region: String,
+    /// Prepended to every key, e.g. `hackbook/`.
+    pub prefix: String,
+    pub access_key_id: String,
+    pub secret_access_key: String,
+}
+
+#[derive(Debug, Clone)]
+pub struct GrpcConfig {
+    pub port: u16,
+    pub token: String,
+}
+
+impl Config {
+    pub fn from_env() -> Result<Self> {
+        // with a separate user database the content one is attached read-only,
+        // so a new crawl can replace it without losing reading progress
+        let content_url = required("DATABASE_URL")?;
+        let (url, attach_content) = match optional("USER_DATABASE_URL") {
+            Some(user_url) => (user_url, Some(read_only_url(&content_url))),
+            None => (content_url.clone(), None),
+        };
+        let database = DatabaseConfig {
+            url,
+            pool_size: parse("DB_POOL_SIZE", 2)?,
+            connection_timeout: Duration::from_secs(parse("DB_CONNECTION_TIMEOUT_SECS", 30)?),
+            busy_timeout: match parse("DB_BUSY_TIMEOUT_MS", 30_000)? {
+                0 => None,
+                ms => Some(Duration::from_millis(ms)),
+            },
+            enable_wal: parse("DB_WAL", true)?,
+            enable_foreign_keys: parse("DB_FOREIGN_KEYS", true)?,
+            synchronous: synchronous(&optional("DB_SYNCHRONOUS").unwrap_or("NORMAL".to_owned()))?,
+            read_only: false,
+            attach_content,
+        };
+        if database.pool_size == 0 {
+            return Err(anyhow!("DB_POOL_SIZE must be at least 1"));
+        }
+
+        let content_database = match optional("CONTENT_DATABASE_URL") {
+            Some(url) => Some(DatabaseConfig {
+                url: read_only_url(&url),
+                attach_content: None,
+                pool_size: parse("CONTENT_DB_POOL_SIZE", database.pool_size)?,
+                // a read-only connection can't switch the journal mode
+                enable_wal: false,
+                read_only: true,
+                ..database.clone()
+            }),
+            None => None,
+        };
+
+        let grpc = match optional("GRPC_PORT") {
+            Some(port) => Some(GrpcConfig {
+                port: port
+                    .parse()
+                    .with_context(|| format!("invalid GRPC_PORT {port:?}"))?,
+                token: required("GRPC_TOKEN")
+                    .context("GRPC_TOKEN must be set when GRPC_PORT is")?,
+            }),
+            None => None,
+        };
+
+        let workers = parse("WORKERS", 2)?;
+        if workers == 0 {
+            return Err(anyhow!("WORKERS must be at least 1"));
+        }
+
+        let storage_path = PathBuf::from(required("STORAGE_PATH")?);
+        let storage = match optional("STORAGE_BACKEND").as_deref() {
+            None | Some("local") => StorageConfig::Local,
+            Some("pack") => StorageConfig::Pack {
+                path: optional("STORAGE_PACK")
+                    .map_or_else(|| storage_path.join("content.pack"), PathBuf::from),
+            },
+            Some("s3") => StorageConfig::S3(S3Config {
+                endpoint: required("S3_ENDPOINT")?,
+                bucket: required("S3_BUCKET")?,
+                region: optional("S3_REGION").unwrap_or("us-east-1".to_owned()),
+                prefix: optional("S3_PREFIX").unwrap_or_default(),
+                access_key_id: required("S3_ACCESS_KEY_ID")?,
+                secret_access_key: required("S3_SECRET_ACCESS_KEY")?,
+            }),
+            Some(backend) => return Err(anyhow!("unknown STORAGE_BACKEND {backend:?}")),
+        };
+
+        Ok(Config {
+            host: optional("HOST").unwrap_or("127.0.0.1".to_owned()),
+            port: parse("PORT", 8080)?,
+            workers,
+            json_limit: parse("JSON_LIMIT", 4096)?,
+            storage_path,
+            storage,
+            content_url,
+            database,
+            content_database,
+            grpc,
+            auto_migrate: parse("AUTO_MIGRATE", true)?,
+            cache: CacheConfig {
+                courses: parse("CACHE_COURSES", 64)?,
+                articles: parse("CACHE_ARTICLES", 512)?,
+                ttl: Duration::from_secs(parse("CACHE_TTL_SECS", 600)?),
+            },
+            comments: CommentConfig {
+                rate_limit: parse("COMMENT_RATE_LIMIT", 5)?,
+                rate_window: Duration::from_secs(parse("COMMENT_RATE_WINDOW_SECS", 60)?),
+            },
+        })
+    }
+}
+
+fn optional(key: &str) -> Option<String> {
+    env::var(key).ok().filter(|v| !v.is_empty())
+}
+
+fn required(key: &str) -> Result<String> {
+    optional(key).ok_or_else(|| anyhow!("{key} must be set"))
+}
+
+fn parse<T>(key: &str, default: T) -> Result<T>
+where
+    T: FromStr,
+    T::Err: std::error::Error + Send + Sync + 'static,
+{
+    match optional(key) {
+        Some(v) => v.parse().with_context(|| format!("invalid {key} {v:?}")),
+        None => Ok(default),
+    }
+}
+
+fn synchronous(value: &str) -> Result<String> {
+    let value = value.to_ascii_uppercase();
+    match value.as_str() {
+        "OFF" | "NORMAL" | "FULL" | "EXTRA" => Ok(value),
+        _ => Err(anyhow!("invalid DB_SYNCHRONOUS {value:?}")),
+    }
+}
+
+/// Opens plain paths in read-only mode, `file:` uris are used as given.
+fn read_only_url(url: &str) -> String {
+    if url.starts_with("file:") {
+        url.to_owned()
+    } else {
+        format!("file:{url}?mode=ro")
+    }
+}
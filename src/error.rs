@@ -0,0 +1,203 @@
+use {
+    crate::{handlers::Accept, pb},
+    actix_web::{
+        body::EitherBody,
+        dev::{ServiceRequest, ServiceResponse},
+        http::{
+            header::{HeaderName, HeaderValue},
+            StatusCode,
+        },
+        HttpResponse, ResponseError,
+    },
+    log::*,
+    std::{fmt, io},
+};
+
+pub const REQUEST_ID: HeaderName = HeaderName::from_static("x-request-id");
+
+/// Failures of the HTTP handlers, rendered as a `pb::Error` by [`render`].
+#[derive(Debug)]
+pub enum AppError {
+    NotFound(String),
+    /// The request is malformed, the message says how.
+    BadRequest(String),
+    /// Not logged in.
+    Unauthorized,
+    /// Logged in but not entitled, e.g. a visitor reading an article.
+    Forbidden,
+    /// Rate limited, the message says when to retry.
+    TooManyRequests(String),
+    /// The server isn't set up to handle the request, e.g. a PDF export
+    /// without a font for its text.
+    Unsupported(String),
+    Internal(anyhow::Error),
+}
+
+impl AppError {
+    /// Machine readable code of the error, stable across releases.
+    pub fn code(&self) -> &'static str {
+        match self {
+            AppError::NotFound(_) => "not_found",
+            AppError::BadRequest(_) => "bad_request",
+            AppError::Unauthorized => "unauthorized",
+            AppError::Forbidden => "forbidden",
+            AppError::TooManyRequests(_) => "too_many_requests",
+            AppError::Unsupported(_) => "unsupported",
+            AppError::Internal(_) => "internal",
+        }
+    }
+
+    /// Message shown to clients, internal details only go to the log.
+    fn public_message(&self) -> String {
+        match self {
+            AppError::Internal(_) => "internal server error".to_owned(),
+            e => e.to_string(),
+        }
+    }
+}
+
+impl fmt::Display for AppError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            AppError::NotFound(msg)
+            | AppError::BadRequest(msg)
+            | AppError::TooManyRequests(msg)
+            | AppError::Unsupported(msg) => f.write_str(msg),
+            AppError::Unauthorized => f.write_str("login required"),
+            AppError::Forbidden => f.write_str("Forbidden"),
+            AppError::Internal(e) => write!(f, "{e:#}"),
+        }
+    }
+}
+
+impl std::error::Error for AppError {}
+
+impl From<anyhow::Error> for AppError {
+    fn from(e: anyhow::Error) -> Self {
+        use diesel::result::{DatabaseErrorKind, Error as DieselError};
+
+        // raised by the repo itself, e.g. when checking a reference
+        let e = match e.downcast::<AppError>() {
+            Ok(e) => return e,
+            Err(e) => e,
+        };
+
+        // e.g. a bookmark on an article which doesn't exist
+        let invalid = e.chain().any(|cause| {
+            matches!(
+                cause.downcast_ref::<DieselError>(),
+                Some(DieselError::DatabaseError(
+                    DatabaseErrorKind::ForeignKeyViolation
+                        | DatabaseErrorKind::CheckViolation
+                        | DatabaseErrorKind::NotNullViolation,
+                    _
+                ))
+            )
+        });
+        if invalid {
+            return AppError::BadRequest(format!("{e}"));
+        }
+
+        let not_found = e.chain().any(|cause| {
+            matches!(
+                cause.downcast_ref::<DieselError>(),
+                Some(DieselError::NotFound)
+            ) || cause
+                .downcast_ref::<io::Error>()
+                .is_some_and(|e| e.kind() == io::ErrorKind::NotFound)
+        });
+        if not_found {
+            // the cause may hold storage paths, keep it out of the response
+            debug!("not found: {e:#}");
+            AppError::NotFound("not found".to_owned())
+        } else {
+            AppError::Internal(e)
+        }
+    }
+}
+
+impl From<actix_web::error::BlockingError> for AppError {
+    fn from(e: actix_web::error::BlockingError) -> Self {
+        AppError::Internal(e.into())
+    }
+}
+
+impl ResponseError for AppError {
+    fn status_code(&self) -> StatusCode {
+        match self {
+            AppError::NotFound(_) => StatusCode::NOT_FOUND,
+            AppError::BadRequest(_) => StatusCode::BAD_REQUEST,
+            AppError::Unauthorized => StatusCode::UNAUTHORIZED,
+            AppError::Forbidden => StatusCode::FORBIDDEN,
+            AppError::TooManyRequests(_) => StatusCode::TOO_MANY_REQUESTS,
+            AppError::Unsupported(_) => StatusCode::NOT_IMPLEMENTED,
+            AppError::Internal(_) => StatusCode::INTERNAL_SERVER_ERROR,
+        }
+    }
+}
+
+/// Id of the request, taken from the `x-request-id` header when the client
+/// or a proxy sent a usable one.
+pub fn request_id(req: &ServiceRequest) -> String {
+    req.headers()
+        .get(&REQUEST_ID)
+        .and_then(|v| v.to_str().ok())
+        .filter(|v| {
+            !v.is_empty()
+                && v.len() <= 64
+                && v.chars().all(|c| c.is_ascii_alphanumeric() || c == '-')
+        })
+        .map_or_else(|| format!("{:016x}", rand::random::<u64>()), str::to_owned)
+}
+
+/// Echoes the request id and replaces the body of failed responses with a
+/// `pb::Error`, in the encoding the client accepts.
+pub fn render<B>(
+    mut res: ServiceResponse<B>,
+    request_id: String,
+) -> ServiceResponse<EitherBody<B>> {
+    if let Ok(value) = HeaderValue::from_str(&request_id) {
+        res.headers_mut().insert(REQUEST_ID, value);
+    }
+    let Some(err) = res.response().error() else {
+        return res.map_into_left_body();
+    };
+
+    let status = res.status();
+    let (code, message) = match err.as_error::<AppError>() {
+        Some(e) => (e.code(), e.public_message()),
+        None if status.is_server_error() => {
+            (status_code(status), "internal server error".to_owned())
+        }
+        None => (status_code(status), err.to_string()),
+    };
+    if status.is_server_error() {
+        error!("request {request_id} failed: {err}");
+    }
+
+    let accept = Accept::from_headers(res.request());
+    let mut builder = HttpResponse::build(status);
+    builder.insert_header((REQUEST_ID, request_id.as_str()));
+    let body = pb::Error {
+        code: code.to_owned(),
+        message,
+        request_id,
+    };
+    match accept.reply(builder, body) {
+        Ok(new_res) => res.into_response(new_res).map_into_right_body(),
+        Err(_) => res.map_into_left_body(),
+    }
+}
+
+/// Code for errors raised by actix itself, such as an invalid query string.
+fn status_code(status: StatusCode) -> &'static str {
+    match status {
+        StatusCode::NOT_FOUND => "not_found",
+        StatusCode::UNAUTHORIZED => "unauthorized",
+        StatusCode::FORBIDDEN => "forbidden",
+        StatusCode::TOO_MANY_REQUESTS => "too_many_requests",
+        StatusCode::NOT_IMPLEMENTED => "unsupported",
+        s if s.is_client_error() => "bad_request",
+        _ => "internal",
+    }
+}
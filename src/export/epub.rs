@@ -0,0 +1,368 @@
+use {
+    super::{
+        image::{DeviceProfile, ImageProcessor},
+        media_type, Book,
+    },
+    crate::{html, repo::Repo},
+    anyhow::Result,
+    std::{
+        collections::HashMap,
+        fs,
+        io::{Cursor, Write},
+        path::PathBuf,
+    },
+    zip::{write::SimpleFileOptions, CompressionMethod, ZipWriter},
+};
+
+const CONTAINER_XML: &str = r#"<?xml version="1.0" encoding="UTF-8"?>
+<container version="1.0" xmlns="urn:oasis:names:tc:opendocument:xmlns:container">
+  <rootfiles>
+    <rootfile full-path="OEBPS/content.opf" media-type="application/oebps-package+xml"/>
+  </rootfiles>
+</container>
+"#;
+
+/// A file packed into the epub besides the chapters, e.g. an image.
+pub struct Resource {
+    /// Path relative to `OEBPS/`.
+    pub href: String,
+    pub media_type: &'static str,
+    pub data: Vec<u8>,
+}
+
+pub struct Chapter {
+    /// Title of the section this chapter is listed under in the TOC.
+    pub section: String,
+    pub title: String,
+    /// Body of the chapter, must be well-formed XHTML.
+    pub body: String,
+}
+
+/// Assembles an EPUB 3 book with a nav document (and an NCX for older readers).
+pub struct EpubBuilder {
+    identifier: String,
+    title: String,
+    author: String,
+    language: String,
+    cover: Option<Resource>,
+    chapters: Vec<Chapter>,
+    resources: Vec<Resource>,
+}
+
+impl EpubBuilder {
+    pub fn new(identifier: &str, title: &str) -> Self {
+        EpubBuilder {
+            identifier: identifier.to_owned(),
+            title: title.to_owned(),
+            author: String::new(),
+            language: "zh-CN".to_owned(),
+            cover: None,
+            chapters: vec![],
+            resources: vec![],
+        }
+    }
+
+    pub fn author(mut self, author: &str) -> Self {
+        self.author = author.to_owned();
+        self
+    }
+
+    pub fn cover(mut self, cover: Resource) -> Self {
+        self.cover = Some(cover);
+        self
+    }
+
+    pub fn add_chapter(&mut self, chapter: Chapter) {
+        self.chapters.push(chapter);
+    }
+
+    pub fn add_resource(&mut self, resource: Resource) {
+        self.resources.push(resource);
+    }
+
+    pub fn build(self) -> Result<Vec<u8>> {
+        let mut zip = ZipWriter::new(Cursor::new(Vec::new()));
+        let stored = SimpleFileOptions::default().compression_method(CompressionMethod::Stored);
+        let deflated = SimpleFileOptions::default().compression_method(CompressionMethod::Deflated);
+
+        // mimetype must be the first entry and uncompressed
+        zip.start_file("mimetype", stored)?;
+        zip.write_all(b"application/epub+zip")?;
+        zip.start_file("META-INF/container.xml", deflated)?;
+        zip.write_all(CONTAINER_XML.as_bytes())?;
+
+        if let Some(cover) = &self.cover {
+            zip.start_file("OEBPS/cover.xhtml", deflated)?;
+            zip.write_all(
+                xhtml_page(
+                    &self.title,
+                    &format!(
+                        r#"<div class="cover"><img src="{}" alt=""/></div>"#,
+                        cover.href
+                    ),
+                )
+                .as_bytes(),
+            )?;
+        }
+        for (i, chapter) in self.chapters.iter().enumerate() {
+            zip.start_file(format!("OEBPS/{}", chapter_href(i)), deflated)?;
+            let body = format!(
+                "<h1>{}</h1>\n{}",
+                html::escape(&chapter.title),
+                chapter.body
+            );
+            zip.write_all(xhtml_page(&chapter.title, &body).as_bytes())?;
+        }
+        for res in self.resources.iter().chain(self.cover.as_ref()) {
+            zip.start_file(format!("OEBPS/{}", res.href), stored)?;
+            zip.write_all(&res.data)?;
+        }
+        zip.start_file("OEBPS/nav.xhtml", deflated)?;
+        zip.write_all(self.nav().as_bytes())?;
+        zip.start_file("OEBPS/toc.ncx", deflated)?;
+        zip.write_all(self.ncx().as_bytes())?;
+        zip.start_file("OEBPS/content.opf", deflated)?;
+        zip.write_all(self.opf().as_bytes())?;
+
+        Ok(zip.finish()?.into_inner())
+    }
+
+    /// Chapters grouped by consecutive sections, with the index of each chapter.
+    fn sections(&self) -> Vec<(&str, Vec<(usize, &Chapter)>)> {
+        let mut sections: Vec<(&str, Vec<(usize, &Chapter)>)> = vec![];
+        for (i, chapter) in self.chapters.iter().enumerate() {
+            match sections.last_mut() {
+                Some((title, chapters)) if *title == chapter.section => chapters.push((i, chapter)),
+                _ => sections.push((&chapter.section, vec![(i, chapter)])),
+            }
+        }
+        sections
+    }
+
+    fn nav(&self) -> String {
+        let mut toc = String::new();
+        for (section, chapters) in self.sections() {
+            toc.push_str(&format!("<li><span>{}</span><ol>\n", html::escape(section)));
+            for (i, chapter) in chapters {
+                toc.push_str(&format!(
+                    "<li><a href=\"{}\">{}</a></li>\n",
+                    chapter_href(i),
+                    html::escape(&chapter.title)
+                ));
+            }
+            toc.push_str("</ol></li>\n");
+        }
+
+        format!(
+            r#"<?xml version="1.0" encoding="UTF-8"?>
+<!DOCTYPE html>
+<html xmlns="http://www.w3.org/1999/xhtml" xmlns:epub="http://www.idpf.org/2007/ops">
+<head><title>{title}</title></head>
+<body>
+<nav epub:type="toc" id="toc"><h1>{title}</h1><ol>
+{toc}</ol></nav>
+</body>
+</html>
+"#,
+            title = html::escape(&self.title),
+        )
+    }
+
+    fn ncx(&self) -> String {
+        let mut points = String::new();
+        let mut order = 0;
+        for (section, chapters) in self.sections() {
+            order += 1;
+            points.push_str(&format!(
+                "<navPoint id=\"s{order}\" playOrder=\"{order}\"><navLabel><text>{}</text></navLabel><content src=\"{}\"/>\n",
+                html::escape(section),
+                chapter_href(chapters[0].0)
+            ));
+            for (i, chapter) in chapters {
+                order += 1;
+                points.push_str(&format!(
+                    "<navPoint id=\"c{i}\" playOrder=\"{order}\"><navLabel><text>{}</text></navLabel><content src=\"{}\"/></navPoint>\n",
+                    html::escape(&chapter.title),
+                    chapter_href(i)
+                ));
+            }
+            points.push_str("</navPoint>\n");
+        }
+
+        format!(
+            r#"<?xml version="1.0" encoding="UTF-8"?>
+<ncx xmlns="http://www.daisy.org/z3986/2005/ncx/" version="2005-1">
+<head><meta name="dtb:uid" content="{id}"/></head>
+<docTitle><text>{title}</text></docTitle>
+<navMap>
+{points}</navMap>
+</ncx>
+"#,
+            id = html::escape(&self.identifier),
+            title = html::escape(&self.title),
+        )
+    }
+
+    fn opf(&self) -> String {
+        let mut manifest = String::from(
+            "<item id=\"nav\" href=\"nav.xhtml\" media-type=\"application/xhtml+xml\" properties=\"nav\"/>\n\
+             <item id=\"ncx\" href=\"toc.ncx\" media-type=\"application/x-dtbncx+xml\"/>\n",
+        );
+        let mut spine = String::new();
+        let mut cover_meta = String::new();
+        if let Some(cover) = &self.cover {
+            manifest.push_str(&format!(
+                "<item id=\"cover-image\" href=\"{}\" media-type=\"{}\" properties=\"cover-image\"/>\n\
+                 <item id=\"cover\" href=\"cover.xhtml\" media-type=\"application/xhtml+xml\"/>\n",
+                cover.href, cover.media_type
+            ));
+            spine.push_str("<itemref idref=\"cover\" linear=\"no\"/>\n");
+            cover_meta.push_str("<meta name=\"cover\" content=\"cover-image\"/>\n");
+        }
+        for i in 0..self.chapters.len() {
+            manifest.push_str(&format!(
+                "<item id=\"c{i}\" href=\"{}\" media-type=\"application/xhtml+xml\"/>\n",
+                chapter_href(i)
+            ));
+            spine.push_str(&format!("<itemref idref=\"c{i}\"/>\n"));
+        }
+        for (i, res) in self.resources.iter().enumerate() {
+            manifest.push_str(&format!(
+                "<item id=\"r{i}\" href=\"{}\" media-type=\"{}\"/>\n",
+                res.href, res.media_type
+            ));
+        }
+
+        format!(
+            r#"<?xml version="1.0" encoding="UTF-8"?>
+<package xmlns="http://www.idpf.org/2007/opf" version="3.0" unique-identifier="book-id">
+<metadata xmlns:dc="http://purl.org/dc/elements/1.1/">
+<dc:identifier id="book-id">{id}</dc:identifier>
+<dc:title>{title}</dc:title>
+<dc:creator>{author}</dc:creator>
+<dc:language>{language}</dc:language>
+<meta property="dcterms:modified">{modified}</meta>
+{cover_meta}</metadata>
+<manifest>
+{manifest}</manifest>
+<spine toc="ncx">
+{spine}</spine>
+</package>
+"#,
+            id = html::escape(&self.identifier),
+            title = html::escape(&self.title),
+            author = html::escape(&self.author),
+            language = self.language,
+            modified = chrono::Utc::now().format("%Y-%m-%dT%H:%M:%SZ"),
+        )
+    }
+}
+
+fn chapter_href(i: usize) -> String {
+    format!("text/ch{i:04}.xhtml")
+}
+
+fn xhtml_page(title: &str, body: &str) -> String {
+    format!(
+        r#"<?xml version="1.0" encoding="UTF-8"?>
+<!DOCTYPE html>
+<html xmlns="http://www.w3.org/1999/xhtml">
+<head><meta charset="utf-8"/><title>{}</title></head>
+<body>
+{}
+</body>
+</html>
+"#,
+        html::escape(title),
+        body
+    )
+}
+
+/// Renders a whole course as an epub, packing the downloaded copies of
+/// article images and the course cover. Images without a local copy are dropped,
+/// the others are downsized for `profile` if one is given.
+pub fn course_epub(
+    repo: &Repo,
+    course_id: &str,
+    profile: Option<DeviceProfile>,
+) -> Result<Vec<u8>> {
+    let book = Book::load(repo, course_id)?;
+    let mut images = Images {
+        repo,
+        processor: profile.map(|p| ImageProcessor::new(repo.cache_dir("images"), p)),
+        files: HashMap::new(),
+    };
+    let mut builder = EpubBuilder::new(&book.id, &book.title).author(&book.author);
+
+    if let Some(path) = repo.find_local_image(&book.cover) {
+        let path = images.process(path);
+        builder = builder.cover(Resource {
+            href: format!("images/cover.{}", extension(&path)),
+            media_type: media_type(&path),
+            data: fs::read(&path)?,
+        });
+    }
+
+    if let Some(desc) = &book.description {
+        builder.add_chapter(Chapter {
+            section: book.title.to_owned(),
+            title: book.title.to_owned(),
+            body: html::to_xhtml(desc, |src| images.href(src)),
+        });
+    }
+    for (section, articles) in &book.sections {
+        for (article, content) in articles {
+            builder.add_chapter(Chapter {
+                section: section.title.to_owned(),
+                title: article.title.to_owned(),
+                body: html::to_xhtml(content, |src| images.href(src)),
+            });
+        }
+    }
+    // the hrefs are numbered in order of first use
+    let mut files = images.files.into_iter().collect::<Vec<_>>();
+    files.sort_by(|a, b| a.1.cmp(&b.1));
+    for (path, href) in files {
+        builder.add_resource(Resource {
+            href: href.trim_start_matches("../").to_owned(),
+            media_type: media_type(&path),
+            data: fs::read(&path)?,
+        });
+    }
+
+    builder.build()
+}
+
+/// Images referenced by the chapters, one entry per file shared by every
+/// chapter referencing it.
+struct Images<'a> {
+    repo: &'a Repo,
+    processor: Option<ImageProcessor>,
+    files: HashMap<PathBuf, String>,
+}
+
+impl Images<'_> {
+    fn process(&self, path: PathBuf) -> PathBuf {
+        match &self.processor {
+            Some(processor) => processor.process_or_original(path),
+            None => path,
+        }
+    }
+
+    fn href(&mut self, src: &str) -> Option<String> {
+        let path = self.process(self.repo.find_local_image(src)?);
+        let next = self.files.len();
+        let href = self
+            .files
+            .entry(path)
+            .or_insert_with_key(|path| format!("../images/{next:05}.{}", extension(path)));
+        Some(href.to_owned())
+    }
+}
+
+fn extension(path: &std::path::Path) -> String {
+    path.extension()
+        .and_then(|e| e.to_str())
+        .unwrap_or("bin")
+        .to_ascii_lowercase()
+}
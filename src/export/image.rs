@@ -0,0 +1,180 @@
+use {
+    anyhow::{anyhow, Result},
+    image::{codecs::jpeg::JpegEncoder, imageops::FilterType, DynamicImage, GenericImageView},
+    serde::Deserialize,
+    sha2::{Digest, Sha256},
+    std::{
+        collections::HashMap,
+        fs,
+        path::{Path, PathBuf},
+        sync::Mutex,
+    },
+    utoipa::ToSchema,
+};
+
+/// Images smaller than this are used as they are.
+const SIZE_THRESHOLD: u64 = 100;
+
+/// Target reader of an export, decides how images are downsized and recompressed.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize, ToSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum DeviceProfile {
+    /// Kindle Paperwhite 3, 6" e-ink
+    KindlePw,
+    /// Kobo Libra 2, 7" e-ink
+    Kobo,
+    /// Color tablet such as an iPad
+    ColorTablet,
+}
+
+impl DeviceProfile {
+    pub fn name(&self) -> &'static str {
+        match self {
+            DeviceProfile::KindlePw => "kindle_pw",
+            DeviceProfile::Kobo => "kobo",
+            DeviceProfile::ColorTablet => "color_tablet",
+        }
+    }
+
+    /// Max width and height of the screen in pixels.
+    pub fn max_size(&self) -> (u32, u32) {
+        match self {
+            DeviceProfile::KindlePw => (1072, 1448),
+            DeviceProfile::Kobo => (1264, 1680),
+            DeviceProfile::ColorTablet => (1620, 2160),
+        }
+    }
+
+    pub fn grayscale(&self) -> bool {
+        !matches!(self, DeviceProfile::ColorTablet)
+    }
+
+    pub fn jpeg_quality(&self) -> u8 {
+        match self {
+            DeviceProfile::KindlePw => 50,
+            DeviceProfile::Kobo => 60,
+            DeviceProfile::ColorTablet => 75,
+        }
+    }
+}
+
+/// Downsizes images for a device profile, caching processed outputs under
+/// `{cache_dir}/{profile}/{sha256 of source}.jpg`.
+pub struct ImageProcessor {
+    cache_dir: PathBuf,
+    profile: DeviceProfile,
+    /// Output of every source already processed during this export, so an
+    /// image referenced many times is read and hashed once.
+    processed: Mutex<HashMap<PathBuf, PathBuf>>,
+}
+
+impl ImageProcessor {
+    pub fn new(cache_dir: PathBuf, profile: DeviceProfile) -> Self {
+        ImageProcessor {
+            cache_dir,
+            profile,
+            processed: Mutex::new(HashMap::new()),
+        }
+    }
+
+    /// Like [`Self::process`], but falls back to `src` with a warning when
+    /// processing fails, so one bad image doesn't fail a whole export.
+    pub fn process_or_original(&self, src: PathBuf) -> PathBuf {
+        self.process(&src).unwrap_or_else(|e| {
+            log::warn!("failed to process {}: {e}", src.to_string_lossy());
+            // warned once, later references use the original right away
+            self.processed
+                .lock()
+                .unwrap()
+                .insert(src.clone(), src.clone());
+            src
+        })
+    }
+
+    /// Returns the path of the processed image, or `src` itself when the
+    /// image is tiny or can't be recompressed (svg, animated gif, broken files).
+    fn process(&self, src: &Path) -> Result<PathBuf> {
+        if let Some(dest) = self.processed.lock().unwrap().get(src) {
+            return Ok(dest.clone());
+        }
+        let dest = self.process_uncached(src)?;
+        self.processed
+            .lock()
+            .unwrap()
+            .insert(src.to_path_buf(), dest.clone());
+        Ok(dest)
+    }
+
+    fn process_uncached(&self, src: &Path) -> Result<PathBuf> {
+        if fs::metadata(src)?.len() < SIZE_THRESHOLD || is_animated_or_vector(src) {
+            return Ok(src.to_path_buf());
+        }
+
+        let data = fs::read(src)?;
+        let hash = format!("{:x}", Sha256::digest(&data));
+        let dest = self
+            .cache_dir
+            .join(self.profile.name())
+            .join(format!("{hash}.jpg"));
+        if dest.is_file() {
+            return Ok(dest);
+        }
+
+        let img = match image::load_from_memory(&data) {
+            Ok(img) => img,
+            Err(e) => {
+                log::warn!("can't decode {}: {e}", src.to_string_lossy());
+                return Ok(src.to_path_buf());
+            }
+        };
+        let out = self.encode(img)?;
+
+        fs::create_dir_all(dest.parent().ok_or_else(|| anyhow!("invalid cache dir"))?)?;
+        // write then rename so concurrent exports never read a partial file
+        let tmp = dest.with_extension(format!("{}.tmp", rand::random::<u32>()));
+        fs::write(&tmp, out)?;
+        fs::rename(&tmp, &dest)?;
+
+        Ok(dest)
+    }
+
+    fn encode(&self, img: DynamicImage) -> Result<Vec<u8>> {
+        let (max_w, max_h) = self.profile.max_size();
+        let (w, h) = img.dimensions();
+        let img = if w > max_w || h > max_h {
+            img.resize(max_w, max_h, FilterType::Lanczos3)
+        } else {
+            img
+        };
+
+        // jpeg has no alpha channel, flatten transparent pixels onto white
+        let mut rgb = img.to_rgba8();
+        rgb.pixels_mut().for_each(|p| {
+            let a = p[3] as u32;
+            for c in 0..3 {
+                p[c] = ((p[c] as u32 * a + 255 * (255 - a)) / 255) as u8;
+            }
+            p[3] = 255;
+        });
+        let img = DynamicImage::ImageRgba8(rgb);
+        let img = if self.profile.grayscale() {
+            DynamicImage::ImageLuma8(img.to_luma8())
+        } else {
+            DynamicImage::ImageRgb8(img.to_rgb8())
+        };
+
+        let mut out = vec![];
+        JpegEncoder::new_with_quality(&mut out, self.profile.jpeg_quality()).encode_image(&img)?;
+        Ok(out)
+    }
+}
+
+fn is_animated_or_vector(path: &Path) -> bool {
+    matches!(
+        path.extension()
+            .and_then(|e| e.to_str())
+            .map(|e| e.to_ascii_lowercase())
+            .as_deref(),
+        Some("gif") | Some("svg")
+    )
+}
@@ -0,0 +1,204 @@
+use {
+    crate::{error::AppError, models, repo::Repo, storage},
+    anyhow::Result,
+    std::{collections::HashSet, path::Path},
+};
+
+pub mod epub;
+pub mod image;
+pub mod pdf;
+
+/// Upper bound on the articles of one `Book::load_articles` export.
+pub const MAX_ARTICLES: usize = 100;
+
+/// A course, or a selection of articles, with the stored html of every
+/// article, ready to be rendered.
+pub struct Book {
+    pub id: String,
+    pub title: String,
+    pub author: String,
+    /// Image src of the cover, as stored in `course.image`.
+    pub cover: String,
+    pub description: Option<String>,
+    pub sections: Vec<(models::Section, Vec<(models::Article, String)>)>,
+}
+
+impl Book {
+    /// Articles whose content isn't downloaded yet are left out, along with
+    /// the sections left empty.
+    pub fn load(repo: &Repo, course_id: &str) -> Result<Self> {
+        let (course, sections, description) = repo.get_course_detail_by_course_id(course_id)?;
+
+        let mut loaded = vec![];
+        for (section, articles) in sections {
+            let mut list = vec![];
+            for article in articles {
+                if let Some(content) = load_content(repo, &article)? {
+                    list.push((article, content));
+                }
+            }
+            if !list.is_empty() {
+                loaded.push((section, list));
+            }
+        }
+
+        Ok(Book {
+            id: course.id,
+            title: course.title,
+            author: course.teacher_name,
+            cover: course.image,
+            description,
+            sections: loaded,
+        })
+    }
+
+    /// Loads articles in the given order, consecutive articles of the same
+    /// section are grouped together. Repeated ids are only kept the first
+    /// time, and articles whose content isn't downloaded yet are left out
+    /// unless none has any.
+    pub fn load_articles(repo: &Repo, ids: &[String]) -> Result<Self> {
+        let mut seen = HashSet::new();
+        let ids = ids
+            .iter()
+            .filter(|id| seen.insert(id.as_str()))
+            .cloned()
+            .collect::<Vec<_>>();
+        let mut articles = repo.find_articles_by_ids(&ids)?;
+
+        let mut sections: Vec<(models::Section, Vec<(models::Article, String)>)> = vec![];
+        for id in &ids {
+            let i = articles
+                .iter()
+                .position(|a| a.id == *id)
+                .ok_or_else(|| AppError::NotFound(format!("article {id} not found")))?;
+            let article = articles.swap_remove(i);
+            let Some(content) = load_content(repo, &article)? else {
+                continue;
+            };
+            match sections.last_mut() {
+                Some((section, list)) if section.id == article.section_id => {
+                    list.push((article, content))
+                }
+                _ => sections.push((
+                    repo.find_section_by_id(&article.section_id)?,
+                    vec![(article, content)],
+                )),
+            }
+        }
+
+        if sections.is_empty() {
+            return Err(AppError::NotFound(format!(
+                "articles {} have no content yet",
+                ids.join(", ")
+            ))
+            .into());
+        }
+
+        let mut course_ids = sections
+            .iter()
+            .map(|(s, _)| &s.course_id)
+            .collect::<Vec<_>>();
+        course_ids.sort_unstable();
+        course_ids.dedup();
+        let (title, author, cover) = match course_ids[..] {
+            [course_id] => {
+                let course = repo.find_course_by_id(course_id)?;
+                (course.title, course.teacher_name, course.image)
+            }
+            _ => ("Selected articles".to_owned(), String::new(), String::new()),
+        };
+
+        Ok(Book {
+            id: format!("articles-{}", ids.join("-")),
+            title,
+            author,
+            cover,
+            description: None,
+            sections,
+        })
+    }
+}
+
+/// Content of `article`, `None` if the crawler hasn't downloaded it yet.
+fn load_content(repo: &Repo, article: &models::Article) -> Result<Option<String>> {
+    match repo.get_article_content(&article.id) {
+        Ok(content) => Ok(Some(content)),
+        Err(e) if storage::is_not_found(&e) => {
+            log::info!("skipping article {} without content", article.id);
+            Ok(None)
+        }
+        Err(e) => Err(e),
+    }
+}
+
+pub fn media_type(path: &Path) -> &'static str {
+    match path
+        .extension()
+        .and_then(|e| e.to_str())
+        .map(|e| e.to_ascii_lowercase())
+        .as_deref()
+    {
+        Some("png") => "image/png",
+        Some("jpg") | Some("jpeg") => "image/jpeg",
+        Some("gif") => "image/gif",
+        Some("svg") => "image/svg+xml",
+        Some("webp") => "image/webp",
+        _ => "application/octet-stream",
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use {super::*, crate::testing::TempDir, std::fs};
+
+    const COURSE: &str = r#"
+        INSERT INTO course (id, brief, teacherName, teacherTitle, image, purchasedCount, price, title)
+            VALUES ('c1', '', 'Teacher', '', '', '0', 0, 'Course');
+        INSERT INTO section (id, courseId, title) VALUES ('s1', 'c1', 'One'), ('s2', 'c1', 'Two');
+        INSERT INTO article (id, sectionId, title) VALUES
+            ('a1', 's1', 'Downloaded'),
+            ('a2', 's1', 'Pending'),
+            ('a3', 's2', 'Pending too');
+    "#;
+
+    fn ids(book: &Book) -> Vec<(&str, Vec<&str>)> {
+        book.sections
+            .iter()
+            .map(|(s, list)| {
+                let articles = list.iter().map(|(a, _)| a.id.as_str()).collect();
+                (s.id.as_str(), articles)
+            })
+            .collect()
+    }
+
+    #[test]
+    fn skips_articles_without_content() {
+        let dir = TempDir::new();
+        fs::create_dir_all(dir.join("contents")).unwrap();
+        fs::write(dir.join("contents/a1.html"), "<p>one</p>").unwrap();
+        let repo = Repo::for_test(&dir, COURSE);
+
+        let book = Book::load(&repo, "c1").unwrap();
+        assert_eq!(ids(&book), [("s1", vec!["a1"])]);
+        assert!(book.sections[0].1[0].1.contains("one"));
+    }
+
+    #[test]
+    fn skips_selected_articles_without_content() {
+        let dir = TempDir::new();
+        fs::create_dir_all(dir.join("contents")).unwrap();
+        fs::write(dir.join("contents/a1.html"), "<p>one</p>").unwrap();
+        let repo = Repo::for_test(&dir, COURSE);
+
+        let selection = ["a2", "a1", "a3"].map(str::to_owned);
+        let book = Book::load_articles(&repo, &selection).unwrap();
+        assert_eq!(ids(&book), [("s1", vec!["a1"])]);
+        assert_eq!(book.title, "Course");
+
+        let err = Book::load_articles(&repo, &selection[2..]).err().unwrap();
+        assert!(
+            matches!(err.downcast_ref(), Some(AppError::NotFound(msg)) if msg.contains("a3")),
+            "{err}"
+        );
+    }
+}
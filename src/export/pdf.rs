@@ -0,0 +1,516 @@
+use {
+    super::{
+        image::{DeviceProfile, ImageProcessor},
+        Book,
+    },
+    crate::{error::AppError, repo::Repo},
+    anyhow::{anyhow, Result},
+    ego_tree::NodeRef,
+    printpdf::{
+        BuiltinFont, ColorBits, ColorSpace, Image, ImageFilter, ImageTransform, ImageXObject,
+        IndirectFontRef, Mm, PdfDocument, PdfDocumentReference, PdfLayerReference, PdfPageIndex,
+        Px,
+    },
+    scraper::{Html, Node},
+    std::{borrow::Cow, env, fs, path::Path},
+};
+
+const PAGE_WIDTH: f32 = 210.0;
+const PAGE_HEIGHT: f32 = 297.0;
+const MARGIN: f32 = 20.0;
+const BODY_SIZE: f32 = 10.5;
+const CODE_SIZE: f32 = 8.5;
+const IMAGE_DPI: f32 = 150.0;
+const PT_TO_MM: f32 = 0.352_778;
+
+/// Block level content of an article, inline markup is flattened to text.
+#[derive(Debug, PartialEq)]
+enum Block {
+    Heading(u8, String),
+    Text(String),
+    Item(String),
+    Quote(String),
+    Code(String),
+    Image(String),
+}
+
+fn blocks(html: &str) -> Vec<Block> {
+    let doc = Html::parse_fragment(html);
+    let mut blocks = vec![];
+    let mut inline = String::new();
+    for child in doc.root_element().children() {
+        collect_blocks(child, &mut blocks, &mut inline);
+    }
+    flush(&mut blocks, &mut inline);
+    blocks
+}
+
+fn collect_blocks(node: NodeRef<Node>, blocks: &mut Vec<Block>, inline: &mut String) {
+    match node.value() {
+        Node::Text(text) => {
+            // collapse whitespace like a browser does
+            let starts_with_space = text.starts_with(char::is_whitespace);
+            let words = text.split_whitespace().collect::<Vec<_>>().join(" ");
+            if starts_with_space && !inline.ends_with([' ', '\n']) && !inline.is_empty() {
+                inline.push(' ');
+            }
+            inline.push_str(&words);
+            if text.ends_with(char::is_whitespace) && !words.is_empty() {
+                inline.push(' ');
+            }
+        }
+        Node::Element(el) => match el.name() {
+            "script" | "style" | "noscript" | "iframe" => (),
+            "br" => inline.push('\n'),
+            "img" => {
+                flush(blocks, inline);
+                if let Some(src) = el.attr("src") {
+                    blocks.push(Block::Image(src.to_owned()));
+                }
+            }
+            "pre" => {
+                flush(blocks, inline);
+                blocks.push(Block::Code(text_of(node)));
+            }
+            name @ ("h1" | "h2" | "h3" | "h4" | "h5" | "h6") => {
+                flush(blocks, inline);
+                let level = name[1..].parse().unwrap_or(6);
+                blocks.push(Block::Heading(level, normalize(&text_of(node))));
+            }
+            "li" => {
+                flush(blocks, inline);
+                let mut item = String::new();
+                let mut nested = vec![];
+                node.children()
+                    .for_each(|c| collect_blocks(c, &mut nested, &mut item));
+                blocks.push(Block::Item(item.trim().to_owned()));
+                blocks.extend(nested);
+            }
+            "blockquote" => {
+                flush(blocks, inline);
+                blocks.push(Block::Quote(normalize(&text_of(node))));
+            }
+            "tr" => {
+                flush(blocks, inline);
+                let cells = node
+                    .children()
+                    .filter(|c| c.value().is_element())
+                    .map(|c| normalize(&text_of(c)))
+                    .collect::<Vec<_>>();
+                blocks.push(Block::Text(cells.join(" | ")));
+            }
+            "p" | "div" | "section" | "article" | "ul" | "ol" | "table" | "figure" | "hr"
+            | "dl" | "dt" | "dd" => {
+                flush(blocks, inline);
+                node.children()
+                    .for_each(|c| collect_blocks(c, blocks, inline));
+                flush(blocks, inline);
+            }
+            _ => node
+                .children()
+                .for_each(|c| collect_blocks(c, blocks, inline)),
+        },
+        _ => (),
+    }
+}
+
+fn flush(blocks: &mut Vec<Block>, inline: &mut String) {
+    let text = inline.trim();
+    if !text.is_empty() {
+        blocks.push(Block::Text(text.to_owned()));
+    }
+    inline.clear();
+}
+
+fn text_of(node: NodeRef<Node>) -> String {
+    node.descendants()
+        .filter_map(|n| n.value().as_text().map(|t| t.to_string()))
+        .collect()
+}
+
+fn normalize(text: &str) -> String {
+    text.split_whitespace().collect::<Vec<_>>().join(" ")
+}
+
+/// A font embedded in the document, with the metrics used to wrap lines.
+struct Font {
+    pdf: IndirectFontRef,
+    /// Raw ttf data of an external font, `None` for builtin fonts.
+    data: Option<Vec<u8>>,
+    /// Average glyph advance in em, used for builtin fonts.
+    avg_advance: f32,
+}
+
+impl Font {
+    fn builtin(doc: &PdfDocumentReference, font: BuiltinFont, avg_advance: f32) -> Result<Self> {
+        Ok(Font {
+            pdf: doc.add_builtin_font(font)?,
+            data: None,
+            avg_advance,
+        })
+    }
+
+    fn external(doc: &PdfDocumentReference, path: &str) -> Result<Self> {
+        let data = fs::read(path).map_err(|e| anyhow!("can't read font {path}: {e}"))?;
+        Ok(Font {
+            pdf: doc.add_external_font(data.as_slice())?,
+            data: Some(data),
+            avg_advance: 0.5,
+        })
+    }
+
+    /// Splits `text` into lines no wider than `width` mm at `size` pt.
+    fn wrap(&self, text: &str, size: f32, width: f32) -> Vec<String> {
+        let face = self
+            .data
+            .as_ref()
+            .and_then(|d| ttf_parser::Face::parse(d, 0).ok());
+        let advance = |c: char| -> f32 {
+            let em = match &face {
+                Some(face) => face
+                    .glyph_index(c)
+                    .and_then(|g| face.glyph_hor_advance(g))
+                    .map_or(1.0, |a| a as f32 / face.units_per_em() as f32),
+                None if c.is_ascii() => self.avg_advance,
+                None => 1.0,
+            };
+            em * size * PT_TO_MM
+        };
+
+        let mut lines = vec![];
+        for paragraph in text.split('\n') {
+            let mut line = String::new();
+            let mut line_width = 0.0;
+            // break at spaces, or anywhere inside words wider than a line (e.g. CJK text)
+            for word in paragraph.split_inclusive(' ') {
+                let word_width: f32 = word.chars().map(advance).sum();
+                if line_width + word_width > width && !line.is_empty() {
+                    lines.push(std::mem::take(&mut line).trim_end().to_owned());
+                    line_width = 0.0;
+                }
+                for c in word.chars() {
+                    let w = advance(c);
+                    if line_width + w > width && !line.is_empty() {
+                        lines.push(std::mem::take(&mut line));
+                        line_width = 0.0;
+                    }
+                    line.push(c);
+                    line_width += w;
+                }
+            }
+            lines.push(line.trim_end().to_owned());
+        }
+        lines
+    }
+
+    /// Builtin fonts only cover Latin-1, common punctuation beyond it is
+    /// replaced by its ASCII look-alike. Any other character fails the
+    /// export rather than showing up as `?`.
+    fn encodable<'t>(&self, text: &'t str) -> Result<Cow<'t, str>> {
+        if self.data.is_some() || text.chars().all(|c| (c as u32) < 256) {
+            return Ok(Cow::Borrowed(text));
+        }
+        let mut out = String::with_capacity(text.len());
+        for c in text.chars() {
+            match c {
+                c if (c as u32) < 256 => out.push(c),
+                '\u{2018}' | '\u{2019}' => out.push('\''),
+                '\u{201c}' | '\u{201d}' => out.push('"'),
+                '\u{2013}' | '\u{2014}' => out.push('-'),
+                '\u{2026}' => out.push_str("..."),
+                '\u{2022}' => out.push('*'),
+                c => {
+                    return Err(AppError::Unsupported(format!(
+                        "the text has characters such as {c:?} the builtin PDF fonts can't \
+                         render, PDF_FONT must point to a TrueType font covering them"
+                    ))
+                    .into())
+                }
+            }
+        }
+        Ok(Cow::Owned(out))
+    }
+}
+
+/// Position of the next line, pages are added as they fill up.
+struct Cursor {
+    doc: PdfDocumentReference,
+    page: PdfPageIndex,
+    layer: PdfLayerReference,
+    /// Distance of the next line from the bottom of the page, in mm.
+    y: f32,
+}
+
+impl Cursor {
+    fn new_page(&mut self) {
+        let (page, layer) = self
+            .doc
+            .add_page(Mm(PAGE_WIDTH), Mm(PAGE_HEIGHT), "content");
+        self.page = page;
+        self.layer = self.doc.get_page(page).get_layer(layer);
+        self.y = PAGE_HEIGHT - MARGIN;
+    }
+
+    fn at_page_top(&self) -> bool {
+        self.y >= PAGE_HEIGHT - MARGIN
+    }
+
+    fn space(&mut self, mm: f32) {
+        if !self.at_page_top() {
+            self.y -= mm;
+        }
+    }
+
+    fn lines(&mut self, text: &str, font: &Font, size: f32, indent: f32) -> Result<()> {
+        let line_height = size * PT_TO_MM * 1.5;
+        let text = font.encodable(text)?;
+        for line in font.wrap(&text, size, PAGE_WIDTH - 2.0 * MARGIN - indent) {
+            if self.y - line_height < MARGIN {
+                self.new_page();
+            }
+            self.y -= line_height;
+            self.layer
+                .use_text(line, size, Mm(MARGIN + indent), Mm(self.y), &font.pdf);
+        }
+        Ok(())
+    }
+}
+
+struct Fonts {
+    text: Font,
+    bold: Font,
+    mono: Font,
+}
+
+impl Fonts {
+    /// Builtin fonts can't render CJK text, so ttf fonts can be configured
+    /// with `PDF_FONT`, `PDF_BOLD_FONT` and `PDF_MONO_FONT`.
+    fn load(doc: &PdfDocumentReference) -> Result<Self> {
+        let Ok(path) = env::var("PDF_FONT") else {
+            return Ok(Fonts {
+                text: Font::builtin(doc, BuiltinFont::Helvetica, 0.5)?,
+                bold: Font::builtin(doc, BuiltinFont::HelveticaBold, 0.55)?,
+                mono: Font::builtin(doc, BuiltinFont::Courier, 0.6)?,
+            });
+        };
+        let bold = env::var("PDF_BOLD_FONT").unwrap_or(path.to_owned());
+        let mono = env::var("PDF_MONO_FONT").unwrap_or(path.to_owned());
+
+        Ok(Fonts {
+            text: Font::external(doc, &path)?,
+            bold: Font::external(doc, &bold)?,
+            mono: Font::external(doc, &mono)?,
+        })
+    }
+}
+
+struct Writer<'a> {
+    cursor: Cursor,
+    fonts: Fonts,
+    repo: &'a Repo,
+    images: Option<ImageProcessor>,
+}
+
+impl<'a> Writer<'a> {
+    fn new(title: &str, repo: &'a Repo, images: Option<ImageProcessor>) -> Result<Self> {
+        let (doc, page, layer) =
+            PdfDocument::new(title, Mm(PAGE_WIDTH), Mm(PAGE_HEIGHT), "content");
+        let layer = doc.get_page(page).get_layer(layer);
+        let fonts = Fonts::load(&doc)?;
+
+        Ok(Writer {
+            cursor: Cursor {
+                doc,
+                page,
+                layer,
+                y: PAGE_HEIGHT - MARGIN,
+            },
+            fonts,
+            repo,
+            images,
+        })
+    }
+
+    fn write_image(&mut self, src: &str) -> Result<()> {
+        let Some(path) = self.repo.find_local_image(src) else {
+            return Ok(());
+        };
+        let path = match &self.images {
+            Some(processor) => processor.process_or_original(path),
+            None => path,
+        };
+        let Some(xobject) = image_xobject(&path)? else {
+            return Ok(());
+        };
+
+        let max_w = PAGE_WIDTH - 2.0 * MARGIN;
+        let max_h = PAGE_HEIGHT - 2.0 * MARGIN;
+        let px_to_mm = 25.4 / IMAGE_DPI;
+        let (w, h) = (
+            xobject.width.0 as f32 * px_to_mm,
+            xobject.height.0 as f32 * px_to_mm,
+        );
+        let scale = (max_w / w).min(max_h / h).min(1.0);
+        let cursor = &mut self.cursor;
+        if cursor.y - h * scale < MARGIN {
+            cursor.new_page();
+        }
+        cursor.y -= h * scale;
+        Image::from(xobject).add_to_layer(
+            cursor.layer.clone(),
+            ImageTransform {
+                translate_x: Some(Mm(MARGIN)),
+                translate_y: Some(Mm(cursor.y)),
+                scale_x: Some(scale),
+                scale_y: Some(scale),
+                dpi: Some(IMAGE_DPI),
+                ..Default::default()
+            },
+        );
+        Ok(())
+    }
+
+    fn write_article(&mut self, title: &str, html: &str) -> Result<()> {
+        let (cursor, fonts) = (&mut self.cursor, &self.fonts);
+        if !cursor.at_page_top() {
+            cursor.new_page();
+        }
+        cursor.doc.add_bookmark(title, cursor.page);
+        cursor.lines(title, &fonts.bold, 18.0, 0.0)?;
+        cursor.space(4.0);
+
+        for block in blocks(html) {
+            let (cursor, fonts) = (&mut self.cursor, &self.fonts);
+            match block {
+                Block::Heading(level, text) => {
+                    cursor.space(3.0);
+                    cursor.lines(&text, &fonts.bold, 16.0 - level as f32, 0.0)?;
+                    cursor.space(1.0);
+                }
+                Block::Text(text) => {
+                    cursor.lines(&text, &fonts.text, BODY_SIZE, 0.0)?;
+                    cursor.space(2.0);
+                }
+                Block::Item(text) => {
+                    cursor.lines(&format!("- {text}"), &fonts.text, BODY_SIZE, 4.0)?;
+                    cursor.space(1.0);
+                }
+                Block::Quote(text) => {
+                    cursor.lines(&text, &fonts.text, BODY_SIZE, 8.0)?;
+                    cursor.space(2.0);
+                }
+                Block::Code(text) => {
+                    cursor.lines(&text, &fonts.mono, CODE_SIZE, 4.0)?;
+                    cursor.space(2.0);
+                }
+                Block::Image(src) => {
+                    self.write_image(&src)?;
+                    self.cursor.space(2.0);
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Jpegs are embedded as they are, other formats are decoded to raw pixels.
+fn image_xobject(path: &Path) -> Result<Option<ImageXObject>> {
+    let data = fs::read(path)?;
+    let reader = image::ImageReader::new(std::io::Cursor::new(&data)).with_guessed_format()?;
+    if reader.format() == Some(image::ImageFormat::Jpeg) {
+        let img = match image::load_from_memory(&data) {
+            Ok(img) => img,
+            Err(_) => return Ok(None),
+        };
+        let color_space = match img.color().channel_count() {
+            1 => ColorSpace::Greyscale,
+            _ => ColorSpace::Rgb,
+        };
+        return Ok(Some(ImageXObject {
+            width: Px(img.width() as usize),
+            height: Px(img.height() as usize),
+            color_space,
+            bits_per_component: ColorBits::Bit8,
+            interpolate: true,
+            image_data: data,
+            image_filter: Some(ImageFilter::DCT),
+            smask: None,
+            clipping_bbox: None,
+        }));
+    }
+
+    let img = match reader.decode() {
+        Ok(img) => img.to_rgb8(),
+        Err(_) => return Ok(None),
+    };
+    Ok(Some(ImageXObject {
+        width: Px(img.width() as usize),
+        height: Px(img.height() as usize),
+        color_space: ColorSpace::Rgb,
+        bits_per_component: ColorBits::Bit8,
+        interpolate: true,
+        image_data: img.into_raw(),
+        image_filter: None,
+        smask: None,
+        clipping_bbox: None,
+    }))
+}
+
+/// Renders a book as an A4 pdf with one bookmark per article.
+pub fn book_pdf(repo: &Repo, book: &Book, profile: Option<DeviceProfile>) -> Result<Vec<u8>> {
+    let images = profile.map(|p| ImageProcessor::new(repo.cache_dir("images"), p));
+    let mut w = Writer::new(&book.title, repo, images)?;
+
+    let (cursor, fonts) = (&mut w.cursor, &w.fonts);
+    cursor.lines(&book.title, &fonts.bold, 24.0, 0.0)?;
+    if !book.author.is_empty() {
+        cursor.space(4.0);
+        cursor.lines(&book.author, &fonts.text, 14.0, 0.0)?;
+    }
+    if let Some(desc) = &book.description {
+        cursor.space(8.0);
+        for block in blocks(desc) {
+            if let Block::Text(text) = block {
+                cursor.lines(&text, &fonts.text, BODY_SIZE, 0.0)?;
+                cursor.space(2.0);
+            }
+        }
+    }
+
+    for (section, articles) in &book.sections {
+        for (i, (article, content)) in articles.iter().enumerate() {
+            let title = match i {
+                0 => format!("{} · {}", section.title, article.title),
+                _ => article.title.to_owned(),
+            };
+            w.write_article(&title, content)?;
+        }
+    }
+
+    Ok(w.cursor.doc.save_to_bytes()?)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn builtin() -> Font {
+        let doc = PdfDocument::empty("test");
+        Font::builtin(&doc, BuiltinFont::Helvetica, 0.5).unwrap()
+    }
+
+    #[test]
+    fn builtin_fonts_replace_typographic_punctuation() {
+        let text = builtin().encodable("“Rust’s” — fast…").unwrap();
+        assert_eq!(text, "\"Rust's\" - fast...");
+    }
+
+    #[test]
+    fn builtin_fonts_reject_cjk_text() {
+        let err = builtin().encodable("并发编程").unwrap_err();
+        assert!(matches!(
+            err.downcast_ref::<AppError>(),
+            Some(AppError::Unsupported(_))
+        ));
+    }
+}
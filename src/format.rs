@@ -0,0 +1,462 @@
+use {
+    crate::html,
+    anyhow::anyhow,
+    ego_tree::NodeRef,
+    scraper::{Html, Node},
+    serde::Deserialize,
+    std::str::FromStr,
+    utoipa::ToSchema,
+};
+
+/// Elements rendered as blocks separated by blank lines, anything else is inline.
+const BLOCK_ELEMENTS: &[&str] = &[
+    "p",
+    "div",
+    "h1",
+    "h2",
+    "h3",
+    "h4",
+    "h5",
+    "h6",
+    "ul",
+    "ol",
+    "pre",
+    "blockquote",
+    "table",
+    "hr",
+    "figure",
+    "figcaption",
+    "caption",
+    "dl",
+    "dt",
+    "dd",
+    "section",
+    "article",
+    "header",
+    "footer",
+];
+
+/// Format in which article content is delivered to clients.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize, ToSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum ContentFormat {
+    #[default]
+    Html,
+    Markdown,
+    Plaintext,
+}
+
+impl ContentFormat {
+    pub fn content_type(&self) -> &'static str {
+        match self {
+            ContentFormat::Html => "text/html; charset=utf-8",
+            ContentFormat::Markdown => "text/markdown; charset=utf-8",
+            ContentFormat::Plaintext => "text/plain; charset=utf-8",
+        }
+    }
+}
+
+impl FromStr for ContentFormat {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "html" => Ok(ContentFormat::Html),
+            "markdown" | "md" => Ok(ContentFormat::Markdown),
+            "plaintext" | "text" => Ok(ContentFormat::Plaintext),
+            _ => Err(anyhow!("unknown format {s}")),
+        }
+    }
+}
+
+/// Converts sanitized article html to the requested format.
+pub fn convert(html: &str, format: ContentFormat) -> String {
+    let markdown = match format {
+        ContentFormat::Html => return html.to_owned(),
+        ContentFormat::Markdown => true,
+        ContentFormat::Plaintext => false,
+    };
+    let doc = Html::parse_fragment(html);
+    let mut out = Renderer { markdown }
+        .blocks(*doc.root_element())
+        .join("\n\n");
+    if !out.is_empty() {
+        out.push('\n');
+    }
+    out
+}
+
+struct Renderer {
+    markdown: bool,
+}
+
+impl Renderer {
+    /// Renders the children of `node`, grouping runs of inline content into paragraphs.
+    fn blocks(&self, node: NodeRef<Node>) -> Vec<String> {
+        let mut blocks = vec![];
+        let mut inline = String::new();
+        for child in node.children() {
+            match child.value().as_element() {
+                Some(el) if BLOCK_ELEMENTS.contains(&el.name()) => {
+                    push_paragraph(&mut blocks, &inline);
+                    inline.clear();
+                    blocks.extend(self.block(child, el.name()));
+                }
+                _ => self.inline(child, &mut inline),
+            }
+        }
+        push_paragraph(&mut blocks, &inline);
+        blocks
+    }
+
+    fn block(&self, node: NodeRef<Node>, name: &str) -> Vec<String> {
+        match name {
+            "h1" | "h2" | "h3" | "h4" | "h5" | "h6" => {
+                let text = self.inline_text(node);
+                if text.is_empty() {
+                    return vec![];
+                }
+                if !self.markdown {
+                    return vec![text];
+                }
+                let level = name[1..].parse().unwrap_or(1);
+                vec![format!("{} {}", "#".repeat(level), text.replace('\n', " "))]
+            }
+            "ul" | "ol" => vec![self.list(node, name == "ol")],
+            "pre" => vec![self.code_block(node)],
+            "blockquote" => {
+                let prefix = if self.markdown { "> " } else { "    " };
+                let quote = self.blocks(node).join("\n\n");
+                vec![prefix_lines(&quote, prefix, prefix)]
+            }
+            "table" => vec![self.table(node)],
+            "hr" => vec!["---".to_owned()],
+            _ => self.blocks(node),
+        }
+        .into_iter()
+        .filter(|b| !b.trim().is_empty())
+        .collect()
+    }
+
+    fn list(&self, node: NodeRef<Node>, ordered: bool) -> String {
+        let start = node
+            .value()
+            .as_element()
+            .and_then(|el| el.attr("start"))
+            .and_then(|s| s.parse::<usize>().ok())
+            .unwrap_or(1);
+        node.children()
+            .filter(|n| n.value().as_element().map(|el| el.name()) == Some("li"))
+            .enumerate()
+            .map(|(i, li)| {
+                let marker = if ordered {
+                    format!("{}. ", start + i)
+                } else {
+                    "- ".to_owned()
+                };
+                let item = self.blocks(li).join("\n");
+                prefix_lines(&item, &marker, &" ".repeat(marker.len()))
+            })
+            .collect::<Vec<_>>()
+            .join("\n")
+    }
+
+    fn code_block(&self, pre: NodeRef<Node>) -> String {
+        let mut code = String::new();
+        html::code_text(pre, &mut code);
+        let code = code.trim_end_matches('\n');
+        if !self.markdown {
+            return prefix_lines(code, "    ", "    ");
+        }
+
+        // the fence must be longer than any backtick run inside the code
+        let fence = "`".repeat(longest_run(code, '`').max(2) + 1);
+        let lang = html::code_language(pre).unwrap_or_default();
+        format!("{fence}{lang}\n{code}\n{fence}")
+    }
+
+    fn table(&self, table: NodeRef<Node>) -> String {
+        let rows = table
+            .children()
+            .flat_map(|n| match n.value().as_element().map(|el| el.name()) {
+                Some("thead") | Some("tbody") | Some("tfoot") => n.children().collect(),
+                _ => vec![n],
+            })
+            .filter(|n| n.value().as_element().map(|el| el.name()) == Some("tr"))
+            .map(|tr| {
+                tr.children()
+                    .filter(|n| {
+                        matches!(
+                            n.value().as_element().map(|el| el.name()),
+                            Some("th" | "td")
+                        )
+                    })
+                    .map(|cell| {
+                        let text = self.inline_text(cell).replace('\n', " ");
+                        if self.markdown {
+                            text.replace('|', "\\|")
+                        } else {
+                            text
+                        }
+                    })
+                    .collect::<Vec<_>>()
+            })
+            .collect::<Vec<_>>();
+        let columns = rows.iter().map(Vec::len).max().unwrap_or(0);
+        if columns == 0 {
+            return String::new();
+        }
+
+        let row = |cells: &[String]| {
+            let mut cells = cells.to_vec();
+            cells.resize(columns, String::new());
+            if self.markdown {
+                format!("| {} |", cells.join(" | "))
+            } else {
+                cells.join(" | ").trim_end().to_owned()
+            }
+        };
+        let mut lines = rows.iter().map(|r| row(r)).collect::<Vec<_>>();
+        if self.markdown {
+            lines.insert(1, format!("|{}", " --- |".repeat(columns)));
+        }
+        lines.join("\n")
+    }
+
+    fn inline_text(&self, node: NodeRef<Node>) -> String {
+        let mut text = String::new();
+        for child in node.children() {
+            self.inline(child, &mut text);
+        }
+        tidy_lines(&text)
+    }
+
+    fn inline(&self, node: NodeRef<Node>, out: &mut String) {
+        let el = match node.value() {
+            Node::Text(text) => {
+                let text = collapse_whitespace(text, out.is_empty() || out.ends_with(' '));
+                out.push_str(&if self.markdown { escape(&text) } else { text });
+                return;
+            }
+            Node::Element(el) => el,
+            _ => return,
+        };
+
+        let wrap = |out: &mut String, mark: &str| {
+            let text = self.inline_text(node);
+            if text.is_empty() {
+                return;
+            }
+            if self.markdown {
+                out.push_str(&format!("{mark}{text}{mark}"));
+            } else {
+                out.push_str(&text);
+            }
+        };
+        match el.name() {
+            "br" => out.push_str(if self.markdown { "  \n" } else { "\n" }),
+            "b" | "strong" => wrap(out, "**"),
+            "i" | "em" => wrap(out, "*"),
+            "s" | "del" => wrap(out, "~~"),
+            "code" | "kbd" => {
+                let mut code = String::new();
+                html::code_text(node, &mut code);
+                let code = code.replace('\n', " ");
+                if code.trim().is_empty() {
+                    return;
+                }
+                if self.markdown {
+                    let ticks = "`".repeat(longest_run(&code, '`') + 1);
+                    let pad = if code.starts_with('`') || code.ends_with('`') {
+                        " "
+                    } else {
+                        ""
+                    };
+                    out.push_str(&format!("{ticks}{pad}{code}{pad}{ticks}"));
+                } else {
+                    out.push_str(&code);
+                }
+            }
+            "a" => {
+                let text = self.inline_text(node);
+                match el.attr("href").filter(|href| !href.is_empty()) {
+                    Some(href) if self.markdown => {
+                        out.push_str(&format!("[{text}]({})", escape_url(href)))
+                    }
+                    Some(href) if !href.starts_with('#') && href != text => {
+                        out.push_str(&format!("{text} ({href})"))
+                    }
+                    _ => out.push_str(&text),
+                }
+            }
+            "img" => {
+                let src = el.attr("src").unwrap_or_default();
+                let alt = el.attr("alt").unwrap_or_default();
+                if self.markdown {
+                    out.push_str(&format!("![{}]({})", escape(alt), escape_url(src)));
+                } else if !alt.is_empty() {
+                    out.push_str(&format!("[image: {alt}]"));
+                } else {
+                    out.push_str(&format!("[image: {src}]"));
+                }
+            }
+            // block elements nested in inline ones are flattened
+            name if BLOCK_ELEMENTS.contains(&name) => {
+                out.push(' ');
+                for child in node.children() {
+                    self.inline(child, out);
+                }
+                out.push(' ');
+            }
+            _ => {
+                for child in node.children() {
+                    self.inline(child, out);
+                }
+            }
+        }
+    }
+}
+
+fn push_paragraph(blocks: &mut Vec<String>, inline: &str) {
+    let text = tidy_lines(inline);
+    if !text.is_empty() {
+        blocks.push(text);
+    }
+}
+
+/// Trims the paragraph and the indentation left on lines after a `<br>`,
+/// keeping the trailing spaces of markdown hard breaks.
+fn tidy_lines(text: &str) -> String {
+    text.trim()
+        .split('\n')
+        .map(str::trim_start)
+        .collect::<Vec<_>>()
+        .join("\n")
+}
+
+/// HTML whitespace rules: any run of whitespace renders as a single space.
+fn collapse_whitespace(text: &str, trim_start: bool) -> String {
+    let mut out = String::with_capacity(text.len());
+    let mut space = trim_start;
+    for c in text.chars() {
+        if c.is_whitespace() && c != '\u{a0}' {
+            if !space {
+                out.push(' ');
+            }
+            space = true;
+        } else {
+            out.push(c);
+            space = false;
+        }
+    }
+    out
+}
+
+fn prefix_lines(text: &str, first: &str, rest: &str) -> String {
+    text.lines()
+        .enumerate()
+        .map(|(i, line)| {
+            let prefix = if i == 0 { first } else { rest };
+            if line.is_empty() {
+                prefix.trim_end().to_owned()
+            } else {
+                format!("{prefix}{line}")
+            }
+        })
+        .collect::<Vec<_>>()
+        .join("\n")
+}
+
+fn longest_run(text: &str, c: char) -> usize {
+    let (mut longest, mut current) = (0, 0);
+    for ch in text.chars() {
+        current = if ch == c { current + 1 } else { 0 };
+        longest = longest.max(current);
+    }
+    longest
+}
+
+/// Escapes characters which would otherwise be read as markdown syntax.
+fn escape(text: &str) -> String {
+    let mut out = String::with_capacity(text.len());
+    for c in text.chars() {
+        if matches!(c, '\\' | '*' | '_' | '`' | '[' | ']') {
+            out.push('\\');
+        }
+        out.push(c);
+    }
+    out
+}
+
+fn escape_url(url: &str) -> String {
+    url.replace(' ', "%20")
+        .replace('(', "%28")
+        .replace(')', "%29")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn markdown(html: &str) -> String {
+        convert(html, ContentFormat::Markdown)
+    }
+
+    fn text(html: &str) -> String {
+        convert(html, ContentFormat::Plaintext)
+    }
+
+    #[test]
+    fn renders_headings() {
+        let html = "<h1>Title</h1><p>Intro</p><h3>Sub <em>part</em></h3>";
+        assert_eq!(markdown(html), "# Title\n\nIntro\n\n### Sub *part*\n");
+        assert_eq!(text(html), "Title\n\nIntro\n\nSub part\n");
+    }
+
+    #[test]
+    fn renders_lists() {
+        let html = "<ol start=\"3\"><li>three</li><li>four<ul><li>nested</li></ul></li></ol>";
+        assert_eq!(markdown(html), "3. three\n4. four\n   - nested\n");
+        assert_eq!(text(html), "3. three\n4. four\n   - nested\n");
+    }
+
+    #[test]
+    fn fences_code_longer_than_its_backticks() {
+        let html =
+            "<pre><code class=\"language-rust\">let s = \"```\";\nlet c = '`';\n</code></pre>";
+        assert_eq!(
+            markdown(html),
+            "````rust\nlet s = \"```\";\nlet c = '`';\n````\n"
+        );
+        assert_eq!(text(html), "    let s = \"```\";\n    let c = '`';\n");
+        assert_eq!(markdown("<p>run <code>a`b</code></p>"), "run ``a`b``\n");
+    }
+
+    #[test]
+    fn renders_tables() {
+        let html = "<table><thead><tr><th>op</th><th>meaning</th></tr></thead>\
+            <tbody><tr><td>a | b</td><td>or</td></tr><tr><td>!a</td></tr></tbody></table>";
+        assert_eq!(
+            markdown(html),
+            "| op | meaning |\n| --- | --- |\n| a \\| b | or |\n| !a |  |\n"
+        );
+        assert_eq!(text(html), "op | meaning\na | b | or\n!a |\n");
+    }
+
+    #[test]
+    fn renders_image_references() {
+        let html =
+            "<p><img src=\"/images/a (1).png\" alt=\"a [chart]\"><img src=\"/images/b.png\"></p>";
+        assert_eq!(
+            markdown(html),
+            "![a \\[chart\\]](/images/a%20%281%29.png)![](/images/b.png)\n"
+        );
+        assert_eq!(text(html), "[image: a [chart]][image: /images/b.png]\n");
+    }
+
+    #[test]
+    fn keeps_html_as_is() {
+        let html = "<p>a</p>";
+        assert_eq!(convert(html, ContentFormat::Html), html);
+    }
+}
@@ -0,0 +1,342 @@
+// tonic hands out `Status` by value in every signature
+#![allow(clippy::result_large_err)]
+
+use {
+    crate::{
+        comment::{self, Cursor},
+        error::AppError,
+        format::ContentFormat,
+        handlers::{self, UserRole},
+        models,
+        pb::{
+            self,
+            hackbook::v1::{
+                self as v1,
+                hackbook_server::{Hackbook, HackbookServer},
+            },
+        },
+        repo::AsyncRepo,
+        ws_server,
+    },
+    hmac::{Hmac, Mac},
+    log::*,
+    sha2::Sha256,
+    std::net::SocketAddr,
+    tonic::{transport::Server, Request, Response, Status},
+};
+
+/// Serves the `hackbook.v1.Hackbook` service until the process exits.
+pub async fn serve(
+    repo: AsyncRepo,
+    addr: SocketAddr,
+    token: String,
+) -> Result<(), tonic::transport::Error> {
+    let service = HackbookServer::with_interceptor(HackbookService { repo }, move |req| {
+        check_token(req, &token)
+    });
+    Server::builder().add_service(service).serve(addr).await
+}
+
+/// Who a call acts for, put in the request extensions by `check_token`.
+#[derive(Debug, Clone, PartialEq)]
+enum Caller {
+    /// Holder of `GRPC_TOKEN` itself, a trusted backend which may act for
+    /// any `userId`.
+    Backend,
+    /// Holder of a token from `user_token`, only acts for this user.
+    User(String),
+}
+
+fn check_token(mut req: Request<()>, secret: &str) -> Result<Request<()>, Status> {
+    let caller = req
+        .metadata()
+        .get("authorization")
+        .and_then(|v| v.to_str().ok())
+        .and_then(|v| v.strip_prefix("Bearer "))
+        .and_then(|token| verify_token(token, secret, chrono::Utc::now().timestamp()))
+        .ok_or_else(|| Status::unauthenticated("invalid token"))?;
+    req.extensions_mut().insert(caller);
+    Ok(req)
+}
+
+/// A token for a single user, `{userId}.{expiresAt}.{signature}` where the
+/// signature is the hex HMAC-SHA256 of `{userId}.{expiresAt}` keyed with
+/// `GRPC_TOKEN`. Printed by the `grpc-token` command.
+pub fn user_token(secret: &str, user_id: &str, expires_at: i64) -> String {
+    let claim = format!("{user_id}.{expires_at}");
+    let signature = mac(secret, &claim)
+        .finalize()
+        .into_bytes()
+        .iter()
+        .map(|b| format!("{b:02x}"))
+        .collect::<String>();
+    format!("{claim}.{signature}")
+}
+
+fn verify_token(token: &str, secret: &str, now: i64) -> Option<Caller> {
+    // compared through the MAC, in constant time, rather than byte by byte
+    let expected = mac(secret, secret).finalize().into_bytes();
+    if mac(secret, token).verify_slice(&expected).is_ok() {
+        return Some(Caller::Backend);
+    }
+    let (claim, signature) = token.rsplit_once('.')?;
+    let (user_id, expires_at) = claim.rsplit_once('.')?;
+    let signature = (0..signature.len())
+        .step_by(2)
+        .map(|i| u8::from_str_radix(signature.get(i..i + 2)?, 16).ok())
+        .collect::<Option<Vec<_>>>()?;
+    mac(secret, claim).verify_slice(&signature).ok()?;
+    let expired = expires_at.parse::<i64>().ok()? <= now;
+    (!user_id.is_empty() && !expired).then(|| Caller::User(user_id.to_owned()))
+}
+
+fn mac(secret: &str, claim: &str) -> Hmac<Sha256> {
+    let mut mac =
+        Hmac::<Sha256>::new_from_slice(secret.as_bytes()).expect("hmac accepts any key length");
+    mac.update(claim.as_bytes());
+    mac
+}
+
+/// Requests naming the user they act for.
+trait ForUser {
+    fn user_id_mut(&mut self) -> &mut String;
+}
+
+macro_rules! for_user {
+    ($($request:ty),*) => {
+        $(impl ForUser for $request {
+            fn user_id_mut(&mut self) -> &mut String {
+                &mut self.user_id
+            }
+        })*
+    };
+}
+
+for_user!(
+    v1::ListCoursesRequest,
+    v1::GetCourseRequest,
+    v1::GetArticleRequest,
+    v1::ListCommentsRequest,
+    v1::SaveStudyInfoRequest,
+    v1::GetConnectSecondsRequest
+);
+
+/// The message of a request, its `userId` set from a user token. Calls with
+/// a user token can't act for someone else.
+fn bind_user<T: ForUser>(req: Request<T>) -> Result<T, Status> {
+    let caller = req.extensions().get::<Caller>().cloned();
+    let mut msg = req.into_inner();
+    match caller {
+        Some(Caller::Backend) => {}
+        Some(Caller::User(id)) => {
+            let user_id = msg.user_id_mut();
+            if user_id.is_empty() {
+                *user_id = id;
+            } else if *user_id != id {
+                return Err(Status::permission_denied("userId doesn't match the token"));
+            }
+        }
+        None => return Err(Status::unauthenticated("invalid token")),
+    }
+    Ok(msg)
+}
+
+pub struct HackbookService {
+    repo: AsyncRepo,
+}
+
+fn to_status(e: anyhow::Error) -> Status {
+    match AppError::from(e) {
+        AppError::NotFound(msg) => Status::not_found(msg),
+        AppError::BadRequest(msg) => Status::invalid_argument(msg),
+        AppError::Unauthorized => Status::unauthenticated("login required"),
+        AppError::Forbidden => Status::permission_denied("Forbidden"),
+        AppError::TooManyRequests(msg) => Status::resource_exhausted(msg),
+        AppError::Unsupported(msg) => Status::unimplemented(msg),
+        AppError::Internal(e) => {
+            error!("gRPC call failed: {e:#}");
+            Status::internal("internal server error")
+        }
+    }
+}
+
+fn require_user(user_id: &str) -> Result<(), Status> {
+    if user_id.is_empty() {
+        return Err(Status::invalid_argument("userId is required"));
+    }
+    Ok(())
+}
+
+#[tonic::async_trait]
+impl Hackbook for HackbookService {
+    async fn list_courses(
+        &self,
+        req: Request<v1::ListCoursesRequest>,
+    ) -> Result<Response<pb::ListCourseResponse>, Status> {
+        let req = bind_user(req)?;
+        let limit = if req.limit > 0 { req.limit } else { 10 };
+        let (courses, more) = self
+            .repo
+            .run(move |repo| repo.list_course(&req.keyword, req.offset, limit, &req.user_id))
+            .await
+            .map_err(to_status)?;
+
+        Ok(Response::new(pb::ListCourseResponse {
+            courses: courses.into_iter().map(|c| c.into()).collect(),
+            more,
+        }))
+    }
+
+    async fn get_course(
+        &self,
+        req: Request<v1::GetCourseRequest>,
+    ) -> Result<Response<pb::Course>, Status> {
+        let req = bind_user(req)?;
+        let course = self
+            .repo
+            .run(move |repo| handlers::course_detail(repo, &req.course_id, &req.user_id))
+            .await
+            .map_err(to_status)?;
+
+        Ok(Response::new(course))
+    }
+
+    async fn get_article(
+        &self,
+        req: Request<v1::GetArticleRequest>,
+    ) -> Result<Response<pb::Article>, Status> {
+        let req = bind_user(req)?;
+        require_user(&req.user_id)?;
+        let format: ContentFormat = req.format().into();
+        let article = self
+            .repo
+            .run(move |repo| {
+                if let UserRole::Visitor = handlers::get_user_role(repo, &req.user_id) {
+                    return Ok(None);
+                }
+                ws_server::article_detail(repo, &req.article_id, format).map(Some)
+            })
+            .await
+            .map_err(to_status)?
+            .ok_or_else(|| Status::permission_denied("Forbidden"))?;
+
+        Ok(Response::new(article))
+    }
+
+    async fn list_comments(
+        &self,
+        req: Request<v1::ListCommentsRequest>,
+    ) -> Result<Response<pb::CommentList>, Status> {
+        let req = bind_user(req)?;
+        let sort = req.sort().into();
+        let cursor = match req.cursor.as_str() {
+            "" => None,
+            cursor => Some(Cursor::parse(cursor).map_err(|e| to_status(e.into()))?),
+        };
+        let limit = if req.limit > 0 {
+            req.limit
+        } else {
+            comment::DEFAULT_LIMIT
+        };
+        let page = self
+            .repo
+            .run(move |repo| {
+                repo.find_comments_by_article_id(
+                    &req.article_id,
+                    sort,
+                    cursor.as_ref(),
+                    limit,
+                    &req.user_id,
+                    false,
+                )
+            })
+            .await
+            .map_err(to_status)?;
+
+        Ok(Response::new(page.into()))
+    }
+
+    async fn save_study_info(
+        &self,
+        req: Request<v1::SaveStudyInfoRequest>,
+    ) -> Result<Response<v1::SaveStudyInfoResponse>, Status> {
+        let req = bind_user(req)?;
+        require_user(&req.user_id)?;
+        let info = req
+            .info
+            .ok_or_else(|| Status::invalid_argument("info is required"))?;
+        let info = models::UserStudyInfo {
+            id: 0,
+            user_id: req.user_id,
+            article_id: info.article_id,
+            course_id: info.course_id,
+            last_study_at: chrono::Utc::now().timestamp(),
+            study_percent: info.percent,
+        };
+        self.repo
+            .run(move |repo| repo.save_study_info(&info))
+            .await
+            .map_err(to_status)?;
+
+        Ok(Response::new(v1::SaveStudyInfoResponse {}))
+    }
+
+    async fn get_connect_seconds(
+        &self,
+        req: Request<v1::GetConnectSecondsRequest>,
+    ) -> Result<Response<pb::ConnectSeconds>, Status> {
+        let req = bind_user(req)?;
+        require_user(&req.user_id)?;
+        let seconds = self
+            .repo
+            .run(move |repo| {
+                repo.get_connect_seconds(&req.user_id, req.start_at_gt, req.start_at_lt)
+            })
+            .await
+            .map_err(to_status)?;
+
+        Ok(Response::new(pb::ConnectSeconds { seconds }))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SECRET: &str = "s3cret";
+
+    #[test]
+    fn user_tokens_are_bound_to_their_user() {
+        let token = user_token(SECRET, "u-1", 1_000);
+        assert_eq!(
+            verify_token(&token, SECRET, 999),
+            Some(Caller::User("u-1".to_owned()))
+        );
+        assert_eq!(verify_token(&token, SECRET, 1_000), None);
+        assert_eq!(verify_token(&token, "other", 999), None);
+
+        let forged = token.replacen("u-1", "u-2", 1);
+        assert_eq!(verify_token(&forged, SECRET, 999), None);
+        assert_eq!(verify_token(SECRET, SECRET, 999), Some(Caller::Backend));
+        assert_eq!(verify_token(&SECRET[1..], SECRET, 999), None);
+        assert_eq!(verify_token("", SECRET, 999), None);
+    }
+
+    #[test]
+    fn user_tokens_fill_or_check_the_user_id() {
+        let request = |user_id: &str| {
+            let mut req = Request::new(v1::GetConnectSecondsRequest {
+                user_id: user_id.to_owned(),
+                ..Default::default()
+            });
+            req.extensions_mut().insert(Caller::User("u-1".to_owned()));
+            req
+        };
+        assert_eq!(bind_user(request("")).unwrap().user_id, "u-1");
+        assert_eq!(bind_user(request("u-1")).unwrap().user_id, "u-1");
+        assert_eq!(
+            bind_user(request("u-2")).unwrap_err().code(),
+            tonic::Code::PermissionDenied
+        );
+    }
+}
@@ -1,31 +1,95 @@
 use {
-    crate::{models, pb, repo::Repo, ws_server, ws_session},
+    crate::{
+        comment::{self, CommentSort, Cursor, ModerationQueue},
+        error::AppError,
+        export::{self, image::DeviceProfile},
+        format::ContentFormat,
+        models, notification, openapi, pb,
+        repo::{AsyncRepo, Repo, LOCAL_IMAGE_PREFIX},
+        ws_server, ws_session,
+    },
     actix::Addr,
+    actix_files::NamedFile,
     actix_identity::Identity,
     actix_protobuf::{ProtoBuf, ProtoBufResponseBuilder as _},
-    actix_web::{dev::Payload, get, post, web, FromRequest, HttpRequest, HttpResponse},
+    actix_web::{
+        delete, dev::Payload, get, http::header, post, put, web, FromRequest, HttpMessage as _,
+        HttpRequest, HttpResponse, HttpResponseBuilder,
+    },
     actix_web_actors::ws,
     log::*,
     // ory_kratos_client::apis::{configuration::Configuration, v0alpha2_api::to_session},
     serde::{Deserialize, Serialize},
+    sha2::{Digest, Sha256},
     std::{
         // env,
-        future::{ready, Ready},
+        future::{ready, Future, Ready},
+        io::Read as _,
+        pin::Pin,
         time::Instant,
     },
+    utoipa::{IntoParams, OpenApi as _},
 };
 
+/// Course with its sections and articles, with the study progress of the logged in user.
+#[utoipa::path(
+    tag = "course",
+    params(("If-None-Match" = Option<String>, Header, description = "ETag of a cached copy")),
+    responses(
+        (status = 200, description = "Course detail", content(("application/protobuf" = inline(pb::Course)), ("application/json" = inline(pb::Course))),
+            headers(("ETag" = String, description = "Changes with the course and the user's progress"))),
+        (status = 304, description = "Cached copy is still valid"),
+    ),
+    security((), ("cookie" = [])),
+)]
 #[get("/api/course/{course_id}")]
 async fn get_course_detail(
-    repo: web::Data<Repo>,
+    req: HttpRequest,
+    repo: web::Data<AsyncRepo>,
     course_id: web::Path<String>,
     logged_user: LoggedUser,
+    accept: Accept,
 ) -> actix_web::Result<HttpResponse> {
-    let repo = repo.into_inner();
+    let encoding = accept.encoding();
+    let course_id = course_id.into_inner();
+    let user_id = logged_user.id;
+    let etag = {
+        let (course_id, user_id) = (course_id.clone(), user_id.clone());
+        repo.run(move |repo| {
+            let version = repo.get_course_version(&course_id)?;
+            let watermark = if user_id.is_empty() {
+                String::new()
+            } else {
+                repo.get_user_watermark(&user_id)?
+            };
+            Ok(entity_tag(&[
+                &version,
+                &user_id,
+                &watermark,
+                encoding.name(),
+            ]))
+        })
+        .await
+        .map_err(AppError::from)?
+    };
+    if is_fresh(&req, &etag) {
+        return Ok(not_modified(etag));
+    }
+
+    let c = {
+        let user_id = user_id.clone();
+        repo.run(move |repo| course_detail(repo, &course_id, &user_id))
+            .await
+            .map_err(AppError::from)?
+    };
+
+    encoding.reply(catalog_response(etag, &user_id), c)
+}
 
-    let (course, sections, desc) = repo
-        .get_course_detail_by_course_id(course_id.as_str())
-        .map_err(actix_web::error::ErrorInternalServerError)?;
+/// Course with its sections and articles, with the user's study progress and
+/// bookmark counts filled in unless `user_id` is empty.
+pub fn course_detail(repo: &Repo, course_id: &str, user_id: &str) -> anyhow::Result<pb::Course> {
+    let (course, sections, desc) = repo.get_course_detail_by_course_id(course_id)?;
 
     let mut c: pb::Course = course.into();
     c.sections = sections.into_iter().map(|s| s.into()).collect();
@@ -33,95 +97,1142 @@ async fn get_course_detail(
         c.description = d;
     }
 
-    if !logged_user.id.is_empty() {
-        let study_info = repo
-            .find_user_study_info(&logged_user.id, course_id.as_str(), "")
-            .map_err(actix_web::error::ErrorInternalServerError)?;
+    if !user_id.is_empty() {
+        let study_info = repo.find_user_study_info(user_id, course_id, "")?;
+        let bookmark_counts = repo.count_bookmarks_by_course(user_id, course_id)?;
         c.sections.iter_mut().for_each(|s| {
             s.articles.iter_mut().for_each(|a| {
                 a.study_info = study_info
                     .iter()
                     .find(|info| info.article_id == a.id)
-                    .and_then(|info| {
-                        Some(pb::StudyInfo {
-                            last_study_at: info.last_study_at as u64,
-                            percent: info.study_percent,
-                        })
+                    .map(|info| pb::StudyInfo {
+                        last_study_at: info.last_study_at as u64,
+                        percent: info.study_percent,
                     });
+                a.bookmark_count = bookmark_counts
+                    .iter()
+                    .find(|(article_id, _)| *article_id == a.id)
+                    .map_or(0, |(_, count)| *count as u32);
             })
         });
     }
 
-    // debug!("{:?}", query);
+    Ok(c)
+}
+
+#[derive(Debug, Deserialize, IntoParams)]
+#[into_params(parameter_in = Query)]
+pub struct ExportQuery {
+    profile: Option<DeviceProfile>,
+}
+
+/// Course as an EPUB book.
+#[utoipa::path(
+    tag = "export",
+    params(ExportQuery),
+    responses(
+        (status = 200, description = "EPUB book", content_type = "application/epub+zip"),
+        (status = 403, description = "Not a reader"),
+    ),
+    security(("cookie" = [])),
+)]
+#[get("/api/course/{course_id}/epub")]
+async fn get_course_epub(
+    repo: web::Data<AsyncRepo>,
+    course_id: web::Path<String>,
+    query: web::Query<ExportQuery>,
+    logged_user: LoggedUser,
+) -> actix_web::Result<HttpResponse> {
+    if let UserRole::Visitor = logged_user.role {
+        return Err(AppError::Forbidden.into());
+    }
+
+    let filename = format!("{}.epub", course_id.as_str());
+    let epub = repo
+        .run(move |repo| export::epub::course_epub(repo, course_id.as_str(), query.profile))
+        .await
+        .map_err(AppError::from)?;
+
+    Ok(HttpResponse::Ok()
+        .content_type("application/epub+zip")
+        .insert_header(header::ContentEncoding::Identity)
+        .insert_header((
+            "content-disposition",
+            format!("attachment; filename=\"{filename}\""),
+        ))
+        .body(epub))
+}
+
+const NO_PDF_FONT: &str = "The text needs characters the builtin PDF fonts don't \
+    have, e.g. Chinese. Set PDF_FONT (and optionally PDF_BOLD_FONT, PDF_MONO_FONT) \
+    to TrueType fonts covering them.";
+
+/// Course as a PDF document.
+#[utoipa::path(
+    tag = "export",
+    params(ExportQuery),
+    responses(
+        (status = 200, description = "PDF document", content_type = "application/pdf"),
+        (status = 403, description = "Not a reader"),
+        (status = 501, description = NO_PDF_FONT),
+    ),
+    security(("cookie" = [])),
+)]
+#[get("/api/course/{course_id}/pdf")]
+async fn get_course_pdf(
+    repo: web::Data<AsyncRepo>,
+    course_id: web::Path<String>,
+    query: web::Query<ExportQuery>,
+    logged_user: LoggedUser,
+) -> actix_web::Result<HttpResponse> {
+    if let UserRole::Visitor = logged_user.role {
+        return Err(AppError::Forbidden.into());
+    }
+
+    let filename = format!("{}.pdf", course_id.as_str());
+    let pdf = repo
+        .run(move |repo| {
+            let book = export::Book::load(repo, course_id.as_str())?;
+            export::pdf::book_pdf(repo, &book, query.profile)
+        })
+        .await
+        .map_err(AppError::from)?;
+
+    Ok(pdf_response(pdf, &filename))
+}
 
-    HttpResponse::Ok().protobuf(c)
+#[derive(Debug, Deserialize, IntoParams)]
+#[into_params(parameter_in = Query)]
+pub struct ArticlesPdfQuery {
+    /// Comma separated article ids, in reading order.
+    ids: String,
+    profile: Option<DeviceProfile>,
 }
 
-#[derive(Debug, Deserialize)]
+/// Selected articles as a PDF document.
+#[utoipa::path(
+    tag = "export",
+    params(ArticlesPdfQuery),
+    responses(
+        (status = 200, description = "PDF document", content_type = "application/pdf"),
+        (status = 400, description = "No article ids, or too many of them"),
+        (status = 404, description = "Article not found"),
+        (status = 403, description = "Not a reader"),
+        (status = 501, description = NO_PDF_FONT),
+    ),
+    security(("cookie" = [])),
+)]
+#[get("/api/articles/pdf")]
+async fn get_articles_pdf(
+    repo: web::Data<AsyncRepo>,
+    query: web::Query<ArticlesPdfQuery>,
+    logged_user: LoggedUser,
+) -> actix_web::Result<HttpResponse> {
+    if let UserRole::Visitor = logged_user.role {
+        return Err(AppError::Forbidden.into());
+    }
+    let ids = query
+        .ids
+        .split(',')
+        .map(str::trim)
+        .filter(|id| !id.is_empty())
+        .map(str::to_owned)
+        .collect::<Vec<_>>();
+    if ids.is_empty() {
+        return Err(AppError::BadRequest("ids is required".to_owned()).into());
+    }
+    if ids.len() > export::MAX_ARTICLES {
+        return Err(AppError::BadRequest(format!(
+            "at most {} ids are allowed",
+            export::MAX_ARTICLES
+        ))
+        .into());
+    }
+
+    let profile = query.profile;
+    let pdf = repo
+        .run(move |repo| {
+            let book = export::Book::load_articles(repo, &ids)?;
+            export::pdf::book_pdf(repo, &book, profile)
+        })
+        .await
+        .map_err(AppError::from)?;
+
+    Ok(pdf_response(pdf, "articles.pdf"))
+}
+
+fn pdf_response(pdf: Vec<u8>, filename: &str) -> HttpResponse {
+    HttpResponse::Ok()
+        .content_type("application/pdf")
+        .insert_header(header::ContentEncoding::Identity)
+        .insert_header((
+            "content-disposition",
+            format!("attachment; filename=\"{filename}\""),
+        ))
+        .body(pdf)
+}
+
+#[derive(Debug, Deserialize, IntoParams)]
+#[into_params(parameter_in = Query)]
 pub struct ListCourseQuery {
+    /// Page size, 10 by default.
     limit: Option<i64>,
     offset: Option<i64>,
+    /// Matched against course titles.
     keyword: Option<String>,
 }
 
+/// Courses matching a keyword, paginated.
+#[utoipa::path(
+    tag = "course",
+    params(ListCourseQuery, ("If-None-Match" = Option<String>, Header, description = "ETag of a cached copy")),
+    responses(
+        (status = 200, description = "A page of courses", content(("application/protobuf" = inline(pb::ListCourseResponse)), ("application/json" = inline(pb::ListCourseResponse))),
+            headers(("ETag" = String, description = "Changes with the catalog and the user's progress"))),
+        (status = 304, description = "Cached copy is still valid"),
+    ),
+    security((), ("cookie" = [])),
+)]
 #[get("/api/courses")]
 async fn list_course(
-    repo: web::Data<Repo>,
+    req: HttpRequest,
+    repo: web::Data<AsyncRepo>,
     query: web::Query<ListCourseQuery>,
     user: LoggedUser,
+    accept: Accept,
 ) -> actix_web::Result<HttpResponse> {
-    let repo = repo.into_inner();
-    // use web::block to offload blocking Diesel code without blocking server thread
-    let (courses, has_more) = web::block(move || {
-        repo.list_course(
-            query.keyword.as_ref().unwrap_or(&String::new()),
-            query.offset.unwrap_or(0),
-            query.limit.unwrap_or(10),
-            user.id.as_str(),
-        )
-    })
-    .await?
-    .map_err(actix_web::error::ErrorInternalServerError)?;
+    let encoding = accept.encoding();
+    let user_id = user.id.clone();
+    let keyword = query.keyword.clone().unwrap_or_default();
+    let (offset, limit) = (query.offset.unwrap_or(0), query.limit.unwrap_or(10));
+    let etag = {
+        let user_id = user_id.clone();
+        repo.run(move |repo| {
+            let version = repo.get_catalog_version()?;
+            // the page is ordered by the user's progress
+            let watermark = if user_id.is_empty() {
+                String::new()
+            } else {
+                repo.get_user_watermark(&user_id)?
+            };
+            Ok(entity_tag(&[
+                &version,
+                &keyword,
+                &offset.to_string(),
+                &limit.to_string(),
+                &user_id,
+                &watermark,
+                encoding.name(),
+            ]))
+        })
+        .await
+        .map_err(AppError::from)?
+    };
+    if is_fresh(&req, &etag) {
+        return Ok(not_modified(etag));
+    }
+
+    let (courses, has_more) = repo
+        .run(move |repo| {
+            repo.list_course(
+                query.keyword.as_ref().unwrap_or(&String::new()),
+                offset,
+                limit,
+                user.id.as_str(),
+            )
+        })
+        .await
+        .map_err(AppError::from)?;
 
     let c = pb::ListCourseResponse {
         courses: courses.into_iter().map(|c| c.into()).collect(),
         more: has_more,
     };
 
-    HttpResponse::Ok().protobuf(c)
+    encoding.reply(catalog_response(etag, &user_id), c)
+}
+
+/// Tag over everything a response depends on. Weak, the bytes sent differ
+/// with the negotiated `Content-Encoding`.
+fn entity_tag(parts: &[&str]) -> header::EntityTag {
+    let mut hasher = Sha256::new();
+    for part in parts {
+        hasher.update(part.as_bytes());
+        hasher.update([0]);
+    }
+    header::EntityTag::new_weak(format!("{:x}", hasher.finalize())[..32].to_owned())
+}
+
+/// Whether the client's cached copy, sent in `If-None-Match`, is `etag`.
+fn is_fresh(req: &HttpRequest, etag: &header::EntityTag) -> bool {
+    match req.get_header::<header::IfNoneMatch>() {
+        Some(header::IfNoneMatch::Any) => true,
+        Some(header::IfNoneMatch::Items(tags)) => tags.iter().any(|t| t.weak_eq(etag)),
+        None => false,
+    }
+}
+
+fn not_modified(etag: header::EntityTag) -> HttpResponse {
+    HttpResponse::NotModified()
+        .insert_header(header::ETag(etag))
+        .finish()
 }
 
+/// Anonymous catalog responses are the same for everyone and may be kept by
+/// shared caches, personalized ones only by the browser, which revalidates.
+fn catalog_response(etag: header::EntityTag, user_id: &str) -> HttpResponseBuilder {
+    let mut res = HttpResponse::Ok();
+    res.insert_header(header::ETag(etag))
+        .append_header((header::VARY, "Cookie"));
+    if user_id.is_empty() {
+        res.insert_header((header::CACHE_CONTROL, "public, max-age=300"));
+    } else {
+        res.insert_header((header::CACHE_CONTROL, "private, no-cache"));
+    }
+    res
+}
+
+/// Article with its content in the requested format.
+#[utoipa::path(
+    tag = "article",
+    params(ContentQuery, ("If-None-Match" = Option<String>, Header, description = "ETag of a cached copy")),
+    responses(
+        (status = 200, description = "Article detail", content(("application/protobuf" = inline(pb::Article)), ("application/json" = inline(pb::Article))),
+            headers(("ETag" = String, description = "Changes with the content and representation"))),
+        (status = 304, description = "Cached copy is still valid"),
+        (status = 403, description = "Not a reader"),
+        (status = 404, description = "Article not found"),
+    ),
+    security(("cookie" = [])),
+)]
+#[get("/api/article/{article_id}")]
+async fn get_article(
+    req: HttpRequest,
+    repo: web::Data<AsyncRepo>,
+    article_id: web::Path<String>,
+    query: web::Query<ContentQuery>,
+    logged_user: LoggedUser,
+    accept: Accept,
+) -> actix_web::Result<HttpResponse> {
+    let format = query.format.unwrap_or_default();
+    let encoding = accept.encoding();
+    article_reply(
+        &req,
+        &repo,
+        article_id.into_inner(),
+        format,
+        &logged_user,
+        encoding.name(),
+        |res, article| encoding.reply(res, article),
+    )
+    .await
+}
+
+/// Both article endpoints go through here, so the access checks, the ETag
+/// and the errors are the same whatever the representation. `reply` writes
+/// the body, `representation` tells it apart in the tag.
+async fn article_reply(
+    req: &HttpRequest,
+    repo: &AsyncRepo,
+    id: String,
+    format: ContentFormat,
+    logged_user: &LoggedUser,
+    representation: &str,
+    reply: impl FnOnce(HttpResponseBuilder, pb::Article) -> actix_web::Result<HttpResponse>,
+) -> actix_web::Result<HttpResponse> {
+    if let UserRole::Visitor = logged_user.role {
+        return Err(AppError::Forbidden.into());
+    }
+
+    let version = {
+        let id = id.clone();
+        repo.run(move |repo| repo.get_article_version(&id))
+            .await
+            .map_err(AppError::from)?
+    };
+    // the representation is part of the tag, the same rows serve several
+    // bodies. Weak like the catalog tags, the bytes sent also differ with the
+    // negotiated `Content-Encoding`
+    let etag = header::EntityTag::new_weak(format!(
+        "{}-{}-{}",
+        &version[..32],
+        pb::ContentFormat::from(format).as_str_name().to_lowercase(),
+        representation,
+    ));
+    if is_fresh(req, &etag) {
+        return Ok(not_modified(etag));
+    }
+
+    let article = repo
+        .run(move |repo| ws_server::article_detail(repo, &id, format))
+        .await
+        .map_err(AppError::from)?;
+
+    let mut res = HttpResponse::Ok();
+    res.insert_header(header::ETag(etag))
+        .insert_header((header::CACHE_CONTROL, "private, no-cache"));
+    reply(res, article)
+}
+
+#[derive(Deserialize, IntoParams)]
+#[into_params(parameter_in = Query)]
+pub struct ContentQuery {
+    format: Option<ContentFormat>,
+}
+
+/// Article content only, as html, markdown or plain text. Same checks and
+/// ETag as the article detail.
+#[utoipa::path(
+    tag = "article",
+    params(ContentQuery, ("If-None-Match" = Option<String>, Header, description = "ETag of a cached copy")),
+    responses(
+        (status = 200, description = "Article content", content_type = "text/html",
+            headers(("ETag" = String, description = "Changes with the content and representation"))),
+        (status = 304, description = "Cached copy is still valid"),
+        (status = 403, description = "Not a reader"),
+        (status = 404, description = "Article not found"),
+    ),
+    security(("cookie" = [])),
+)]
+#[get("/api/article/{article_id}/content")]
+async fn get_article_content(
+    req: HttpRequest,
+    repo: web::Data<AsyncRepo>,
+    article_id: web::Path<String>,
+    query: web::Query<ContentQuery>,
+    logged_user: LoggedUser,
+) -> actix_web::Result<HttpResponse> {
+    let format = query.format.unwrap_or_default();
+    article_reply(
+        &req,
+        &repo,
+        article_id.into_inner(),
+        format,
+        &logged_user,
+        "raw",
+        |mut res, article| {
+            Ok(res
+                .content_type(format.content_type())
+                .body(article.content))
+        },
+    )
+    .await
+}
+
+#[derive(Deserialize, IntoParams)]
+#[into_params(parameter_in = Query)]
+pub struct CommentQuery {
+    /// Order of the top level comments, by likes by default.
+    sort: Option<CommentSort>,
+    /// `nextCursor` of the previous page, with the same sort.
+    cursor: Option<String>,
+    /// Page size, 20 by default and at most 100.
+    limit: Option<i64>,
+}
+
+/// A page of the top level comments of an article with their replies, the
+/// pinned ones on top of the first page. Hidden comments are only listed
+/// for admins.
+#[utoipa::path(
+    tag = "article",
+    params(CommentQuery),
+    responses(
+        (status = 200, description = "Comments", content(("application/protobuf" = inline(pb::CommentList)), ("application/json" = inline(pb::CommentList)))),
+        (status = 400, description = "Invalid cursor"),
+    ),
+)]
 #[get("/api/article/{article_id}/comments")]
 async fn get_article_comments(
-    repo: web::Data<Repo>,
+    repo: web::Data<AsyncRepo>,
     article_id: web::Path<String>,
+    query: web::Query<CommentQuery>,
+    logged_user: LoggedUser,
+    accept: Accept,
 ) -> actix_web::Result<HttpResponse> {
-    let repo = repo.into_inner();
-    // use web::block to offload blocking Diesel code without blocking server thread
-    let comments = web::block(move || repo.find_comments_by_article_id(article_id.as_str()))
-        .await?
-        .map_err(actix_web::error::ErrorInternalServerError)?;
-
-    HttpResponse::Ok().protobuf(pb::CommentList {
-        comments: comments
-            .into_iter()
-            .map(|(replies, comment)| {
-                let mut pbc: pb::Comment = comment.into();
-                pbc.replies = replies.into_iter().map(|r| r.into()).collect();
-                pbc
-            })
-            .collect::<Vec<_>>(),
-    })
+    let cursor = query
+        .cursor
+        .as_deref()
+        .filter(|c| !c.is_empty())
+        .map(Cursor::parse)
+        .transpose()?;
+    let page = repo
+        .run(move |repo| {
+            repo.find_comments_by_article_id(
+                article_id.as_str(),
+                query.sort.unwrap_or_default(),
+                cursor.as_ref(),
+                query.limit.unwrap_or(comment::DEFAULT_LIMIT),
+                &logged_user.id,
+                logged_user.is_admin(),
+            )
+        })
+        .await
+        .map_err(AppError::from)?;
+
+    accept.reply(HttpResponse::Ok(), pb::CommentList::from(page))
+}
+
+/// Posts a comment on an article, or a reply to one of its comments. The
+/// author of the comment replied to is notified.
+#[utoipa::path(
+    tag = "comment",
+    request_body(content = inline(pb::SaveCommentRequest), content_type = "application/protobuf"),
+    responses(
+        (status = 200, description = "Posted comment", content(("application/protobuf" = inline(pb::Comment)), ("application/json" = inline(pb::Comment)))),
+        (status = 400, description = "Invalid content, article or parent comment"),
+        (status = 401, description = "Not logged in"),
+        (status = 429, description = "Posted too many comments lately"),
+    ),
+    security(("cookie" = [])),
+)]
+#[post("/api/article/{article_id}/comments")]
+async fn post_comment(
+    repo: web::Data<AsyncRepo>,
+    srv: web::Data<Addr<ws_server::WsServer>>,
+    article_id: web::Path<String>,
+    logged_user: LoggedUser,
+    req: ProtoBuf<pb::SaveCommentRequest>,
+    accept: Accept,
+) -> actix_web::Result<HttpResponse> {
+    if logged_user.id.is_empty() {
+        return Err(AppError::Unauthorized.into());
+    }
+    let content = comment::validate_content(&req.content)?;
+    let nick_name = comment::validate_nick_name(&req.nick_name, &logged_user.id)?;
+    let parent_id = Some(req.parent_id.to_owned()).filter(|id| !id.is_empty());
+
+    let (comment, notification) = repo
+        .run(move |repo| {
+            repo.post_comment(
+                &logged_user.id,
+                article_id.as_str(),
+                parent_id.as_deref(),
+                &nick_name,
+                &content,
+            )
+        })
+        .await
+        .map_err(AppError::from)?;
+
+    if let Some(n) = notification {
+        srv.do_send(ws_server::Notify {
+            user_id: n.user_id.clone(),
+            notification: n.into(),
+        });
+    }
+    accept.reply(HttpResponse::Ok(), pb::Comment::from(comment))
+}
+
+/// Replaces the content of a comment of the logged in user.
+#[utoipa::path(
+    tag = "comment",
+    request_body(content = inline(pb::SaveCommentRequest), content_type = "application/protobuf", description = "Only `content` is used"),
+    responses(
+        (status = 200, description = "Edited comment", content(("application/protobuf" = inline(pb::Comment)), ("application/json" = inline(pb::Comment)))),
+        (status = 400, description = "Invalid content"),
+        (status = 401, description = "Not logged in"),
+        (status = 404, description = "Comment not found"),
+    ),
+    security(("cookie" = [])),
+)]
+#[put("/api/comment/{id}")]
+async fn edit_comment(
+    repo: web::Data<AsyncRepo>,
+    id: web::Path<String>,
+    logged_user: LoggedUser,
+    req: ProtoBuf<pb::SaveCommentRequest>,
+    accept: Accept,
+) -> actix_web::Result<HttpResponse> {
+    if logged_user.id.is_empty() {
+        return Err(AppError::Unauthorized.into());
+    }
+    let content = comment::validate_content(&req.content)?;
+
+    let comment = repo
+        .run(move |repo| repo.edit_comment(&logged_user.id, id.as_str(), &content))
+        .await
+        .map_err(AppError::from)?
+        .ok_or_else(|| AppError::NotFound("comment not found".to_owned()))?;
+
+    accept.reply(HttpResponse::Ok(), pb::Comment::from(comment))
+}
+
+/// Deletes a comment of the logged in user.
+#[utoipa::path(
+    tag = "comment",
+    responses(
+        (status = 200, description = "Comment deleted"),
+        (status = 401, description = "Not logged in"),
+        (status = 404, description = "Comment not found"),
+    ),
+    security(("cookie" = [])),
+)]
+#[delete("/api/comment/{id}")]
+async fn delete_comment(
+    repo: web::Data<AsyncRepo>,
+    id: web::Path<String>,
+    logged_user: LoggedUser,
+) -> actix_web::Result<HttpResponse> {
+    if logged_user.id.is_empty() {
+        return Err(AppError::Unauthorized.into());
+    }
+
+    let deleted = repo
+        .run(move |repo| repo.delete_comment(&logged_user.id, id.as_str()))
+        .await
+        .map_err(AppError::from)?;
+
+    if !deleted {
+        return Err(AppError::NotFound("comment not found".to_owned()).into());
+    }
+    Ok(HttpResponse::Ok().finish())
+}
+
+/// Likes a comment written by a reader.
+#[utoipa::path(
+    tag = "comment",
+    responses(
+        (status = 200, description = "Liked comment", content(("application/protobuf" = inline(pb::Comment)), ("application/json" = inline(pb::Comment)))),
+        (status = 400, description = "Imported comment"),
+        (status = 401, description = "Not logged in"),
+        (status = 404, description = "Comment not found"),
+    ),
+    security(("cookie" = [])),
+)]
+#[post("/api/comment/{id}/like")]
+async fn like_comment(
+    repo: web::Data<AsyncRepo>,
+    id: web::Path<String>,
+    logged_user: LoggedUser,
+    accept: Accept,
+) -> actix_web::Result<HttpResponse> {
+    set_comment_like(repo, id, logged_user, accept, true).await
+}
+
+/// Takes back a like of the logged in user.
+#[utoipa::path(
+    tag = "comment",
+    responses(
+        (status = 200, description = "Unliked comment", content(("application/protobuf" = inline(pb::Comment)), ("application/json" = inline(pb::Comment)))),
+        (status = 400, description = "Imported comment"),
+        (status = 401, description = "Not logged in"),
+        (status = 404, description = "Comment not found"),
+    ),
+    security(("cookie" = [])),
+)]
+#[delete("/api/comment/{id}/like")]
+async fn unlike_comment(
+    repo: web::Data<AsyncRepo>,
+    id: web::Path<String>,
+    logged_user: LoggedUser,
+    accept: Accept,
+) -> actix_web::Result<HttpResponse> {
+    set_comment_like(repo, id, logged_user, accept, false).await
+}
+
+async fn set_comment_like(
+    repo: web::Data<AsyncRepo>,
+    id: web::Path<String>,
+    logged_user: LoggedUser,
+    accept: Accept,
+    like: bool,
+) -> actix_web::Result<HttpResponse> {
+    if logged_user.id.is_empty() {
+        return Err(AppError::Unauthorized.into());
+    }
+
+    let comment = repo
+        .run(move |repo| repo.like_comment(&logged_user.id, id.as_str(), like))
+        .await
+        .map_err(AppError::from)?;
+
+    accept.reply(HttpResponse::Ok(), pb::Comment::from(comment))
+}
+
+/// Reports a comment to the moderators.
+#[utoipa::path(
+    tag = "comment",
+    request_body(content = inline(pb::FlagCommentRequest), content_type = "application/protobuf"),
+    responses(
+        (status = 200, description = "Comment reported"),
+        (status = 401, description = "Not logged in"),
+        (status = 404, description = "Comment not found"),
+    ),
+    security(("cookie" = [])),
+)]
+#[post("/api/comment/{id}/flag")]
+async fn flag_comment(
+    repo: web::Data<AsyncRepo>,
+    id: web::Path<String>,
+    logged_user: LoggedUser,
+    req: ProtoBuf<pb::FlagCommentRequest>,
+) -> actix_web::Result<HttpResponse> {
+    if logged_user.id.is_empty() {
+        return Err(AppError::Unauthorized.into());
+    }
+    let reason = req.reason.trim().chars().take(255).collect::<String>();
+
+    let flagged = repo
+        .run(move |repo| repo.flag_comment(&logged_user.id, id.as_str(), &reason))
+        .await
+        .map_err(AppError::from)?;
+
+    if !flagged {
+        return Err(AppError::NotFound("comment not found".to_owned()).into());
+    }
+    Ok(HttpResponse::Ok().finish())
+}
+
+fn require_admin(logged_user: &LoggedUser) -> Result<(), AppError> {
+    if logged_user.id.is_empty() {
+        return Err(AppError::Unauthorized);
+    }
+    if !logged_user.is_admin() {
+        return Err(AppError::Forbidden);
+    }
+    Ok(())
+}
+
+#[derive(Deserialize, IntoParams)]
+#[into_params(parameter_in = Query)]
+pub struct ModerationQueueQuery {
+    /// Reported comments by default.
+    queue: Option<ModerationQueue>,
+    /// `nextCursor` of the previous page of the same queue.
+    cursor: Option<String>,
+    /// Page size, 20 by default and at most 100.
+    limit: Option<i64>,
+}
+
+/// Comments waiting for a moderator.
+#[utoipa::path(
+    tag = "moderation",
+    params(ModerationQueueQuery),
+    responses(
+        (status = 200, description = "A page of the queue", content(("application/protobuf" = inline(pb::ModerationQueue)), ("application/json" = inline(pb::ModerationQueue)))),
+        (status = 401, description = "Not logged in"),
+        (status = 403, description = "Not an admin"),
+    ),
+    security(("cookie" = [])),
+)]
+#[get("/api/admin/comments")]
+async fn get_moderation_queue(
+    repo: web::Data<AsyncRepo>,
+    query: web::Query<ModerationQueueQuery>,
+    logged_user: LoggedUser,
+    accept: Accept,
+) -> actix_web::Result<HttpResponse> {
+    require_admin(&logged_user)?;
+    let cursor = query
+        .cursor
+        .as_deref()
+        .filter(|c| !c.is_empty())
+        .map(Cursor::parse)
+        .transpose()?;
+
+    let page = repo
+        .run(move |repo| {
+            repo.moderation_queue(
+                query.queue.unwrap_or_default(),
+                cursor.as_ref(),
+                query.limit.unwrap_or(comment::DEFAULT_LIMIT),
+            )
+        })
+        .await
+        .map_err(AppError::from)?;
+
+    accept.reply(HttpResponse::Ok(), pb::ModerationQueue::from(page))
+}
+
+/// Hides, restores, pins, unpins or deletes a comment, or dismisses its
+/// reports. Recorded in the moderation log.
+#[utoipa::path(
+    tag = "moderation",
+    request_body(content = inline(pb::ModerateCommentRequest), content_type = "application/protobuf"),
+    responses(
+        (status = 200, description = "Action applied"),
+        (status = 400, description = "Action not applicable to the comment"),
+        (status = 401, description = "Not logged in"),
+        (status = 403, description = "Not an admin"),
+        (status = 404, description = "Comment not found"),
+    ),
+    security(("cookie" = [])),
+)]
+#[post("/api/admin/comment/{id}/moderate")]
+async fn moderate_comment(
+    repo: web::Data<AsyncRepo>,
+    id: web::Path<String>,
+    logged_user: LoggedUser,
+    req: ProtoBuf<pb::ModerateCommentRequest>,
+) -> actix_web::Result<HttpResponse> {
+    require_admin(&logged_user)?;
+    let action = req.action().into();
+    let reason = req.reason.trim().chars().take(255).collect::<String>();
+
+    let found = repo
+        .run(move |repo| repo.moderate_comment(&logged_user.id, id.as_str(), action, &reason))
+        .await
+        .map_err(AppError::from)?;
+
+    if !found {
+        return Err(AppError::NotFound("comment not found".to_owned()).into());
+    }
+    Ok(HttpResponse::Ok().finish())
+}
+
+#[derive(Deserialize, IntoParams)]
+#[into_params(parameter_in = Query)]
+pub struct ModerationLogQuery {
+    /// Only entries with a lower id, for the next page.
+    before: Option<i32>,
+    /// Page size, 20 by default and at most 100.
+    limit: Option<i64>,
 }
 
+/// Moderation actions, latest first.
+#[utoipa::path(
+    tag = "moderation",
+    params(ModerationLogQuery),
+    responses(
+        (status = 200, description = "Log entries", content(("application/protobuf" = inline(pb::ModerationLog)), ("application/json" = inline(pb::ModerationLog)))),
+        (status = 401, description = "Not logged in"),
+        (status = 403, description = "Not an admin"),
+    ),
+    security(("cookie" = [])),
+)]
+#[get("/api/admin/moderation_log")]
+async fn get_moderation_log(
+    repo: web::Data<AsyncRepo>,
+    query: web::Query<ModerationLogQuery>,
+    logged_user: LoggedUser,
+    accept: Accept,
+) -> actix_web::Result<HttpResponse> {
+    require_admin(&logged_user)?;
+
+    let entries = repo
+        .run(move |repo| {
+            repo.moderation_log(query.before, query.limit.unwrap_or(comment::DEFAULT_LIMIT))
+        })
+        .await
+        .map_err(AppError::from)?;
+
+    accept.reply(
+        HttpResponse::Ok(),
+        pb::ModerationLog {
+            entries: entries.into_iter().map(|e| e.into()).collect(),
+        },
+    )
+}
+
+/// Same check as the websocket, which bookmarks can also be managed over.
+fn require_reader(logged_user: &LoggedUser) -> Result<(), AppError> {
+    if logged_user.id.is_empty() {
+        return Err(AppError::Unauthorized);
+    }
+    if !logged_user.is_reader() {
+        return Err(AppError::Forbidden);
+    }
+    Ok(())
+}
+
+/// Bookmarks of the logged in user in an article.
+#[utoipa::path(
+    tag = "bookmark",
+    responses(
+        (status = 200, description = "Bookmarks", content(("application/protobuf" = inline(pb::BookmarkList)), ("application/json" = inline(pb::BookmarkList)))),
+        (status = 401, description = "Not logged in"),
+    ),
+    security(("cookie" = [])),
+)]
+#[get("/api/article/{article_id}/bookmarks")]
+async fn list_bookmarks(
+    repo: web::Data<AsyncRepo>,
+    article_id: web::Path<String>,
+    logged_user: LoggedUser,
+    accept: Accept,
+) -> actix_web::Result<HttpResponse> {
+    require_reader(&logged_user)?;
+
+    let bookmarks = repo
+        .run(move |repo| repo.find_bookmarks(&logged_user.id, article_id.as_str()))
+        .await
+        .map_err(AppError::from)?;
+
+    accept.reply(
+        HttpResponse::Ok(),
+        pb::BookmarkList {
+            bookmarks: bookmarks.into_iter().map(|b| b.into()).collect(),
+        },
+    )
+}
+
+/// Creates a bookmark in an article.
+#[utoipa::path(
+    tag = "bookmark",
+    request_body(content = inline(pb::SaveBookmarkRequest), content_type = "application/protobuf"),
+    responses(
+        (status = 200, description = "Saved bookmark", content(("application/protobuf" = inline(pb::Bookmark)), ("application/json" = inline(pb::Bookmark)))),
+        (status = 400, description = "No anchor, or an anchor or label too long"),
+        (status = 401, description = "Not logged in"),
+        (status = 403, description = "Not a reader"),
+    ),
+    security(("cookie" = [])),
+)]
+#[post("/api/bookmark")]
+async fn save_bookmark(
+    repo: web::Data<AsyncRepo>,
+    logged_user: LoggedUser,
+    req: ProtoBuf<pb::SaveBookmarkRequest>,
+    accept: Accept,
+) -> actix_web::Result<HttpResponse> {
+    require_reader(&logged_user)?;
+    if req.article_id.is_empty() || req.anchor.is_empty() {
+        return Err(AppError::BadRequest("article id and anchor are required".to_owned()).into());
+    }
+
+    let bookmark = models::NewArticleBookmark {
+        user_id: logged_user.id,
+        article_id: req.article_id.to_owned(),
+        anchor: req.anchor.to_owned(),
+        label: req.label.to_owned(),
+        created_at: chrono::Utc::now().timestamp(),
+    };
+    let saved = repo
+        .run(move |repo| repo.save_bookmark(&bookmark))
+        .await
+        .map_err(AppError::from)?;
+
+    accept.reply(HttpResponse::Ok(), pb::Bookmark::from(saved))
+}
+
+/// Deletes a bookmark of the logged in user.
+#[utoipa::path(
+    tag = "bookmark",
+    responses(
+        (status = 200, description = "Bookmark deleted"),
+        (status = 401, description = "Not logged in"),
+        (status = 403, description = "Not a reader"),
+        (status = 404, description = "Bookmark not found"),
+    ),
+    security(("cookie" = [])),
+)]
+#[delete("/api/bookmark/{id}")]
+async fn delete_bookmark(
+    repo: web::Data<AsyncRepo>,
+    id: web::Path<i32>,
+    logged_user: LoggedUser,
+) -> actix_web::Result<HttpResponse> {
+    require_reader(&logged_user)?;
+
+    let deleted = repo
+        .run(move |repo| repo.delete_bookmark(&logged_user.id, id.into_inner()))
+        .await
+        .map_err(AppError::from)?;
+
+    if !deleted {
+        return Err(AppError::NotFound("bookmark not found".to_owned()).into());
+    }
+    Ok(HttpResponse::Ok().finish())
+}
+
+#[derive(Deserialize, IntoParams)]
+#[into_params(parameter_in = Query)]
+pub struct NotificationQuery {
+    /// Only notifications with a lower id, for the next page.
+    before: Option<i32>,
+    /// Page size, 20 by default and at most 100.
+    limit: Option<i64>,
+    /// Leave out the read ones.
+    #[serde(default)]
+    unread: bool,
+}
+
+/// Notifications of the logged in user, latest first. They're also pushed
+/// over the websocket as they happen, to clients connected with `?compress=`
+/// (`none` included), in binary frames whose flag byte has `0x02` set.
+#[utoipa::path(
+    tag = "notification",
+    params(NotificationQuery),
+    responses(
+        (status = 200, description = "Notifications", content(("application/protobuf" = inline(pb::NotificationList)), ("application/json" = inline(pb::NotificationList)))),
+        (status = 401, description = "Not logged in"),
+    ),
+    security(("cookie" = [])),
+)]
+#[get("/api/notifications")]
+async fn list_notifications(
+    repo: web::Data<AsyncRepo>,
+    query: web::Query<NotificationQuery>,
+    logged_user: LoggedUser,
+    accept: Accept,
+) -> actix_web::Result<HttpResponse> {
+    if logged_user.id.is_empty() {
+        return Err(AppError::Unauthorized.into());
+    }
+
+    let (notifications, unread) = repo
+        .run(move |repo| {
+            repo.find_notifications(
+                &logged_user.id,
+                query.before,
+                query.limit.unwrap_or(notification::DEFAULT_LIMIT),
+                query.unread,
+            )
+        })
+        .await
+        .map_err(AppError::from)?;
+
+    accept.reply(
+        HttpResponse::Ok(),
+        pb::NotificationList {
+            notifications: notifications.into_iter().map(|n| n.into()).collect(),
+            unread_count: unread as u32,
+        },
+    )
+}
+
+/// Marks notifications of the logged in user as read.
+#[utoipa::path(
+    tag = "notification",
+    request_body(content = inline(pb::ReadNotificationsRequest), content_type = "application/protobuf"),
+    responses(
+        (status = 200, description = "Marked as read"),
+        (status = 401, description = "Not logged in"),
+    ),
+    security(("cookie" = [])),
+)]
+#[post("/api/notifications/read")]
+async fn read_notifications(
+    repo: web::Data<AsyncRepo>,
+    logged_user: LoggedUser,
+    req: ProtoBuf<pb::ReadNotificationsRequest>,
+) -> actix_web::Result<HttpResponse> {
+    if logged_user.id.is_empty() {
+        return Err(AppError::Unauthorized.into());
+    }
+    let ids = req.0.ids;
+
+    repo.run(move |repo| repo.read_notifications(&logged_user.id, &ids))
+        .await
+        .map_err(AppError::from)?;
+
+    Ok(HttpResponse::Ok().finish())
+}
+
+/// Image downloaded from the crawled articles.
+#[utoipa::path(
+    tag = "article",
+    responses(
+        (status = 200, description = "Image file", content_type = "image/*"),
+        (status = 304, description = "Cached copy is still valid"),
+        (status = 404, description = "Image not found"),
+    ),
+)]
+#[get("/api/images/{name}")]
+async fn get_image(
+    repo: web::Data<AsyncRepo>,
+    name: web::Path<String>,
+    req: HttpRequest,
+) -> actix_web::Result<HttpResponse> {
+    let (file, mime) = repo
+        .run(move |repo| {
+            let file = repo.find_local_image(&format!("{LOCAL_IMAGE_PREFIX}{name}"));
+            Ok(file.map(|file| {
+                let mime = sniff_image_type(&file);
+                (file, mime)
+            }))
+        })
+        .await
+        .map_err(AppError::from)?
+        .ok_or_else(|| AppError::NotFound("image not found".to_owned()))?;
+
+    let mut named = NamedFile::open_async(&file).await?;
+    if let Some(mime) = mime {
+        named = named.set_content_type(mime);
+    }
+    // already compressed, not worth another pass by the middleware
+    let mut res = named
+        .set_content_encoding(header::ContentEncoding::Identity)
+        .into_response(&req);
+    res.headers_mut().insert(
+        header::CACHE_CONTROL,
+        header::HeaderValue::from_static("public, max-age=604800"),
+    );
+    Ok(res)
+}
+
+/// Detects the image type from the file header, downloaded files may carry a
+/// wrong or no extension.
+fn sniff_image_type(path: &std::path::Path) -> Option<mime::Mime> {
+    let mut head = [0u8; 12];
+    let n = std::fs::File::open(path).ok()?.read(&mut head).ok()?;
+    let head = &head[..n];
+    let mime = if head.starts_with(b"\xff\xd8\xff") {
+        mime::IMAGE_JPEG
+    } else if head.starts_with(b"\x89PNG\r\n\x1a\n") {
+        mime::IMAGE_PNG
+    } else if head.starts_with(b"GIF8") {
+        mime::IMAGE_GIF
+    } else if head.starts_with(b"BM") {
+        mime::IMAGE_BMP
+    } else if head.starts_with(b"RIFF") && head.get(8..12) == Some(b"WEBP") {
+        "image/webp".parse().ok()?
+    } else {
+        return None;
+    };
+    Some(mime)
+}
+
+#[get("/api/openapi.json")]
+async fn get_openapi() -> HttpResponse {
+    HttpResponse::Ok().json(openapi::ApiDoc::openapi())
+}
+
+/// Cache hit and miss counters in the Prometheus text format.
+#[utoipa::path(
+    tag = "metrics",
+    responses(
+        (status = 200, description = "Counters of every cache", content_type = "text/plain; version=0.0.4", body = String),
+    ),
+)]
+#[get("/api/metrics")]
+async fn get_metrics(repo: web::Data<AsyncRepo>) -> actix_web::Result<HttpResponse> {
+    let metrics = repo
+        .run(|repo| Ok(repo.cache_metrics()))
+        .await
+        .map_err(AppError::from)?;
+    Ok(HttpResponse::Ok()
+        .content_type("text/plain; version=0.0.4")
+        .body(metrics))
+}
+
+#[derive(Deserialize, IntoParams)]
+#[into_params(parameter_in = Query)]
+pub struct WsQuery {
+    /// Compression of the binary frames, see `FrameCompression`. Given, even
+    /// as `none`, frames start with a flag byte and notifications are pushed.
+    compress: Option<ws_session::FrameCompression>,
+}
+
+/// Websocket of the reader, see `WsSession` for the commands.
+#[utoipa::path(
+    tag = "session",
+    params(WsQuery),
+    responses(
+        (status = 101, description = "Switching to the websocket protocol"),
+        (status = 403, description = "Not a reader"),
+    ),
+    security(("cookie" = [])),
+)]
 #[get("/api/ws")]
 async fn ws_start(
     req: HttpRequest,
     stream: web::Payload,
     srv: web::Data<Addr<ws_server::WsServer>>,
+    query: web::Query<WsQuery>,
     logged_user: LoggedUser,
 ) -> actix_web::Result<HttpResponse> {
-    if let UserRole::Reader = logged_user.role {
+    if logged_user.is_reader() {
         debug!("WS connection established");
         ws::start(
             ws_session::WsSession {
@@ -130,12 +1241,13 @@ async fn ws_start(
                 addr: srv.get_ref().clone(),
                 user_id: logged_user.id,
                 start_at: chrono::Utc::now(),
+                compression: query.compress,
             },
             &req,
             stream,
         )
     } else {
-        Err(actix_web::error::ErrorForbidden("Forbidden"))
+        Err(AppError::Forbidden.into())
     }
 }
 
@@ -145,16 +1257,30 @@ pub struct LoggedUser {
     pub role: UserRole,
 }
 
+impl LoggedUser {
+    /// Readers and admins, the roles allowed to keep per-user state.
+    pub fn is_reader(&self) -> bool {
+        matches!(self.role, UserRole::Reader | UserRole::Admin)
+    }
+
+    pub fn is_admin(&self) -> bool {
+        matches!(self.role, UserRole::Admin)
+    }
+}
+
 #[derive(Debug, Serialize, Deserialize)]
 pub enum UserRole {
     Visitor = 0,
     Reader = 1,
+    /// A reader who moderates comments.
+    Admin = 2,
 }
 
 impl From<u32> for UserRole {
     fn from(v: u32) -> Self {
         match v {
             x if x == UserRole::Reader as u32 => UserRole::Reader,
+            x if x == UserRole::Admin as u32 => UserRole::Admin,
             _ => UserRole::Visitor,
         }
     }
@@ -162,37 +1288,124 @@ impl From<u32> for UserRole {
 
 impl FromRequest for LoggedUser {
     type Error = actix_web::Error;
-    type Future = Ready<Result<LoggedUser, actix_web::Error>>;
+    type Future = Pin<Box<dyn Future<Output = Result<LoggedUser, actix_web::Error>>>>;
 
     fn from_request(req: &HttpRequest, pl: &mut Payload) -> Self::Future {
         if let Ok(identity) = Identity::from_request(req, pl).into_inner() {
             if let Some(user_json) = identity.identity() {
                 if let Ok(mut user) = serde_json::from_str::<LoggedUser>(&user_json) {
-                    if let Some(repo) = req.app_data::<web::Data<Repo>>() {
-                        user.role = get_user_role(repo.get_ref(), user.id.as_str());
-                    }
-                    return ready(Ok(user));
+                    let repo = req.app_data::<web::Data<AsyncRepo>>().cloned();
+                    return Box::pin(async move {
+                        if let Some(repo) = repo {
+                            user.role = user_role(&repo, &user.id).await;
+                        }
+                        Ok(user)
+                    });
                 }
             }
         }
-        ready(Ok(LoggedUser {
+        Box::pin(ready(Ok(LoggedUser {
             id: String::new(),
             role: UserRole::Visitor,
-        }))
+        })))
         // ready(Err(errors::ServiceError::Unauthorized.into()))
     }
 }
 
-#[derive(Debug, Deserialize)]
+/// Wire encoding of `pb` messages in responses.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Encoding {
+    Protobuf,
+    Json,
+}
+
+impl Encoding {
+    pub fn name(&self) -> &'static str {
+        match self {
+            Encoding::Protobuf => "pb",
+            Encoding::Json => "json",
+        }
+    }
+
+    /// Sends `msg` in this encoding, marking the response as negotiated.
+    pub fn reply<T>(&self, mut res: HttpResponseBuilder, msg: T) -> actix_web::Result<HttpResponse>
+    where
+        T: prost::Message + Default + Serialize,
+    {
+        res.append_header((header::VARY, "Accept"));
+        match self {
+            Encoding::Protobuf => res.protobuf(msg),
+            Encoding::Json => Ok(res.json(msg)),
+        }
+    }
+}
+
+/// Encoding preferred by the `Accept` header, `None` when the client accepts
+/// either one. Endpoints default to protobuf.
+pub struct Accept(Option<Encoding>);
+
+impl Accept {
+    pub fn encoding(&self) -> Encoding {
+        self.0.unwrap_or(Encoding::Protobuf)
+    }
+
+    pub fn or(&self, default: Encoding) -> Encoding {
+        self.0.unwrap_or(default)
+    }
+
+    pub fn reply<T>(&self, res: HttpResponseBuilder, msg: T) -> actix_web::Result<HttpResponse>
+    where
+        T: prost::Message + Default + Serialize,
+    {
+        self.encoding().reply(res, msg)
+    }
+}
+
+impl Accept {
+    pub fn from_headers(req: &HttpRequest) -> Self {
+        let encoding = req
+            .get_header::<header::Accept>()
+            .map(|accept| accept.ranked())
+            .unwrap_or_default()
+            .iter()
+            .find_map(|mime| match (mime.type_(), mime.subtype().as_str()) {
+                (mime::APPLICATION, "json") => Some(Encoding::Json),
+                (mime::APPLICATION, "protobuf" | "x-protobuf") => Some(Encoding::Protobuf),
+                _ => None,
+            });
+        Accept(encoding)
+    }
+}
+
+impl FromRequest for Accept {
+    type Error = actix_web::Error;
+    type Future = Ready<Result<Accept, actix_web::Error>>;
+
+    fn from_request(req: &HttpRequest, _: &mut Payload) -> Self::Future {
+        ready(Ok(Accept::from_headers(req)))
+    }
+}
+
+#[derive(Debug, Deserialize, IntoParams)]
+#[into_params(parameter_in = Query)]
 pub struct LoginQuery {
+    /// Where to redirect once logged in.
     return_to: String,
 }
 
+/// Logs in and redirects back to `return_to`.
+#[utoipa::path(
+    tag = "session",
+    params(LoginQuery),
+    responses(
+        (status = 307, description = "Logged in, redirecting to return_to"),
+    ),
+)]
 #[get("/api/login")]
 pub async fn login(
     // req: HttpRequest,
     id: Identity,
-    repo: web::Data<Repo>,
+    repo: web::Data<AsyncRepo>,
     query: web::Query<LoginQuery>,
 ) -> actix_web::Result<HttpResponse> {
     let success = HttpResponse::TemporaryRedirect()
@@ -200,7 +1413,7 @@ pub async fn login(
         .finish();
     let uid = String::from("0698edd5-1ea8-4493-9092-003c4230516a");
     let user = LoggedUser {
-        role: get_user_role(repo.as_ref(), uid.as_ref()),
+        role: user_role(&repo, &uid).await,
         id: uid,
     };
     id.remember(serde_json::to_string(&user).unwrap());
@@ -245,28 +1458,42 @@ pub async fn login(
     // }
 }
 
+/// The logged in user and their role.
+#[utoipa::path(
+    tag = "session",
+    responses(
+        (status = 200, description = "User info", content(("application/protobuf" = inline(pb::UserInfo)), ("application/json" = inline(pb::UserInfo)))),
+        (status = 401, description = "Not logged in"),
+    ),
+    security(("cookie" = [])),
+)]
 #[get("/api/me")]
 pub async fn get_me(
     mut logged_user: LoggedUser,
     id: Identity,
-    repo: web::Data<Repo>,
+    repo: web::Data<AsyncRepo>,
+    accept: Accept,
 ) -> actix_web::Result<HttpResponse> {
     if logged_user.id.is_empty() {
-        return Ok(HttpResponse::Unauthorized().finish());
+        return Err(AppError::Unauthorized.into());
     }
 
-    logged_user.role = get_user_role(&repo, logged_user.id.as_str());
+    logged_user.role = user_role(&repo, &logged_user.id).await;
     id.remember(serde_json::to_string(&logged_user).unwrap());
     let u: pb::UserInfo = logged_user.into();
-    HttpResponse::Ok().protobuf(u)
+    accept.reply(HttpResponse::Ok(), u)
+}
+
+pub async fn user_role(repo: &AsyncRepo, user_id: &str) -> UserRole {
+    let user_id = user_id.to_owned();
+    repo.run(move |repo| Ok(get_user_role(repo, &user_id)))
+        .await
+        .unwrap_or(UserRole::Visitor)
 }
 
 pub fn get_user_role(repo: &Repo, user_id: &str) -> UserRole {
     match repo.find_user_role(user_id) {
-        Ok(user_role) => match user_role.role {
-            1 => UserRole::Reader,
-            _ => UserRole::Visitor,
-        },
+        Ok(user_role) => UserRole::from(user_role.role as u32),
         Err(e) => {
             error!("get_user_role, {:?}", e);
             UserRole::Visitor
@@ -274,14 +1501,24 @@ pub fn get_user_role(repo: &Repo, user_id: &str) -> UserRole {
     }
 }
 
+/// Saves the reading progress of an article.
+#[utoipa::path(
+    tag = "session",
+    request_body(content = inline(pb::SaveStudyInfoRequest), content_type = "application/protobuf"),
+    responses(
+        (status = 200, description = "Progress saved"),
+        (status = 401, description = "Not logged in"),
+    ),
+    security(("cookie" = [])),
+)]
 #[post("/api/study_info")]
 pub async fn save_study_info(
     logged_user: LoggedUser,
-    repo: web::Data<Repo>,
+    repo: web::Data<AsyncRepo>,
     req: ProtoBuf<pb::SaveStudyInfoRequest>,
 ) -> actix_web::Result<HttpResponse> {
     if logged_user.id.is_empty() {
-        return Ok(HttpResponse::Unauthorized().finish());
+        return Err(AppError::Unauthorized.into());
     }
 
     let info = models::UserStudyInfo {
@@ -292,45 +1529,72 @@ pub async fn save_study_info(
         last_study_at: chrono::Utc::now().timestamp(),
         study_percent: req.percent,
     };
-    repo.save_study_info(&info)
-        .map_err(actix_web::error::ErrorInternalServerError)?;
+    repo.run(move |repo| repo.save_study_info(&info))
+        .await
+        .map_err(AppError::from)?;
 
     Ok(HttpResponse::Ok().finish())
 }
 
-#[derive(Debug, Deserialize)]
+#[derive(Debug, Deserialize, IntoParams)]
+#[into_params(parameter_in = Query)]
 pub struct GetConnectSecQuery {
+    /// Unix timestamp, latest connection start included.
     start_at_lt: i64,
+    /// Unix timestamp, earliest connection start included.
     start_at_gt: i64,
 }
 
+/// Seconds the logged in user spent connected in a time range.
+#[utoipa::path(
+    tag = "session",
+    params(GetConnectSecQuery),
+    responses(
+        (status = 200, description = "Connected seconds, a bare JSON number unless protobuf is asked for",
+            content(("application/json" = i64), ("application/protobuf" = inline(pb::ConnectSeconds)))),
+        (status = 401, description = "Not logged in"),
+    ),
+    security(("cookie" = [])),
+)]
 #[get("/api/connect_seconds")]
 pub async fn get_connect_seconds(
     logged_user: LoggedUser,
-    repo: web::Data<Repo>,
+    repo: web::Data<AsyncRepo>,
     query: web::Query<GetConnectSecQuery>,
+    accept: Accept,
 ) -> actix_web::Result<HttpResponse> {
     if logged_user.id.is_empty() {
-        return Ok(HttpResponse::Unauthorized().finish());
+        return Err(AppError::Unauthorized.into());
     }
 
     let secs = repo
-        .get_connect_seconds(
-            logged_user.id.as_str(),
-            query.start_at_gt,
-            query.start_at_lt,
-        )
-        .map_err(actix_web::error::ErrorInternalServerError)?;
+        .run(move |repo| {
+            repo.get_connect_seconds(
+                logged_user.id.as_str(),
+                query.start_at_gt,
+                query.start_at_lt,
+            )
+        })
+        .await
+        .map_err(AppError::from)?;
 
-    Ok(HttpResponse::Ok().json(secs))
+    // existing clients expect a bare number, only protobuf gets the message
+    match accept.or(Encoding::Json) {
+        Encoding::Json => Ok(HttpResponse::Ok()
+            .append_header((header::VARY, "Accept"))
+            .json(secs)),
+        Encoding::Protobuf => {
+            Encoding::Protobuf.reply(HttpResponse::Ok(), pb::ConnectSeconds { seconds: secs })
+        }
+    }
 }
 
 #[get("/api/test")]
 pub async fn test(
     _logged_user: LoggedUser,
-    repo: web::Data<Repo>,
+    repo: web::Data<AsyncRepo>,
 ) -> actix_web::Result<HttpResponse> {
-    if let Err(e) = repo.test() {
+    if let Err(e) = repo.run(|repo| repo.test()).await {
         println!("{:?}", e);
     }
 
@@ -0,0 +1,198 @@
+use {
+    ego_tree::NodeRef,
+    scraper::{Html, Node},
+};
+
+/// Elements which never have children, serialized as `<br/>` in XHTML.
+const VOID_ELEMENTS: &[&str] = &[
+    "area", "base", "br", "col", "embed", "hr", "img", "input", "link", "meta", "source", "track",
+    "wbr",
+];
+
+/// Elements dropped together with their children when serializing to XHTML.
+const SKIPPED_ELEMENTS: &[&str] = &["script", "style", "iframe", "noscript", "object"];
+
+/// Serializes a crawled HTML fragment as well-formed XHTML.
+///
+/// Every `<img src>` is passed to `map_img`, which returns the new src or
+/// `None` to drop the image. Code blocks are normalized to
+/// `<pre><code class="language-x">` holding plain text.
+pub fn to_xhtml(html: &str, mut map_img: impl FnMut(&str) -> Option<String>) -> String {
+    let doc = Html::parse_fragment(html);
+    let mut out = String::with_capacity(html.len());
+    for child in doc.root_element().children() {
+        write_xhtml(child, &mut out, &mut map_img);
+    }
+    out
+}
+
+fn write_xhtml(
+    node: NodeRef<Node>,
+    out: &mut String,
+    map_img: &mut impl FnMut(&str) -> Option<String>,
+) {
+    match node.value() {
+        Node::Text(text) => out.push_str(&escape(text)),
+        Node::Element(el) => {
+            let name = el.name();
+            if SKIPPED_ELEMENTS.contains(&name) {
+                return;
+            }
+            if name == "pre" {
+                write_code_block(node, out);
+                return;
+            }
+            // markup pasted from Word such as `<o:p>` uses undeclared
+            // namespace prefixes, only its content is kept
+            if !is_xml_name(name) {
+                for child in node.children() {
+                    write_xhtml(child, out, map_img);
+                }
+                return;
+            }
+
+            let mut attrs = el
+                .attrs()
+                .filter(|(k, _)| is_xml_name(k) && !k.starts_with("on"))
+                .map(|(k, v)| (k.to_owned(), v.to_owned()))
+                .collect::<Vec<_>>();
+            // the parser doesn't keep the source order, sorting keeps the
+            // output stable for caches and ETags
+            attrs.sort();
+            if name == "img" {
+                let src = attrs.iter_mut().find(|(k, _)| k == "src");
+                match src.and_then(|(_, v)| map_img(v).map(|new_src| (v, new_src))) {
+                    Some((v, new_src)) => *v = new_src,
+                    None => return,
+                }
+                if !attrs.iter().any(|(k, _)| k == "alt") {
+                    attrs.push(("alt".to_owned(), String::new()));
+                }
+            }
+
+            out.push('<');
+            out.push_str(name);
+            for (k, v) in &attrs {
+                out.push_str(&format!(" {}=\"{}\"", k, escape_attr(v)));
+            }
+            if VOID_ELEMENTS.contains(&name) {
+                out.push_str("/>");
+                return;
+            }
+            out.push('>');
+            for child in node.children() {
+                write_xhtml(child, out, map_img);
+            }
+            out.push_str(&format!("</{name}>"));
+        }
+        _ => (),
+    }
+}
+
+/// Highlighters wrap tokens in spans and some editors put every line in its
+/// own element, flatten all of it to the plain source text.
+fn write_code_block(pre: NodeRef<Node>, out: &mut String) {
+    let mut code = String::new();
+    code_text(pre, &mut code);
+
+    out.push_str("<pre><code");
+    if let Some(lang) = code_language(pre) {
+        out.push_str(&format!(" class=\"language-{}\"", escape_attr(&lang)));
+    }
+    out.push('>');
+    out.push_str(&escape(code.trim_end_matches('\n')));
+    out.push_str("</code></pre>");
+}
+
+pub fn code_text(node: NodeRef<Node>, code: &mut String) {
+    for child in node.children() {
+        match child.value() {
+            Node::Text(text) => code.push_str(text),
+            Node::Element(el) => match el.name() {
+                "br" => code.push('\n'),
+                "div" | "p" | "li" => {
+                    code_text(child, code);
+                    if !code.ends_with('\n') {
+                        code.push('\n');
+                    }
+                }
+                _ => code_text(child, code),
+            },
+            _ => (),
+        }
+    }
+}
+
+/// Language of a code block from `language-x`/`lang-x` classes or
+/// `data-lang`-like attributes on the `<pre>` or its `<code>`.
+pub fn code_language(pre: NodeRef<Node>) -> Option<String> {
+    pre.descendants()
+        .filter_map(|n| n.value().as_element())
+        .filter(|el| matches!(el.name(), "pre" | "code"))
+        .find_map(|el| {
+            let from_class = el.classes().find_map(|c| {
+                c.strip_prefix("language-")
+                    .or_else(|| c.strip_prefix("lang-"))
+                    .map(str::to_owned)
+            });
+            from_class.or_else(|| {
+                ["data-lang", "data-language", "lang"]
+                    .iter()
+                    .find_map(|a| el.attr(a).map(str::to_owned))
+            })
+        })
+        .map(|lang| lang.trim().to_ascii_lowercase())
+        .filter(|lang| {
+            !lang.is_empty()
+                && lang
+                    .chars()
+                    .all(|c| c.is_ascii_alphanumeric() || "+#-_".contains(c))
+        })
+}
+
+fn is_xml_name(name: &str) -> bool {
+    let mut chars = name.chars();
+    matches!(chars.next(), Some(c) if c.is_ascii_alphabetic() || c == '_')
+        && chars.all(|c| c.is_ascii_alphanumeric() || matches!(c, '-' | '_' | '.'))
+}
+
+/// Escapes text content for HTML and XML output.
+pub fn escape(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+}
+
+fn escape_attr(value: &str) -> String {
+    escape(value).replace('"', "&quot;")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn unwraps_elements_with_invalid_names() {
+        let xhtml = to_xhtml("<p>a<o:p>b</o:p><span 1x=\"y\">c</span></p>", |_| None);
+        assert_eq!(xhtml, "<p>ab<span>c</span></p>");
+    }
+
+    #[test]
+    fn drops_images_without_a_new_src() {
+        let xhtml = to_xhtml(r#"<p><img src="a.png"><img src="b.png"></p>"#, |src| {
+            (src == "a.png").then(|| "x.png".to_owned())
+        });
+        assert_eq!(xhtml, r#"<p><img src="x.png" alt=""/></p>"#);
+    }
+
+    #[test]
+    fn maps_img_src_with_gt_in_attributes() {
+        let xhtml = to_xhtml(r#"<p><img alt="a > b" src="a.png"> c</p>"#, |src| {
+            Some(format!("/local/{src}"))
+        });
+        assert_eq!(
+            xhtml,
+            r#"<p><img alt="a &gt; b" src="/local/a.png"/> c</p>"#
+        );
+    }
+}
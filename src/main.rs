@@ -2,45 +2,129 @@ use {
     actix::Actor,
     actix_identity::{CookieIdentityPolicy, IdentityService},
     actix_web::{
+        dev::Service as _,
         middleware,
         web::{Data, JsonConfig},
         App, HttpServer,
     },
+    anyhow::{anyhow, Context},
+    config::Config,
     dotenvy::dotenv,
     log::*,
-    std::{env, sync::Arc},
+    std::env,
     time::Duration,
 };
 
+mod cache;
+mod comment;
+mod config;
+mod error;
+mod export;
+mod format;
+mod grpc;
 mod handlers;
+mod html;
+mod migrations;
 mod models;
+mod notification;
+mod openapi;
 mod pb;
 mod repo;
+mod sanitize;
 mod schema;
+mod storage;
+#[cfg(test)]
+mod testing;
 mod ws_server;
 mod ws_session;
 
 #[actix_web::main]
-async fn main() -> std::io::Result<()> {
+async fn main() -> anyhow::Result<()> {
     dotenv().ok();
     env_logger::init_from_env(env_logger::Env::new().default_filter_or("info"));
 
-    let repo = Arc::new(repo::Repo::new());
+    let config = Config::from_env()?;
+    let args = env::args().collect::<Vec<_>>();
+    match args.get(1).map(String::as_str) {
+        Some("migrate") => return migrations::run(&config),
+        Some("pack") => {
+            let out = args.get(2).map_or_else(
+                || config.storage_path.join("content.pack"),
+                std::path::PathBuf::from,
+            );
+            let n = storage::pack::write(&config.storage_path, &out)?;
+            info!("packed {n} files into {}", out.display());
+            return Ok(());
+        }
+        Some("compress-contents") => {
+            let with_dictionary = args.get(2).is_some_and(|arg| arg == "--dict");
+            return storage::local::compress_contents(&config.storage_path, with_dictionary);
+        }
+        Some("grpc-token") => {
+            let grpc = config
+                .grpc
+                .as_ref()
+                .ok_or_else(|| anyhow!("GRPC_PORT and GRPC_TOKEN must be set"))?;
+            let user_id = args
+                .get(2)
+                .ok_or_else(|| anyhow!("usage: grpc-token <user id> [days]"))?;
+            let days = match args.get(3) {
+                Some(days) => days.parse().context("days must be a number")?,
+                None => 30,
+            };
+            let expires_at = chrono::Utc::now().timestamp() + days * 24 * 3600;
+            println!("{}", grpc::user_token(&grpc.token, user_id, expires_at));
+            return Ok(());
+        }
+        Some(command) => return Err(anyhow!("unknown command {command}")),
+        None => {}
+    }
+    if config.auto_migrate {
+        migrations::run_at_startup(&config)?;
+    }
+
+    let repo = repo::Repo::new(&config)?;
+    repo.verify_schema()?;
+    let repo = repo::AsyncRepo::new(repo);
     // start chat server actor
     let server = ws_server::WsServer::new(repo.clone()).start();
 
-    let host = env::var("HOST").unwrap_or("127.0.0.1".to_string());
-    let port: u16 = env::var("PORT")
-        .unwrap_or("8080".to_string())
-        .parse()
-        .unwrap();
+    let Config {
+        host,
+        port,
+        workers,
+        json_limit,
+        grpc,
+        ..
+    } = config;
+
+    if let Some(grpc) = grpc {
+        let addr = format!("{host}:{}", grpc.port)
+            .parse()
+            .context("HOST and GRPC_PORT must form a socket address")?;
+        info!("starting gRPC server at {}", addr);
+        let repo = repo.clone();
+        actix_web::rt::spawn(async move {
+            if let Err(e) = grpc::serve(repo, addr, grpc.token).await {
+                error!("gRPC server stopped: {e}");
+            }
+        });
+    }
+
     info!("starting HTTP server at http://{}:{}", host, port);
     // Start HTTP server
     HttpServer::new(move || {
         App::new()
-            .app_data(Data::from(repo.clone()))
+            .app_data(Data::new(repo.clone()))
             .app_data(Data::new(server.clone()))
-            .app_data(JsonConfig::default().limit(4096))
+            .app_data(JsonConfig::default().limit(json_limit))
+            .wrap_fn(|req, srv| {
+                let request_id = error::request_id(&req);
+                let res = srv.call(req);
+                async move { Ok(error::render(res.await?, request_id)) }
+            })
+            // gzip, brotli or zstd, as negotiated with `Accept-Encoding`
+            .wrap(middleware::Compress::default())
             .wrap(middleware::Logger::default())
             .wrap(IdentityService::new(
                 CookieIdentityPolicy::new("1996".repeat(8).as_bytes())
@@ -51,17 +135,42 @@ async fn main() -> std::io::Result<()> {
                     .secure(false), // this can only be true if you have https
             ))
             .service(handlers::get_course_detail)
+            .service(handlers::get_course_epub)
+            .service(handlers::get_course_pdf)
+            .service(handlers::get_articles_pdf)
             .service(handlers::list_course)
+            .service(handlers::get_article)
+            .service(handlers::get_article_content)
             .service(handlers::get_article_comments)
+            .service(handlers::post_comment)
+            .service(handlers::edit_comment)
+            .service(handlers::delete_comment)
+            .service(handlers::like_comment)
+            .service(handlers::unlike_comment)
+            .service(handlers::flag_comment)
+            .service(handlers::get_moderation_queue)
+            .service(handlers::moderate_comment)
+            .service(handlers::get_moderation_log)
+            .service(handlers::list_bookmarks)
+            .service(handlers::save_bookmark)
+            .service(handlers::delete_bookmark)
+            .service(handlers::list_notifications)
+            .service(handlers::read_notifications)
+            .service(handlers::get_image)
             .service(handlers::ws_start)
             .service(handlers::login)
             .service(handlers::get_me)
             .service(handlers::save_study_info)
             .service(handlers::get_connect_seconds)
+            .service(handlers::get_openapi)
+            .service(handlers::get_metrics)
             .service(handlers::test)
     })
-    .workers(2)
-    .bind((host, port))?
+    .workers(workers)
+    .bind((host.as_str(), port))
+    .with_context(|| format!("failed to bind {host}:{port}"))?
     .run()
-    .await
+    .await?;
+
+    Ok(())
 }
@@ -0,0 +1,157 @@
+use {
+    crate::{config::Config, schema},
+    anyhow::{anyhow, Context, Result},
+    diesel::{
+        connection::SimpleConnection,
+        debug_query,
+        dsl::sql,
+        prelude::*,
+        sql_types::{Bool, Text},
+        sqlite::Sqlite,
+        SqliteConnection,
+    },
+    diesel_migrations::{embed_migrations, EmbeddedMigrations, MigrationHarness},
+    log::*,
+};
+
+/// Content tables, plus the user data when it shares the content database.
+const CONTENT: EmbeddedMigrations = embed_migrations!("migrations");
+/// User data, applied to `USER_DATABASE_URL` when it's set.
+const USER_DATA: EmbeddedMigrations = embed_migrations!("user_migrations");
+/// First of `CONTENT`, whose tables the crawler creates by itself.
+const BASELINE: &str = "20250304132915";
+
+/// Applies the pending migrations of every database the server uses.
+pub fn run(config: &Config) -> Result<()> {
+    // with a separate user database, `database.url` points at it
+    match &config.database.attach_content {
+        Some(_) => {
+            apply(&config.content_url, CONTENT)?;
+            apply(&config.database.url, USER_DATA)
+        }
+        None => apply(&config.database.url, CONTENT),
+    }
+}
+
+/// Applies the pending migrations of the database the server writes to. A
+/// separate content database is opened read-only and replaced by every
+/// crawl, it's only migrated by the `migrate` command.
+pub fn run_at_startup(config: &Config) -> Result<()> {
+    match &config.database.attach_content {
+        Some(_) => apply(&config.database.url, USER_DATA),
+        None => apply(&config.database.url, CONTENT),
+    }
+}
+
+fn apply(url: &str, migrations: EmbeddedMigrations) -> Result<()> {
+    let conn = &mut SqliteConnection::establish(url)
+        .with_context(|| format!("failed to open database {url}"))?;
+    adopt_crawled(conn).with_context(|| format!("failed to migrate {url}"))?;
+    let applied = conn
+        .run_pending_migrations(migrations)
+        .map_err(|e| anyhow!("failed to migrate {url}: {e}"))?;
+    for version in applied {
+        info!("applied migration {version} to {url}");
+    }
+
+    Ok(())
+}
+
+/// Every migration, to a fresh database in the tests of other modules.
+#[cfg(test)]
+pub fn apply_all(url: &str) -> Result<()> {
+    apply(url, CONTENT)
+}
+
+/// A database made by the crawler has the tables of the first migration but
+/// no record of it, running it would fail on the existing indexes.
+fn adopt_crawled(conn: &mut SqliteConnection) -> Result<()> {
+    let table_exists = |conn: &mut SqliteConnection, name: &str| {
+        diesel::select(
+            sql::<Bool>("EXISTS (SELECT 1 FROM sqlite_master WHERE type = 'table' AND name = ")
+                .bind::<Text, _>(name.to_owned())
+                .sql(")"),
+        )
+        .get_result::<bool>(conn)
+    };
+    if table_exists(conn, "__diesel_schema_migrations")? || !table_exists(conn, "course")? {
+        return Ok(());
+    }
+
+    info!("recording migration {BASELINE} as applied to the crawled database");
+    conn.batch_execute(&format!(
+        "CREATE TABLE __diesel_schema_migrations (
+            version VARCHAR(50) PRIMARY KEY NOT NULL,
+            run_on TIMESTAMP NOT NULL DEFAULT CURRENT_TIMESTAMP
+        );
+        INSERT INTO __diesel_schema_migrations (version) VALUES ('{BASELINE}');"
+    ))?;
+    Ok(())
+}
+
+/// Checks every table and column of `schema.rs` can be queried, so a
+/// database the migrations didn't create fails at boot rather than on
+/// the first request touching it.
+pub fn verify_schema(conn: &mut SqliteConnection) -> Result<()> {
+    macro_rules! check {
+        ($($table:ident),*) => {$(
+            let query = schema::$table::table
+                .select(schema::$table::all_columns)
+                .filter(sql::<Bool>("0"));
+            // the debug output ends with the binds in a `--` comment
+            conn.batch_execute(&debug_query::<Sqlite, _>(&query).to_string())
+                .with_context(|| {
+                    format!(
+                        "table {} doesn't match schema.rs, run `hackbook-server migrate`",
+                        stringify!($table)
+                    )
+                })?;
+        )*};
+    }
+
+    check!(
+        article,
+        article_bookmark,
+        article_comment,
+        comment_flag,
+        comment_like,
+        comment_moderation,
+        comment_moderation_log,
+        comment_post,
+        course,
+        course_article_count,
+        course_tend,
+        notification,
+        section,
+        user,
+        user_comment,
+        user_role,
+        user_study_info,
+        ws_connect_info
+    );
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use {super::*, crate::testing::TempDir};
+
+    #[test]
+    fn migrates_a_crawled_database() {
+        let dir = TempDir::new();
+        let url = dir.join("content.db").to_string_lossy().into_owned();
+        let mut conn = SqliteConnection::establish(&url).unwrap();
+        conn.batch_execute(include_str!(
+            "../migrations/2025-03-04-132915_create_all_table/up.sql"
+        ))
+        .unwrap();
+        drop(conn);
+
+        apply(&url, CONTENT).unwrap();
+        let conn = &mut SqliteConnection::establish(&url).unwrap();
+        verify_schema(conn).unwrap();
+        // already recorded, nothing is applied twice
+        apply(&url, CONTENT).unwrap();
+    }
+}
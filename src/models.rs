@@ -1,14 +1,18 @@
 use {
     crate::schema::{
-        article, article_comment, course, section, user_role, user_study_info, ws_connect_info,
+        article, article_bookmark, article_comment, comment_flag, comment_moderation,
+        comment_moderation_log, course, course_article_count, notification, section, user_comment,
+        user_role, user_study_info, ws_connect_info,
     },
     diesel::{
-        prelude::{Associations, Identifiable, Insertable, Queryable, QueryableByName},
-        sql_types::BigInt,
+        prelude::{
+            AsChangeset, Associations, Identifiable, Insertable, Queryable, QueryableByName,
+        },
+        sql_types::{BigInt, Text},
     },
 };
 
-#[derive(Identifiable, Debug, Queryable, Associations)]
+#[derive(Identifiable, Debug, Clone, Queryable, Associations)]
 #[diesel(belongs_to(Section, foreign_key = sectionId))]
 #[diesel(table_name = article)]
 #[diesel(check_for_backend(diesel::sqlite::Sqlite))]
@@ -21,7 +25,7 @@ pub struct Article {
     pub title: String,
 }
 
-#[derive(Identifiable, Debug, Queryable, Associations)]
+#[derive(Identifiable, Debug, Clone, Queryable, Associations)]
 #[diesel(belongs_to(Course, foreign_key = courseId))]
 #[diesel(table_name = section)]
 #[diesel(check_for_backend(diesel::sqlite::Sqlite))]
@@ -32,7 +36,7 @@ pub struct Section {
     pub title: String,
 }
 
-#[derive(Identifiable, Debug, Queryable, QueryableByName)]
+#[derive(Identifiable, Debug, Clone, Queryable, QueryableByName)]
 #[diesel(table_name = course)]
 #[diesel(check_for_backend(diesel::sqlite::Sqlite))]
 pub struct Course {
@@ -65,6 +69,71 @@ pub struct ArticleComment {
     pub article_id: Option<String>,
     #[diesel(column_name = parentCommentId)]
     pub parent_comment_id: Option<String>,
+    #[diesel(column_name = createdAt)]
+    pub created_at: i64,
+}
+
+/// Comment written by a reader, `id` is generated by the server.
+#[derive(Identifiable, Debug, Clone, Queryable, Insertable)]
+#[diesel(table_name = user_comment)]
+#[diesel(check_for_backend(diesel::sqlite::Sqlite))]
+pub struct UserComment {
+    pub id: String,
+    pub article_id: String,
+    pub parent_id: Option<String>,
+    pub user_id: String,
+    pub nick_name: String,
+    pub content: String,
+    pub like_count: i32,
+    pub created_at: i64,
+    pub updated_at: i64,
+    pub deleted: bool,
+}
+
+#[derive(Debug, Insertable)]
+#[diesel(table_name = comment_flag)]
+#[diesel(check_for_backend(diesel::sqlite::Sqlite))]
+pub struct CommentFlag {
+    pub comment_id: String,
+    pub user_id: String,
+    pub reason: String,
+    pub created_at: i64,
+}
+
+#[derive(Identifiable, Debug, Queryable, Insertable, AsChangeset)]
+#[diesel(table_name = comment_moderation)]
+#[diesel(primary_key(comment_id))]
+#[diesel(check_for_backend(diesel::sqlite::Sqlite))]
+pub struct CommentModeration {
+    pub comment_id: String,
+    pub article_id: String,
+    pub hidden: bool,
+    pub pinned_at: i64,
+    pub updated_at: i64,
+    pub updated_by: String,
+}
+
+#[derive(Identifiable, Debug, Queryable)]
+#[diesel(table_name = comment_moderation_log)]
+#[diesel(check_for_backend(diesel::sqlite::Sqlite))]
+pub struct CommentModerationLog {
+    pub id: i32,
+    pub comment_id: String,
+    pub moderator_id: String,
+    pub action: String,
+    pub reason: String,
+    pub created_at: i64,
+}
+
+#[derive(Debug, Insertable)]
+#[diesel(table_name = comment_moderation_log)]
+#[diesel(check_for_backend(diesel::sqlite::Sqlite))]
+pub struct NewCommentModerationLog {
+    pub comment_id: String,
+    pub moderator_id: String,
+    pub action: String,
+    pub reason: String,
+    pub created_at: i64,
 }
 
 #[derive(Identifiable, Debug, Queryable)]
@@ -100,9 +169,87 @@ pub struct WsConnectInfo {
     pub end_at: i64,
 }
 
+#[derive(Identifiable, Debug, Queryable)]
+#[diesel(table_name = article_bookmark)]
+#[diesel(check_for_backend(diesel::sqlite::Sqlite))]
+pub struct ArticleBookmark {
+    pub id: i32,
+    pub user_id: String,
+    pub article_id: String,
+    pub anchor: String,
+    pub label: String,
+    pub created_at: i64,
+}
+
+#[derive(Debug, Insertable)]
+#[diesel(table_name = article_bookmark)]
+#[diesel(check_for_backend(diesel::sqlite::Sqlite))]
+pub struct NewArticleBookmark {
+    pub user_id: String,
+    pub article_id: String,
+    pub anchor: String,
+    pub label: String,
+    pub created_at: i64,
+}
+
+#[derive(Identifiable, Debug, Queryable)]
+#[diesel(table_name = notification)]
+#[diesel(check_for_backend(diesel::sqlite::Sqlite))]
+pub struct Notification {
+    pub id: i32,
+    pub user_id: String,
+    pub kind: String,
+    pub course_id: String,
+    pub article_id: String,
+    pub comment_id: String,
+    pub title: String,
+    pub excerpt: String,
+    pub article_count: i32,
+    pub created_at: i64,
+    pub read_at: i64,
+}
+
+#[derive(Debug, Insertable)]
+#[diesel(table_name = notification)]
+#[diesel(check_for_backend(diesel::sqlite::Sqlite))]
+pub struct NewNotification {
+    pub user_id: String,
+    pub kind: String,
+    pub course_id: String,
+    pub article_id: String,
+    pub comment_id: String,
+    pub title: String,
+    pub excerpt: String,
+    pub article_count: i32,
+    pub created_at: i64,
+}
+
+/// Articles a course had at the last import.
+#[derive(Identifiable, Debug, Queryable, Insertable, AsChangeset)]
+#[diesel(table_name = course_article_count)]
+#[diesel(primary_key(course_id))]
+#[diesel(check_for_backend(diesel::sqlite::Sqlite))]
+pub struct CourseArticleCount {
+    pub course_id: String,
+    pub article_count: i32,
+    pub updated_at: i64,
+}
+
 #[derive(QueryableByName)]
 #[diesel(check_for_backend(diesel::sqlite::Sqlite))]
 pub struct ConnectionSecs {
     #[diesel(sql_type = BigInt)]
     pub secs: i64,
 }
+
+/// A flagged comment in the moderation queue.
+#[derive(QueryableByName)]
+#[diesel(check_for_backend(diesel::sqlite::Sqlite))]
+pub struct FlaggedComment {
+    #[diesel(sql_type = Text)]
+    pub comment_id: String,
+    #[diesel(sql_type = BigInt)]
+    pub flags: i64,
+    #[diesel(sql_type = BigInt)]
+    pub flagged_at: i64,
+}
@@ -0,0 +1,37 @@
+use {anyhow::anyhow, std::str::FromStr};
+
+pub const DEFAULT_LIMIT: i64 = 20;
+pub const MAX_LIMIT: i64 = 100;
+/// Characters of a reply kept in its notification.
+pub const EXCERPT_LEN: usize = 100;
+
+/// What a notification is about.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum NotificationKind {
+    /// Someone replied to a comment of the user.
+    Reply,
+    /// A course the user is studying got new articles in an import.
+    NewArticles,
+}
+
+impl NotificationKind {
+    /// Name in the `kind` column.
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            NotificationKind::Reply => "reply",
+            NotificationKind::NewArticles => "new_articles",
+        }
+    }
+}
+
+impl FromStr for NotificationKind {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "reply" => Ok(NotificationKind::Reply),
+            "new_articles" => Ok(NotificationKind::NewArticles),
+            _ => Err(anyhow!("unknown notification kind {s}")),
+        }
+    }
+}
@@ -0,0 +1,101 @@
+use {
+    crate::{
+        comment::{CommentSort, ModerationQueue},
+        export::image::DeviceProfile,
+        format::ContentFormat,
+        handlers, pb,
+        ws_session::FrameCompression,
+    },
+    utoipa::{
+        openapi::security::{ApiKey, ApiKeyValue, SecurityScheme},
+        Modify, OpenApi,
+    },
+};
+
+/// OpenAPI document of the HTTP API, served at `/api/openapi.json`.
+///
+/// Responses holding `pb` messages are protobuf unless JSON is asked for in
+/// the `Accept` header, failed requests answer with a `pb::Error` body.
+#[derive(OpenApi)]
+#[openapi(
+    info(title = "hackbook", description = "Course reader API"),
+    paths(
+        handlers::get_course_detail,
+        handlers::get_course_epub,
+        handlers::get_course_pdf,
+        handlers::get_articles_pdf,
+        handlers::list_course,
+        handlers::get_article,
+        handlers::get_article_content,
+        handlers::get_article_comments,
+        handlers::post_comment,
+        handlers::edit_comment,
+        handlers::delete_comment,
+        handlers::like_comment,
+        handlers::unlike_comment,
+        handlers::flag_comment,
+        handlers::get_moderation_queue,
+        handlers::moderate_comment,
+        handlers::get_moderation_log,
+        handlers::list_bookmarks,
+        handlers::save_bookmark,
+        handlers::delete_bookmark,
+        handlers::list_notifications,
+        handlers::read_notifications,
+        handlers::get_image,
+        handlers::ws_start,
+        handlers::login,
+        handlers::get_me,
+        handlers::save_study_info,
+        handlers::get_connect_seconds,
+        handlers::get_metrics,
+    ),
+    components(schemas(
+        pb::Course,
+        pb::StudyInfo,
+        pb::ListCourseResponse,
+        pb::Section,
+        pb::Article,
+        pb::Comment,
+        pb::CommentList,
+        pb::SaveCommentRequest,
+        pb::FlagCommentRequest,
+        pb::ModerationAction,
+        pb::ModerateCommentRequest,
+        pb::ModerationItem,
+        pb::ModerationQueue,
+        pb::ModerationLogEntry,
+        pb::ModerationLog,
+        pb::UserInfo,
+        pb::SaveStudyInfoRequest,
+        pb::ConnectSeconds,
+        pb::Bookmark,
+        pb::BookmarkList,
+        pb::SaveBookmarkRequest,
+        pb::NotificationKind,
+        pb::Notification,
+        pb::NotificationList,
+        pb::ReadNotificationsRequest,
+        pb::Error,
+        ContentFormat,
+        CommentSort,
+        ModerationQueue,
+        DeviceProfile,
+        FrameCompression,
+    )),
+    modifiers(&CookieAuth),
+)]
+pub struct ApiDoc;
+
+/// Session cookie set by `/api/login`.
+struct CookieAuth;
+
+impl Modify for CookieAuth {
+    fn modify(&self, openapi: &mut utoipa::openapi::OpenApi) {
+        let components = openapi.components.get_or_insert_with(Default::default);
+        components.add_security_scheme(
+            "cookie",
+            SecurityScheme::ApiKey(ApiKey::Cookie(ApiKeyValue::new("bookauth"))),
+        );
+    }
+}
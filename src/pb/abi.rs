@@ -1,121 +1,526 @@
+#[derive(serde::Serialize, utoipa::ToSchema)]
+#[serde(rename_all = "camelCase")]
+#[allow(clippy::derive_partial_eq_without_eq)]
 #[derive(Clone, PartialEq, ::prost::Message)]
 pub struct Course {
-    #[prost(uint32, tag="1")]
+    #[prost(uint32, tag = "1")]
     pub article_count: u32,
-    #[prost(string, tag="2")]
+    #[prost(string, tag = "2")]
     pub brief: ::prost::alloc::string::String,
-    #[prost(bool, tag="3")]
+    #[prost(bool, tag = "3")]
     pub done: bool,
-    #[prost(string, tag="4")]
+    #[prost(string, tag = "4")]
     pub id: ::prost::alloc::string::String,
-    #[prost(string, tag="5")]
+    #[prost(string, tag = "5")]
     pub image: ::prost::alloc::string::String,
-    #[prost(uint32, tag="6")]
+    #[prost(uint32, tag = "6")]
     pub price: u32,
-    #[prost(string, tag="7")]
+    #[prost(string, tag = "7")]
     pub purchased_count: ::prost::alloc::string::String,
-    #[prost(string, tag="8")]
+    #[prost(string, tag = "8")]
     pub teacher_name: ::prost::alloc::string::String,
-    #[prost(string, tag="9")]
+    #[prost(string, tag = "9")]
     pub teacher_title: ::prost::alloc::string::String,
-    #[prost(string, tag="10")]
+    #[prost(string, tag = "10")]
     pub title: ::prost::alloc::string::String,
-    #[prost(message, repeated, tag="11")]
+    #[prost(message, repeated, tag = "11")]
     pub sections: ::prost::alloc::vec::Vec<Section>,
-    #[prost(string, tag="12")]
+    #[prost(string, tag = "12")]
     pub description: ::prost::alloc::string::String,
-    #[prost(message, optional, tag="13")]
+    #[prost(message, optional, tag = "13")]
     pub study_info: ::core::option::Option<StudyInfo>,
 }
+#[derive(serde::Serialize, utoipa::ToSchema)]
+#[serde(rename_all = "camelCase")]
+#[allow(clippy::derive_partial_eq_without_eq)]
 #[derive(Clone, PartialEq, ::prost::Message)]
 pub struct StudyInfo {
-    #[prost(float, tag="1")]
+    #[prost(float, tag = "1")]
     pub percent: f32,
-    #[prost(uint64, tag="2")]
+    #[prost(uint64, tag = "2")]
     pub last_study_at: u64,
 }
+#[derive(serde::Serialize, utoipa::ToSchema)]
+#[serde(rename_all = "camelCase")]
+#[allow(clippy::derive_partial_eq_without_eq)]
 #[derive(Clone, PartialEq, ::prost::Message)]
 pub struct ListCourseResponse {
-    #[prost(message, repeated, tag="1")]
+    #[prost(message, repeated, tag = "1")]
     pub courses: ::prost::alloc::vec::Vec<Course>,
-    #[prost(bool, tag="2")]
+    #[prost(bool, tag = "2")]
     pub more: bool,
 }
+#[derive(serde::Serialize, utoipa::ToSchema)]
+#[serde(rename_all = "camelCase")]
+#[allow(clippy::derive_partial_eq_without_eq)]
 #[derive(Clone, PartialEq, ::prost::Message)]
 pub struct Section {
-    #[prost(string, tag="1")]
+    #[prost(string, tag = "1")]
     pub id: ::prost::alloc::string::String,
-    #[prost(string, tag="2")]
+    #[prost(string, tag = "2")]
     pub title: ::prost::alloc::string::String,
-    #[prost(message, repeated, tag="3")]
+    #[prost(message, repeated, tag = "3")]
     pub articles: ::prost::alloc::vec::Vec<Article>,
 }
+#[derive(serde::Serialize, utoipa::ToSchema)]
+#[serde(rename_all = "camelCase")]
+#[allow(clippy::derive_partial_eq_without_eq)]
 #[derive(Clone, PartialEq, ::prost::Message)]
 pub struct SectionList {
-    #[prost(message, repeated, tag="1")]
+    #[prost(message, repeated, tag = "1")]
     pub sections: ::prost::alloc::vec::Vec<Section>,
 }
+#[derive(serde::Serialize, utoipa::ToSchema)]
+#[serde(rename_all = "camelCase")]
+#[allow(clippy::derive_partial_eq_without_eq)]
 #[derive(Clone, PartialEq, ::prost::Message)]
 pub struct Article {
-    #[prost(string, tag="1")]
+    #[prost(string, tag = "1")]
     pub id: ::prost::alloc::string::String,
-    #[prost(string, tag="2")]
+    #[prost(string, tag = "2")]
     pub title: ::prost::alloc::string::String,
-    #[prost(string, tag="3")]
+    #[prost(string, tag = "3")]
     pub publish_date: ::prost::alloc::string::String,
-    #[prost(bool, tag="4")]
+    #[prost(bool, tag = "4")]
     pub done: bool,
-    #[prost(string, tag="5")]
+    #[prost(string, tag = "5")]
     pub content: ::prost::alloc::string::String,
-    #[prost(message, optional, tag="6")]
+    #[prost(message, optional, tag = "6")]
     pub course: ::core::option::Option<Course>,
-    #[prost(message, optional, tag="7")]
+    #[prost(message, optional, tag = "7")]
     pub section: ::core::option::Option<Section>,
-    #[prost(message, optional, tag="8")]
+    #[prost(message, optional, tag = "8")]
     pub study_info: ::core::option::Option<StudyInfo>,
+    #[prost(uint32, tag = "9")]
+    pub bookmark_count: u32,
+    #[prost(enumeration = "ContentFormat", tag = "10")]
+    pub format: i32,
 }
+#[derive(serde::Serialize, utoipa::ToSchema)]
+#[serde(rename_all = "camelCase")]
+#[allow(clippy::derive_partial_eq_without_eq)]
 #[derive(Clone, PartialEq, ::prost::Message)]
 pub struct ArticleList {
-    #[prost(message, repeated, tag="1")]
+    #[prost(message, repeated, tag = "1")]
     pub articles: ::prost::alloc::vec::Vec<Article>,
 }
+#[derive(serde::Serialize, utoipa::ToSchema)]
+#[serde(rename_all = "camelCase")]
+#[allow(clippy::derive_partial_eq_without_eq)]
 #[derive(Clone, PartialEq, ::prost::Message)]
 pub struct Comment {
-    #[prost(string, tag="1")]
+    #[prost(string, tag = "1")]
     pub content: ::prost::alloc::string::String,
-    #[prost(uint32, tag="2")]
+    #[prost(uint32, tag = "2")]
     pub like_count: u32,
-    #[prost(string, tag="3")]
+    #[prost(string, tag = "3")]
     pub nick_name: ::prost::alloc::string::String,
-    #[prost(message, repeated, tag="4")]
+    /// direct replies, each with its own, oldest first
+    #[prost(message, repeated, tag = "4")]
     pub replies: ::prost::alloc::vec::Vec<Comment>,
+    #[prost(string, tag = "5")]
+    pub id: ::prost::alloc::string::String,
+    /// empty for a top level comment
+    #[prost(string, tag = "6")]
+    pub parent_id: ::prost::alloc::string::String,
+    /// 0 when unknown
+    #[prost(uint64, tag = "7")]
+    pub created_at: u64,
+    /// written by a reader rather than imported
+    #[prost(bool, tag = "8")]
+    pub local: bool,
+    /// author of a local comment
+    #[prost(string, tag = "9")]
+    pub user_id: ::prost::alloc::string::String,
+    /// 0 unless edited
+    #[prost(uint64, tag = "10")]
+    pub updated_at: u64,
+    /// by the logged in user
+    #[prost(bool, tag = "11")]
+    pub liked: bool,
+    /// removed by its author, kept for its replies
+    #[prost(bool, tag = "12")]
+    pub deleted: bool,
+    /// only listed for admins
+    #[prost(bool, tag = "13")]
+    pub hidden: bool,
+    /// listed first, on top of the first page
+    #[prost(bool, tag = "14")]
+    pub pinned: bool,
+}
+#[derive(serde::Serialize, utoipa::ToSchema)]
+#[serde(rename_all = "camelCase")]
+#[allow(clippy::derive_partial_eq_without_eq)]
+#[derive(Clone, PartialEq, ::prost::Message)]
+pub struct SaveCommentRequest {
+    /// the comment replied to, empty for a top level one
+    #[prost(string, tag = "1")]
+    pub parent_id: ::prost::alloc::string::String,
+    #[prost(string, tag = "2")]
+    pub content: ::prost::alloc::string::String,
+    #[prost(string, tag = "3")]
+    pub nick_name: ::prost::alloc::string::String,
+}
+#[derive(serde::Serialize, utoipa::ToSchema)]
+#[serde(rename_all = "camelCase")]
+#[allow(clippy::derive_partial_eq_without_eq)]
+#[derive(Clone, PartialEq, ::prost::Message)]
+pub struct FlagCommentRequest {
+    #[prost(string, tag = "1")]
+    pub reason: ::prost::alloc::string::String,
 }
+#[derive(serde::Serialize, utoipa::ToSchema)]
+#[serde(rename_all = "camelCase")]
+#[allow(clippy::derive_partial_eq_without_eq)]
+#[derive(Clone, PartialEq, ::prost::Message)]
+pub struct ModerateCommentRequest {
+    #[prost(enumeration = "ModerationAction", tag = "1")]
+    pub action: i32,
+    #[prost(string, tag = "2")]
+    pub reason: ::prost::alloc::string::String,
+}
+#[derive(serde::Serialize, utoipa::ToSchema)]
+#[serde(rename_all = "camelCase")]
+#[allow(clippy::derive_partial_eq_without_eq)]
+#[derive(Clone, PartialEq, ::prost::Message)]
+pub struct ModerationItem {
+    #[prost(message, optional, tag = "1")]
+    pub comment: ::core::option::Option<Comment>,
+    #[prost(string, tag = "2")]
+    pub article_id: ::prost::alloc::string::String,
+    #[prost(uint32, tag = "3")]
+    pub flag_count: u32,
+    #[prost(string, repeated, tag = "4")]
+    pub flag_reasons: ::prost::alloc::vec::Vec<::prost::alloc::string::String>,
+    /// of the latest report, 0 if none
+    #[prost(uint64, tag = "5")]
+    pub flagged_at: u64,
+}
+#[derive(serde::Serialize, utoipa::ToSchema)]
+#[serde(rename_all = "camelCase")]
+#[allow(clippy::derive_partial_eq_without_eq)]
+#[derive(Clone, PartialEq, ::prost::Message)]
+pub struct ModerationQueue {
+    #[prost(message, repeated, tag = "1")]
+    pub items: ::prost::alloc::vec::Vec<ModerationItem>,
+    /// cursor of the next page, empty on the last one
+    #[prost(string, tag = "2")]
+    pub next_cursor: ::prost::alloc::string::String,
+}
+#[derive(serde::Serialize, utoipa::ToSchema)]
+#[serde(rename_all = "camelCase")]
+#[allow(clippy::derive_partial_eq_without_eq)]
+#[derive(Clone, PartialEq, ::prost::Message)]
+pub struct ModerationLogEntry {
+    #[prost(int32, tag = "1")]
+    pub id: i32,
+    #[prost(string, tag = "2")]
+    pub comment_id: ::prost::alloc::string::String,
+    #[prost(string, tag = "3")]
+    pub moderator_id: ::prost::alloc::string::String,
+    #[prost(enumeration = "ModerationAction", tag = "4")]
+    pub action: i32,
+    #[prost(string, tag = "5")]
+    pub reason: ::prost::alloc::string::String,
+    #[prost(uint64, tag = "6")]
+    pub created_at: u64,
+}
+#[derive(serde::Serialize, utoipa::ToSchema)]
+#[serde(rename_all = "camelCase")]
+#[allow(clippy::derive_partial_eq_without_eq)]
+#[derive(Clone, PartialEq, ::prost::Message)]
+pub struct ModerationLog {
+    #[prost(message, repeated, tag = "1")]
+    pub entries: ::prost::alloc::vec::Vec<ModerationLogEntry>,
+}
+#[derive(serde::Serialize, utoipa::ToSchema)]
+#[serde(rename_all = "camelCase")]
+#[allow(clippy::derive_partial_eq_without_eq)]
 #[derive(Clone, PartialEq, ::prost::Message)]
 pub struct CommentList {
-    #[prost(message, repeated, tag="1")]
+    #[prost(message, repeated, tag = "1")]
     pub comments: ::prost::alloc::vec::Vec<Comment>,
+    /// cursor of the next page, empty on the last one
+    #[prost(string, tag = "2")]
+    pub next_cursor: ::prost::alloc::string::String,
+}
+#[derive(serde::Serialize, utoipa::ToSchema)]
+#[serde(rename_all = "camelCase")]
+#[allow(clippy::derive_partial_eq_without_eq)]
+#[derive(Clone, PartialEq, ::prost::Message)]
+pub struct Notification {
+    #[prost(int32, tag = "1")]
+    pub id: i32,
+    #[prost(enumeration = "NotificationKind", tag = "2")]
+    pub kind: i32,
+    /// empty for a reply
+    #[prost(string, tag = "3")]
+    pub course_id: ::prost::alloc::string::String,
+    /// empty for new articles
+    #[prost(string, tag = "4")]
+    pub article_id: ::prost::alloc::string::String,
+    /// of the reply
+    #[prost(string, tag = "5")]
+    pub comment_id: ::prost::alloc::string::String,
+    /// nick name of who replied, or title of the course
+    #[prost(string, tag = "6")]
+    pub title: ::prost::alloc::string::String,
+    /// start of the reply
+    #[prost(string, tag = "7")]
+    pub excerpt: ::prost::alloc::string::String,
+    /// new articles in the course
+    #[prost(uint32, tag = "8")]
+    pub article_count: u32,
+    #[prost(uint64, tag = "9")]
+    pub created_at: u64,
+    #[prost(bool, tag = "10")]
+    pub read: bool,
 }
+#[derive(serde::Serialize, utoipa::ToSchema)]
+#[serde(rename_all = "camelCase")]
+#[allow(clippy::derive_partial_eq_without_eq)]
+#[derive(Clone, PartialEq, ::prost::Message)]
+pub struct NotificationList {
+    /// latest first
+    #[prost(message, repeated, tag = "1")]
+    pub notifications: ::prost::alloc::vec::Vec<Notification>,
+    #[prost(uint32, tag = "2")]
+    pub unread_count: u32,
+}
+#[derive(serde::Serialize, utoipa::ToSchema)]
+#[serde(rename_all = "camelCase")]
+#[allow(clippy::derive_partial_eq_without_eq)]
+#[derive(Clone, PartialEq, ::prost::Message)]
+pub struct ReadNotificationsRequest {
+    /// every notification of the user when empty
+    #[prost(int32, repeated, tag = "1")]
+    pub ids: ::prost::alloc::vec::Vec<i32>,
+}
+#[derive(serde::Serialize, utoipa::ToSchema)]
+#[serde(rename_all = "camelCase")]
+#[allow(clippy::derive_partial_eq_without_eq)]
 #[derive(Clone, PartialEq, ::prost::Message)]
 pub struct UserInfo {
-    #[prost(string, tag="1")]
+    #[prost(string, tag = "1")]
     pub id: ::prost::alloc::string::String,
-    #[prost(enumeration="UserRole", tag="2")]
+    #[prost(enumeration = "UserRole", tag = "2")]
     pub role: i32,
 }
+#[derive(serde::Serialize, utoipa::ToSchema)]
+#[serde(rename_all = "camelCase")]
+#[allow(clippy::derive_partial_eq_without_eq)]
+#[derive(Clone, PartialEq, ::prost::Message)]
+pub struct ConnectSeconds {
+    #[prost(int64, tag = "1")]
+    pub seconds: i64,
+}
+#[derive(serde::Serialize, utoipa::ToSchema)]
+#[serde(rename_all = "camelCase")]
+#[allow(clippy::derive_partial_eq_without_eq)]
 #[derive(Clone, PartialEq, ::prost::Message)]
 pub struct SaveStudyInfoRequest {
-    #[prost(string, tag="1")]
+    #[prost(string, tag = "1")]
     pub article_id: ::prost::alloc::string::String,
-    #[prost(string, tag="2")]
+    #[prost(string, tag = "2")]
     pub course_id: ::prost::alloc::string::String,
-    #[prost(float, tag="3")]
+    #[prost(float, tag = "3")]
     pub percent: f32,
 }
+#[derive(serde::Serialize, utoipa::ToSchema)]
+#[serde(rename_all = "camelCase")]
+#[allow(clippy::derive_partial_eq_without_eq)]
+#[derive(Clone, PartialEq, ::prost::Message)]
+pub struct Bookmark {
+    #[prost(int32, tag = "1")]
+    pub id: i32,
+    #[prost(string, tag = "2")]
+    pub article_id: ::prost::alloc::string::String,
+    #[prost(string, tag = "3")]
+    pub anchor: ::prost::alloc::string::String,
+    #[prost(string, tag = "4")]
+    pub label: ::prost::alloc::string::String,
+    #[prost(uint64, tag = "5")]
+    pub created_at: u64,
+}
+#[derive(serde::Serialize, utoipa::ToSchema)]
+#[serde(rename_all = "camelCase")]
+#[allow(clippy::derive_partial_eq_without_eq)]
+#[derive(Clone, PartialEq, ::prost::Message)]
+pub struct BookmarkList {
+    #[prost(message, repeated, tag = "1")]
+    pub bookmarks: ::prost::alloc::vec::Vec<Bookmark>,
+}
+#[derive(serde::Serialize, utoipa::ToSchema)]
+#[serde(rename_all = "camelCase")]
+#[allow(clippy::derive_partial_eq_without_eq)]
+#[derive(Clone, PartialEq, ::prost::Message)]
+pub struct SaveBookmarkRequest {
+    #[prost(string, tag = "1")]
+    pub article_id: ::prost::alloc::string::String,
+    #[prost(string, tag = "2")]
+    pub anchor: ::prost::alloc::string::String,
+    #[prost(string, tag = "3")]
+    pub label: ::prost::alloc::string::String,
+}
+#[derive(serde::Serialize, utoipa::ToSchema)]
+#[serde(rename_all = "camelCase")]
+#[allow(clippy::derive_partial_eq_without_eq)]
+#[derive(Clone, PartialEq, ::prost::Message)]
+pub struct Error {
+    /// not_found, bad_request, unauthorized, forbidden, too_many_requests,
+    /// unsupported or internal
+    #[prost(string, tag = "1")]
+    pub code: ::prost::alloc::string::String,
+    #[prost(string, tag = "2")]
+    pub message: ::prost::alloc::string::String,
+    #[prost(string, tag = "3")]
+    pub request_id: ::prost::alloc::string::String,
+}
+#[derive(serde::Serialize, utoipa::ToSchema)]
+#[serde(rename_all = "camelCase")]
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, PartialOrd, Ord, ::prost::Enumeration)]
+#[repr(i32)]
+pub enum ContentFormat {
+    Html = 0,
+    Markdown = 1,
+    Plaintext = 2,
+}
+impl ContentFormat {
+    /// String value of the enum field names used in the ProtoBuf definition.
+    ///
+    /// The values are not transformed in any way and thus are considered stable
+    /// (if the ProtoBuf definition does not change) and safe for programmatic use.
+    pub fn as_str_name(&self) -> &'static str {
+        match self {
+            ContentFormat::Html => "Html",
+            ContentFormat::Markdown => "Markdown",
+            ContentFormat::Plaintext => "Plaintext",
+        }
+    }
+    /// Creates an enum from field names used in the ProtoBuf definition.
+    pub fn from_str_name(value: &str) -> ::core::option::Option<Self> {
+        match value {
+            "Html" => Some(Self::Html),
+            "Markdown" => Some(Self::Markdown),
+            "Plaintext" => Some(Self::Plaintext),
+            _ => None,
+        }
+    }
+}
+/// Every action but dismiss also resolves the reports of the comment.
+#[derive(serde::Serialize, utoipa::ToSchema)]
+#[serde(rename_all = "camelCase")]
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, PartialOrd, Ord, ::prost::Enumeration)]
+#[repr(i32)]
+pub enum ModerationAction {
+    /// resolves the reports only
+    Dismiss = 0,
+    Hide = 1,
+    Restore = 2,
+    /// top level comments only
+    Pin = 3,
+    Unpin = 4,
+    /// comments of readers only, imported ones can be hidden
+    Delete = 5,
+}
+impl ModerationAction {
+    /// String value of the enum field names used in the ProtoBuf definition.
+    ///
+    /// The values are not transformed in any way and thus are considered stable
+    /// (if the ProtoBuf definition does not change) and safe for programmatic use.
+    pub fn as_str_name(&self) -> &'static str {
+        match self {
+            ModerationAction::Dismiss => "Dismiss",
+            ModerationAction::Hide => "Hide",
+            ModerationAction::Restore => "Restore",
+            ModerationAction::Pin => "Pin",
+            ModerationAction::Unpin => "Unpin",
+            ModerationAction::Delete => "Delete",
+        }
+    }
+    /// Creates an enum from field names used in the ProtoBuf definition.
+    pub fn from_str_name(value: &str) -> ::core::option::Option<Self> {
+        match value {
+            "Dismiss" => Some(Self::Dismiss),
+            "Hide" => Some(Self::Hide),
+            "Restore" => Some(Self::Restore),
+            "Pin" => Some(Self::Pin),
+            "Unpin" => Some(Self::Unpin),
+            "Delete" => Some(Self::Delete),
+            _ => None,
+        }
+    }
+}
+#[derive(serde::Serialize, utoipa::ToSchema)]
+#[serde(rename_all = "camelCase")]
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, PartialOrd, Ord, ::prost::Enumeration)]
+#[repr(i32)]
+pub enum CommentSort {
+    Likes = 0,
+    Newest = 1,
+    Oldest = 2,
+}
+impl CommentSort {
+    /// String value of the enum field names used in the ProtoBuf definition.
+    ///
+    /// The values are not transformed in any way and thus are considered stable
+    /// (if the ProtoBuf definition does not change) and safe for programmatic use.
+    pub fn as_str_name(&self) -> &'static str {
+        match self {
+            CommentSort::Likes => "Likes",
+            CommentSort::Newest => "Newest",
+            CommentSort::Oldest => "Oldest",
+        }
+    }
+    /// Creates an enum from field names used in the ProtoBuf definition.
+    pub fn from_str_name(value: &str) -> ::core::option::Option<Self> {
+        match value {
+            "Likes" => Some(Self::Likes),
+            "Newest" => Some(Self::Newest),
+            "Oldest" => Some(Self::Oldest),
+            _ => None,
+        }
+    }
+}
+#[derive(serde::Serialize, utoipa::ToSchema)]
+#[serde(rename_all = "camelCase")]
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, PartialOrd, Ord, ::prost::Enumeration)]
+#[repr(i32)]
+pub enum NotificationKind {
+    /// to a comment of the user
+    Reply = 0,
+    /// in a course the user studies
+    NewArticles = 1,
+}
+impl NotificationKind {
+    /// String value of the enum field names used in the ProtoBuf definition.
+    ///
+    /// The values are not transformed in any way and thus are considered stable
+    /// (if the ProtoBuf definition does not change) and safe for programmatic use.
+    pub fn as_str_name(&self) -> &'static str {
+        match self {
+            NotificationKind::Reply => "Reply",
+            NotificationKind::NewArticles => "NewArticles",
+        }
+    }
+    /// Creates an enum from field names used in the ProtoBuf definition.
+    pub fn from_str_name(value: &str) -> ::core::option::Option<Self> {
+        match value {
+            "Reply" => Some(Self::Reply),
+            "NewArticles" => Some(Self::NewArticles),
+            _ => None,
+        }
+    }
+}
+#[derive(serde::Serialize, utoipa::ToSchema)]
+#[serde(rename_all = "camelCase")]
 #[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, PartialOrd, Ord, ::prost::Enumeration)]
 #[repr(i32)]
 pub enum UserRole {
     Visitor = 0,
     Reader = 1,
+    /// a reader who moderates comments
+    Admin = 2,
 }
 impl UserRole {
     /// String value of the enum field names used in the ProtoBuf definition.
@@ -126,6 +531,16 @@ impl UserRole {
         match self {
             UserRole::Visitor => "Visitor",
             UserRole::Reader => "Reader",
+            UserRole::Admin => "Admin",
+        }
+    }
+    /// Creates an enum from field names used in the ProtoBuf definition.
+    pub fn from_str_name(value: &str) -> ::core::option::Option<Self> {
+        match value {
+            "Visitor" => Some(Self::Visitor),
+            "Reader" => Some(Self::Reader),
+            "Admin" => Some(Self::Admin),
+            _ => None,
         }
     }
 }
@@ -1,8 +1,18 @@
-use crate::{handlers, models};
+use {
+    crate::{comment, format, handlers, models, notification},
+    std::collections::HashMap,
+};
 
+#[allow(dead_code)]
 mod abi; // 声明 abi.rs
 pub use abi::*;
 
+pub mod hackbook {
+    pub mod v1 {
+        include!("hackbook.v1.rs");
+    }
+}
+
 impl From<models::Course> for Course {
     fn from(course: models::Course) -> Self {
         Course {
@@ -54,17 +64,166 @@ impl From<models::Article> for Article {
             course: None,
             section: None,
             study_info: None,
+            bookmark_count: 0,
+            format: ContentFormat::Html as i32,
+        }
+    }
+}
+
+impl From<models::ArticleBookmark> for Bookmark {
+    fn from(bookmark: models::ArticleBookmark) -> Self {
+        Bookmark {
+            id: bookmark.id,
+            article_id: bookmark.article_id,
+            anchor: bookmark.anchor,
+            label: bookmark.label,
+            created_at: bookmark.created_at as u64,
         }
     }
 }
 
-impl From<models::ArticleComment> for Comment {
-    fn from(comment: models::ArticleComment) -> Self {
+impl From<comment::Comment> for Comment {
+    fn from(comment: comment::Comment) -> Self {
         Comment {
             content: comment.content,
             like_count: comment.like_count as u32,
             nick_name: comment.nick_name,
             replies: vec![],
+            id: comment.id,
+            parent_id: comment.parent_id.unwrap_or_default(),
+            created_at: comment.created_at as u64,
+            local: comment.user_id.is_some(),
+            user_id: comment.user_id.unwrap_or_default(),
+            updated_at: comment.updated_at as u64,
+            liked: comment.liked,
+            deleted: comment.deleted,
+            hidden: comment.hidden,
+            pinned: comment.pinned,
+        }
+    }
+}
+
+impl From<comment::CommentPage> for CommentList {
+    fn from(page: comment::CommentPage) -> Self {
+        let mut children = HashMap::<String, Vec<comment::Comment>>::new();
+        for reply in page.replies {
+            let parent = reply.parent_id.clone().unwrap_or_default();
+            children.entry(parent).or_default().push(reply);
+        }
+
+        fn thread(
+            comment: comment::Comment,
+            children: &mut HashMap<String, Vec<comment::Comment>>,
+        ) -> Comment {
+            let replies = children.remove(&comment.id).unwrap_or_default();
+            let mut pbc: Comment = comment.into();
+            pbc.replies = replies.into_iter().map(|r| thread(r, children)).collect();
+            pbc
+        }
+
+        CommentList {
+            comments: page
+                .comments
+                .into_iter()
+                .map(|c| thread(c, &mut children))
+                .collect(),
+            next_cursor: page.next_cursor.map(|c| c.to_string()).unwrap_or_default(),
+        }
+    }
+}
+
+impl From<comment::ModerationPage> for ModerationQueue {
+    fn from(page: comment::ModerationPage) -> Self {
+        ModerationQueue {
+            items: page
+                .items
+                .into_iter()
+                .map(|item| ModerationItem {
+                    article_id: item.comment.article_id.clone().unwrap_or_default(),
+                    comment: Some(item.comment.into()),
+                    flag_count: item.flag_count as u32,
+                    flag_reasons: item.flag_reasons,
+                    flagged_at: item.flagged_at as u64,
+                })
+                .collect(),
+            next_cursor: page.next_cursor.map(|c| c.to_string()).unwrap_or_default(),
+        }
+    }
+}
+
+impl From<models::CommentModerationLog> for ModerationLogEntry {
+    fn from(entry: models::CommentModerationLog) -> Self {
+        let action = entry
+            .action
+            .parse::<comment::ModerationAction>()
+            .map_or(ModerationAction::Dismiss, ModerationAction::from);
+        ModerationLogEntry {
+            id: entry.id,
+            comment_id: entry.comment_id,
+            moderator_id: entry.moderator_id,
+            action: action as i32,
+            reason: entry.reason,
+            created_at: entry.created_at as u64,
+        }
+    }
+}
+
+impl From<models::Notification> for Notification {
+    fn from(n: models::Notification) -> Self {
+        let kind = n.kind.parse::<notification::NotificationKind>().map_or(
+            NotificationKind::Reply,
+            |kind| match kind {
+                notification::NotificationKind::Reply => NotificationKind::Reply,
+                notification::NotificationKind::NewArticles => NotificationKind::NewArticles,
+            },
+        );
+        Notification {
+            id: n.id,
+            kind: kind as i32,
+            course_id: n.course_id,
+            article_id: n.article_id,
+            comment_id: n.comment_id,
+            title: n.title,
+            excerpt: n.excerpt,
+            article_count: n.article_count as u32,
+            created_at: n.created_at as u64,
+            read: n.read_at > 0,
+        }
+    }
+}
+
+impl From<comment::ModerationAction> for ModerationAction {
+    fn from(action: comment::ModerationAction) -> Self {
+        match action {
+            comment::ModerationAction::Dismiss => ModerationAction::Dismiss,
+            comment::ModerationAction::Hide => ModerationAction::Hide,
+            comment::ModerationAction::Restore => ModerationAction::Restore,
+            comment::ModerationAction::Pin => ModerationAction::Pin,
+            comment::ModerationAction::Unpin => ModerationAction::Unpin,
+            comment::ModerationAction::Delete => ModerationAction::Delete,
+        }
+    }
+}
+
+impl From<ModerationAction> for comment::ModerationAction {
+    fn from(action: ModerationAction) -> Self {
+        match action {
+            ModerationAction::Dismiss => comment::ModerationAction::Dismiss,
+            ModerationAction::Hide => comment::ModerationAction::Hide,
+            ModerationAction::Restore => comment::ModerationAction::Restore,
+            ModerationAction::Pin => comment::ModerationAction::Pin,
+            ModerationAction::Unpin => comment::ModerationAction::Unpin,
+            ModerationAction::Delete => comment::ModerationAction::Delete,
+        }
+    }
+}
+
+impl From<CommentSort> for comment::CommentSort {
+    fn from(sort: CommentSort) -> Self {
+        match sort {
+            CommentSort::Likes => comment::CommentSort::Likes,
+            CommentSort::Newest => comment::CommentSort::Newest,
+            CommentSort::Oldest => comment::CommentSort::Oldest,
         }
     }
 }
@@ -77,3 +236,57 @@ impl From<handlers::LoggedUser> for UserInfo {
         }
     }
 }
+
+impl From<format::ContentFormat> for ContentFormat {
+    fn from(format: format::ContentFormat) -> Self {
+        match format {
+            format::ContentFormat::Html => ContentFormat::Html,
+            format::ContentFormat::Markdown => ContentFormat::Markdown,
+            format::ContentFormat::Plaintext => ContentFormat::Plaintext,
+        }
+    }
+}
+
+impl From<ContentFormat> for format::ContentFormat {
+    fn from(format: ContentFormat) -> Self {
+        match format {
+            ContentFormat::Html => format::ContentFormat::Html,
+            ContentFormat::Markdown => format::ContentFormat::Markdown,
+            ContentFormat::Plaintext => format::ContentFormat::Plaintext,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn threads_replies_at_any_depth() {
+        let page = comment::CommentPage {
+            comments: vec![
+                comment::Comment::for_test("a", None, 2, 100),
+                comment::Comment::for_test("e", None, 1, 100),
+            ],
+            replies: vec![
+                comment::Comment::for_test("b", Some("a"), 0, 101),
+                comment::Comment::for_test("c", Some("b"), 0, 102),
+                comment::Comment::for_test("d", Some("a"), 0, 103),
+            ],
+            next_cursor: Some(comment::Cursor {
+                key: 1,
+                id: "e".to_owned(),
+            }),
+        };
+        let list = CommentList::from(page);
+        let ids = |comments: &[Comment]| comments.iter().map(|c| c.id.clone()).collect::<Vec<_>>();
+
+        assert_eq!(ids(&list.comments), ["a", "e"]);
+        assert_eq!(ids(&list.comments[0].replies), ["b", "d"]);
+        assert_eq!(ids(&list.comments[0].replies[0].replies), ["c"]);
+        assert!(list.comments[0].replies[1].replies.is_empty());
+        assert!(list.comments[1].replies.is_empty());
+        assert_eq!(list.comments[0].replies[0].replies[0].parent_id, "b");
+        assert_eq!(list.next_cursor, "1.e");
+    }
+}
@@ -1,20 +1,106 @@
 use {
-    crate::models,
-    anyhow::{Context, Result},
+    crate::{
+        cache::Cache,
+        comment::{
+            self, Comment, CommentPage, CommentSort, Cursor, ModerationAction, ModerationItem,
+            ModerationPage, ModerationQueue,
+        },
+        config::{CommentConfig, Config, DatabaseConfig},
+        error::AppError,
+        html, migrations, models,
+        notification::{self, NotificationKind},
+        sanitize,
+        storage::{self, Storage},
+    },
+    actix_web::web,
+    anyhow::{anyhow, Context, Result},
     diesel::{
         connection::SimpleConnection,
         prelude::*,
-        r2d2::{ConnectionManager, Pool},
+        r2d2::{ConnectionManager, Pool, PooledConnection},
         sql_query,
         sql_types::{BigInt, Integer, VarChar},
         SqliteConnection,
     },
-    std::{env, fs, path::PathBuf, time::Duration},
+    sha2::{Digest, Sha256},
+    std::{
+        collections::HashMap,
+        fs,
+        path::{Path, PathBuf},
+        sync::{Arc, Mutex},
+        time::{Duration, Instant, SystemTime},
+    },
 };
 
+/// Url prefix under which `STORAGE_PATH/images` is served.
+pub const LOCAL_IMAGE_PREFIX: &str = "/api/images/";
+
+/// Touched under `STORAGE_PATH` by the crawler once an import is done, which
+/// drops the cached content.
+pub const IMPORT_STAMP: &str = "last_import";
+/// In characters, the column holds 255.
+pub const MAX_BOOKMARK_ANCHOR_LEN: usize = 255;
+/// In characters.
+pub const MAX_BOOKMARK_LABEL_LEN: usize = 100;
+/// How often the import stamp is looked at.
+pub const IMPORT_CHECK_INTERVAL: Duration = Duration::from_secs(5);
+
+pub type CourseDetail = (
+    models::Course,
+    Vec<(models::Section, Vec<models::Article>)>,
+    Option<String>,
+);
+
+type DbPool = Pool<ConnectionManager<SqliteConnection>>;
+
+/// Keeps the top level comments of a page, in the order of `CommentSort`,
+/// for either comment table.
+macro_rules! page_query {
+    ($query:expr, $likes:expr, $created_at:expr, $id:expr, $sort:expr, $cursor:expr) => {{
+        let query = match ($sort, $cursor) {
+            (CommentSort::Likes, Some(c)) => {
+                // the key comes from the client, it may not fit the column
+                let key = i32::try_from(c.key)
+                    .map_err(|_| AppError::BadRequest("invalid cursor".to_owned()))?;
+                $query.filter($likes.lt(key).or($likes.eq(key).and($id.gt(&c.id))))
+            }
+            (CommentSort::Newest, Some(c)) => $query.filter(
+                $created_at
+                    .lt(c.key)
+                    .or($created_at.eq(c.key).and($id.gt(&c.id))),
+            ),
+            (CommentSort::Oldest, Some(c)) => $query.filter(
+                $created_at
+                    .gt(c.key)
+                    .or($created_at.eq(c.key).and($id.gt(&c.id))),
+            ),
+            (_, None) => $query,
+        };
+        match $sort {
+            CommentSort::Likes => query.order(($likes.desc(), $id.asc())),
+            CommentSort::Newest => query.order(($created_at.desc(), $id.asc())),
+            CommentSort::Oldest => query.order(($created_at.asc(), $id.asc())),
+        }
+    }};
+}
+
 pub struct Repo {
-    pool: Pool<ConnectionManager<SqliteConnection>>,
+    pool: DbPool,
+    /// Read-only replica for the content tables, if configured.
+    content_pool: Option<DbPool>,
     storage_path: PathBuf,
+    storage: Box<dyn Storage>,
+    courses: Cache<String, CourseDetail>,
+    /// Sanitized article html by article id.
+    articles: Cache<String, String>,
+    /// Result of `get_catalog_version`, a single entry.
+    catalog: Cache<(), String>,
+    /// When the import stamp was last looked at, and its mtime then.
+    import_check: Mutex<(Instant, Option<SystemTime>)>,
+    /// Import stamp as of the last look for new articles, `None` until the
+    /// first one.
+    article_check: Mutex<Option<SystemTime>>,
+    comment_limit: CommentConfig,
 }
 
 #[derive(Debug)]
@@ -22,6 +108,9 @@ pub struct ConnectionOptions {
     pub enable_wal: bool,
     pub enable_foreign_keys: bool,
     pub busy_timeout: Option<Duration>,
+    pub synchronous: String,
+    pub read_only: bool,
+    pub attach_content: Option<String>,
 }
 
 impl diesel::r2d2::CustomizeConnection<SqliteConnection, diesel::r2d2::Error>
@@ -29,48 +118,186 @@ impl diesel::r2d2::CustomizeConnection<SqliteConnection, diesel::r2d2::Error>
 {
     fn on_acquire(&self, conn: &mut SqliteConnection) -> Result<(), diesel::r2d2::Error> {
         (|| {
+            // an unqualified journal_mode applies to every attached database
+            // too, which fails on a read-only content one not in WAL mode
             if self.enable_wal {
-                conn.batch_execute("PRAGMA journal_mode = WAL; PRAGMA synchronous = NORMAL;")?;
+                conn.batch_execute("PRAGMA main.journal_mode = WAL;")?;
             }
+            conn.batch_execute(&format!("PRAGMA main.synchronous = {};", self.synchronous))?;
             if self.enable_foreign_keys {
                 conn.batch_execute("PRAGMA foreign_keys = ON;")?;
             }
             if let Some(d) = self.busy_timeout {
                 conn.batch_execute(&format!("PRAGMA busy_timeout = {};", d.as_millis()))?;
             }
+            if self.read_only {
+                conn.batch_execute("PRAGMA query_only = ON;")?;
+            }
+            if let Some(url) = &self.attach_content {
+                conn.batch_execute(&format!(
+                    "ATTACH DATABASE '{}' AS content;",
+                    url.replace('\'', "''")
+                ))?;
+            }
             Ok(())
         })()
         .map_err(diesel::r2d2::Error::QueryError)
     }
 }
 
+/// Deletes a reader's comment, of `author` if given, returns whether there
+/// was one. A comment with replies loses its content but stays in the
+/// thread.
+fn remove_comment(
+    conn: &mut SqliteConnection,
+    id: &str,
+    author: Option<&str>,
+) -> QueryResult<bool> {
+    use crate::schema::{comment_flag, comment_like, comment_moderation, user_comment::dsl};
+
+    let comment = dsl::user_comment
+        .filter(dsl::id.eq(id))
+        .filter(dsl::deleted.eq(false))
+        .first::<models::UserComment>(conn)
+        .optional()?;
+    match comment {
+        Some(c) if author.is_none_or(|author| c.user_id == author) => {}
+        _ => return Ok(false),
+    }
+
+    let has_replies = diesel::select(diesel::dsl::exists(
+        dsl::user_comment.filter(dsl::parent_id.eq(id)),
+    ))
+    .get_result::<bool>(conn)?;
+    if has_replies {
+        diesel::update(dsl::user_comment.filter(dsl::id.eq(id)))
+            .set((
+                dsl::content.eq(""),
+                dsl::like_count.eq(0),
+                dsl::deleted.eq(true),
+                dsl::updated_at.eq(chrono::Utc::now().timestamp()),
+            ))
+            .execute(conn)?;
+    } else {
+        diesel::delete(dsl::user_comment.filter(dsl::id.eq(id))).execute(conn)?;
+        diesel::delete(comment_moderation::table.filter(comment_moderation::comment_id.eq(id)))
+            .execute(conn)?;
+    }
+    diesel::delete(comment_like::table.filter(comment_like::comment_id.eq(id))).execute(conn)?;
+    diesel::delete(comment_flag::table.filter(comment_flag::comment_id.eq(id))).execute(conn)?;
+
+    Ok(true)
+}
+
+fn validate_bookmark(bookmark: &models::NewArticleBookmark) -> Result<(), AppError> {
+    if bookmark.anchor.trim().is_empty() {
+        return Err(AppError::BadRequest("anchor is required".to_owned()));
+    }
+    if bookmark.anchor.chars().count() > MAX_BOOKMARK_ANCHOR_LEN {
+        return Err(AppError::BadRequest(format!(
+            "anchor is longer than {MAX_BOOKMARK_ANCHOR_LEN} characters"
+        )));
+    }
+    if bookmark.label.chars().count() > MAX_BOOKMARK_LABEL_LEN {
+        return Err(AppError::BadRequest(format!(
+            "label is longer than {MAX_BOOKMARK_LABEL_LEN} characters"
+        )));
+    }
+    Ok(())
+}
+
+fn build_pool(config: &DatabaseConfig) -> Result<DbPool> {
+    Pool::builder()
+        .max_size(config.pool_size)
+        .connection_timeout(config.connection_timeout)
+        .connection_customizer(Box::new(ConnectionOptions {
+            enable_wal: config.enable_wal,
+            enable_foreign_keys: config.enable_foreign_keys,
+            busy_timeout: config.busy_timeout,
+            synchronous: config.synchronous.clone(),
+            read_only: config.read_only,
+            attach_content: config.attach_content.clone(),
+        }))
+        .build(ConnectionManager::<SqliteConnection>::new(&config.url))
+        .with_context(|| format!("failed to open database {}", config.url))
+}
+
 impl Repo {
-    pub fn new() -> Self {
-        let database_url = env::var("DATABASE_URL").expect("DATABASE_URL must be set");
-        let storage = env::var("STORAGE_PATH").expect("STORAGE_PATH must be set");
-
-        let pool = Pool::builder()
-            .max_size(2)
-            .connection_customizer(Box::new(ConnectionOptions {
-                enable_wal: true,
-                enable_foreign_keys: true,
-                busy_timeout: Some(Duration::from_secs(30)),
-            }))
-            .build(ConnectionManager::<SqliteConnection>::new(database_url))
-            .unwrap();
+    pub fn new(config: &Config) -> Result<Self> {
+        let pool = build_pool(&config.database)?;
+        let content_pool = config
+            .content_database
+            .as_ref()
+            .map(build_pool)
+            .transpose()?;
 
-        let storage_path = PathBuf::from(&storage);
+        let storage_path = config.storage_path.clone();
         if !storage_path.is_dir() {
-            panic!("{storage} is not a dir");
+            return Err(anyhow!("{} is not a dir", storage_path.display()));
         }
 
-        Repo { pool, storage_path }
+        Ok(Repo {
+            pool,
+            content_pool,
+            storage_path,
+            storage: storage::open(config)?,
+            courses: Cache::new("courses", config.cache.courses, config.cache.ttl),
+            articles: Cache::new("articles", config.cache.articles, config.cache.ttl),
+            catalog: Cache::new(
+                "catalog",
+                usize::from(config.cache.courses > 0),
+                config.cache.ttl,
+            ),
+            import_check: Mutex::new((Instant::now(), import_stamp(&config.storage_path))),
+            article_check: Mutex::new(None),
+            comment_limit: config.comments.clone(),
+        })
+    }
+
+    pub fn verify_schema(&self) -> Result<()> {
+        migrations::verify_schema(&mut *self.pool.get()?)?;
+        if let Some(pool) = &self.content_pool {
+            migrations::verify_schema(&mut *pool.get()?)?;
+        }
+
+        Ok(())
+    }
+
+    /// Drops the cached content if an import ran since the last check.
+    fn check_import(&self) {
+        let mut check = self.import_check.lock().unwrap();
+        if check.0.elapsed() < IMPORT_CHECK_INTERVAL {
+            return;
+        }
+        let stamp = import_stamp(&self.storage_path);
+        if stamp != check.1 {
+            log::info!("import detected, clearing caches");
+            self.courses.clear();
+            self.articles.clear();
+            self.catalog.clear();
+        }
+        *check = (Instant::now(), stamp);
+    }
+
+    /// Counters of the caches in the Prometheus text format.
+    pub fn cache_metrics(&self) -> String {
+        let mut out = String::new();
+        self.courses.write_metrics(&mut out);
+        self.articles.write_metrics(&mut out);
+        self.catalog.write_metrics(&mut out);
+        out
+    }
+
+    /// Connection for reading courses, sections, articles and comments,
+    /// which are never written by the server.
+    fn content_conn(&self) -> Result<PooledConnection<ConnectionManager<SqliteConnection>>> {
+        Ok(self.content_pool.as_ref().unwrap_or(&self.pool).get()?)
     }
 
     pub fn find_section_by_id(&self, id: &str) -> Result<models::Section> {
         use crate::schema::section::dsl;
 
-        let conn = &mut self.pool.get()?;
+        let conn = &mut self.content_conn()?;
 
         Ok(dsl::section
             .filter(dsl::id.eq(id))
@@ -80,24 +307,84 @@ impl Repo {
     pub fn find_course_by_id(&self, id: &str) -> Result<models::Course> {
         use crate::schema::course::dsl;
 
-        let conn = &mut self.pool.get()?;
+        let conn = &mut self.content_conn()?;
 
         Ok(dsl::course
             .filter(dsl::id.eq(id))
             .first::<models::Course>(conn)?)
     }
 
-    pub fn get_course_detail_by_course_id(
-        &self,
-        course_id: &str,
-    ) -> Result<(
-        models::Course,
-        Vec<(models::Section, Vec<models::Article>)>,
-        Option<String>,
-    )> {
+    pub fn get_course_detail_by_course_id(&self, course_id: &str) -> Result<CourseDetail> {
+        self.check_import();
+        self.courses
+            .get_or_load(course_id.to_owned(), || self.load_course_detail(course_id))
+    }
+
+    /// Hash of the course, its table of contents and description, changes
+    /// whenever any of them does.
+    pub fn get_course_version(&self, course_id: &str) -> Result<String> {
+        let (course, sections, desc) = self.get_course_detail_by_course_id(course_id)?;
+        let mut parts = course_parts(&course);
+        for (section, articles) in &sections {
+            parts.extend([section.id.clone(), section.title.clone()]);
+            for article in articles {
+                parts.extend([
+                    article.id.clone(),
+                    article.title.clone(),
+                    article.publish_date.clone(),
+                    article.done.to_string(),
+                ]);
+            }
+        }
+        parts.push(desc.unwrap_or_default());
+        Ok(hash_parts(&parts))
+    }
+
+    /// Changes whenever a course, section or article is added, removed or
+    /// marked done: the import stamp along with row counts. Cached like the
+    /// courses, until the next import or for `CACHE_TTL_SECS`.
+    pub fn get_catalog_version(&self) -> Result<String> {
+        self.check_import();
+        self.catalog.get_or_load((), || self.load_catalog_version())
+    }
+
+    fn load_catalog_version(&self) -> Result<String> {
+        use crate::schema::{article, course, section};
+
+        let conn = &mut self.content_conn()?;
+        let courses = course::table.count().get_result::<i64>(conn)?;
+        let courses_done = course::table
+            .filter(course::done)
+            .count()
+            .get_result::<i64>(conn)?;
+        let article_counts = course::table
+            .select(diesel::dsl::sum(course::articleCount))
+            .first::<Option<i64>>(conn)?;
+        let sections = section::table.count().get_result::<i64>(conn)?;
+        let articles = article::table.count().get_result::<i64>(conn)?;
+        let articles_done = article::table
+            .filter(article::done)
+            .count()
+            .get_result::<i64>(conn)?;
+        let stamp = import_stamp(&self.storage_path)
+            .and_then(|t| t.duration_since(SystemTime::UNIX_EPOCH).ok())
+            .map_or(0, |d| d.as_nanos());
+
+        Ok(hash_parts(&[
+            stamp.to_string(),
+            courses.to_string(),
+            courses_done.to_string(),
+            article_counts.unwrap_or_default().to_string(),
+            sections.to_string(),
+            articles.to_string(),
+            articles_done.to_string(),
+        ]))
+    }
+
+    fn load_course_detail(&self, course_id: &str) -> Result<CourseDetail> {
         use crate::schema::course;
 
-        let conn = &mut self.pool.get()?;
+        let conn = &mut self.content_conn()?;
 
         let one_course = course::dsl::course
             .filter(course::dsl::id.eq(course_id))
@@ -105,11 +392,11 @@ impl Repo {
 
         let sections = models::Section::belonging_to(&one_course).load::<models::Section>(conn)?;
 
-        let desc = fs::read_to_string(
-            self.storage_path
-                .join(format!("courses/{}.html", one_course.id)),
-        )
-        .ok();
+        let desc = self
+            .storage
+            .read_to_string(&format!("courses/{}.html", one_course.id))
+            .ok()
+            .map(|desc| self.localize_images(&sanitize::sanitize(&desc)));
 
         let articles = models::Article::belonging_to(&sections)
             .load::<models::Article>(conn)?
@@ -181,34 +468,937 @@ OFFSET
 
     pub fn get_article_detail(&self, id: &str) -> Result<(models::Article, String)> {
         use crate::schema::article::dsl;
-        let conn = &mut self.pool.get()?;
+        let conn = &mut self.content_conn()?;
         let art = dsl::article
             .filter(dsl::id.eq(id))
             .first::<models::Article>(conn)?;
-        let file_path = self.storage_path.join(format!("contents/{id}.html"));
-        let content = fs::read_to_string(&file_path)
-            .context(format!("{} not exist", file_path.to_string_lossy()))?;
+        let content = self.localize_images(&self.get_article_content(id)?);
 
         Ok((art, content))
     }
 
+    /// Hex sha256 over everything the article detail is rendered from: the
+    /// version of the crawled file, the sanitizer, the downloaded images the
+    /// content may point at, and the article, section and course rows.
+    /// Doesn't read the content itself.
+    pub fn get_article_version(&self, id: &str) -> Result<String> {
+        use crate::schema::article::dsl;
+
+        let article = {
+            let conn = &mut self.content_conn()?;
+            dsl::article
+                .filter(dsl::id.eq(id))
+                .first::<models::Article>(conn)?
+        };
+        let section = self.find_section_by_id(&article.section_id)?;
+        let course = self.find_course_by_id(&section.course_id)?;
+        let file = self.storage.version(&format!("contents/{id}.html"))?;
+        // `download_images` only ever adds files, which bumps the directory
+        let images = fs::metadata(self.storage_path.join("images"))
+            .and_then(|m| m.modified())
+            .ok()
+            .and_then(|t| t.duration_since(SystemTime::UNIX_EPOCH).ok())
+            .map_or(0, |d| d.as_nanos());
+
+        let mut parts = vec![
+            file,
+            sanitize::VERSION.to_string(),
+            images.to_string(),
+            article.id,
+            article.title,
+            article.publish_date,
+            article.done.to_string(),
+            section.id,
+            section.title,
+        ];
+        parts.extend(course_parts(&course));
+        Ok(hash_parts(&parts))
+    }
+
+    /// Sanitized html of an article, kept in memory, and on disk under
+    /// `cache/sanitized-v{VERSION}` next to the version of the crawled file it
+    /// was made from.
+    pub fn get_article_content(&self, id: &str) -> Result<String> {
+        self.check_import();
+        self.articles
+            .get_or_load(id.to_owned(), || self.load_article_content(id))
+    }
+
+    fn load_article_content(&self, id: &str) -> Result<String> {
+        let key = format!("contents/{id}.html");
+        let cache_dir = self.cache_dir(&format!("sanitized-v{}", sanitize::VERSION));
+        let cache_path = cache_dir.join(format!("{id}.html"));
+        let version_path = cache_dir.join(format!("{id}.version"));
+        let version = self.storage.version(&key)?;
+        if fs::read_to_string(&version_path).is_ok_and(|v| v == version) {
+            if let Ok(content) = fs::read_to_string(&cache_path) {
+                return Ok(content);
+            }
+        }
+
+        let content = sanitize::sanitize(&self.storage.read_to_string(&key)?);
+        // the version goes last, a crash in between leaves a stale version
+        // which is simply rebuilt
+        if let Err(e) = write_atomic(&cache_path, content.as_bytes())
+            .and_then(|_| write_atomic(&version_path, version.as_bytes()))
+        {
+            log::warn!("failed to cache sanitized article {id}: {e}");
+        }
+
+        Ok(content)
+    }
+
+    /// Foreign keys can't span the content and user databases, so user data
+    /// referencing an article checks it exists first.
+    fn check_article(&self, id: &str) -> Result<()> {
+        use crate::schema::article::dsl;
+
+        let conn = &mut self.content_conn()?;
+        let exists = diesel::select(diesel::dsl::exists(dsl::article.filter(dsl::id.eq(id))))
+            .get_result::<bool>(conn)?;
+        if !exists {
+            return Err(AppError::BadRequest(format!("article {id} doesn't exist")).into());
+        }
+
+        Ok(())
+    }
+
+    pub fn find_articles_by_ids(&self, ids: &[String]) -> Result<Vec<models::Article>> {
+        use crate::schema::article::dsl;
+        let conn = &mut self.content_conn()?;
+
+        Ok(dsl::article
+            .filter(dsl::id.eq_any(ids))
+            .load::<models::Article>(conn)?)
+    }
+
+    /// Directory under `STORAGE_PATH` for derived files which can be regenerated.
+    pub fn cache_dir(&self, name: &str) -> PathBuf {
+        self.storage_path.join("cache").join(name)
+    }
+
+    /// Points every image with a downloaded copy at the local image route,
+    /// so articles can be read offline.
+    pub fn localize_images(&self, html: &str) -> String {
+        html::to_xhtml(html, |src| {
+            let local = self.find_local_image(src).and_then(|file| {
+                let name = file.file_name()?.to_str()?;
+                Some(format!("{LOCAL_IMAGE_PREFIX}{name}"))
+            });
+            Some(local.unwrap_or_else(|| src.to_owned()))
+        })
+    }
+
+    /// Maps an image src written by `download_images` or
+    /// `download_course_images` (`/images/{name}`), or one of the local image
+    /// route, to the downloaded copy under `STORAGE_PATH`. Remote urls are
+    /// left alone, downloads are renamed on collisions so their name doesn't
+    /// tell which file they became.
+    pub fn find_local_image(&self, src: &str) -> Option<PathBuf> {
+        let path = src.split(['?', '#']).next().unwrap_or_default();
+        let name = ["/images/", "./images/", LOCAL_IMAGE_PREFIX]
+            .iter()
+            .find_map(|prefix| path.strip_prefix(prefix))?;
+        if name.is_empty() || name.contains(['/', '\\']) || name.starts_with('.') {
+            return None;
+        }
+        let file = self.storage_path.join("images").join(name);
+        file.is_file().then_some(file)
+    }
+
+    /// A page of the top level comments of an article in `sort` order,
+    /// starting after `cursor`, with the whole tree of replies under them.
+    /// Imported comments and the ones of readers are listed together,
+    /// `user_id` tells which were liked.
     pub fn find_comments_by_article_id(
         &self,
         article_id: &str,
-    ) -> Result<Vec<(Vec<models::ArticleComment>, models::ArticleComment)>> {
-        use crate::schema::article_comment::dsl;
+        sort: CommentSort,
+        cursor: Option<&Cursor>,
+        limit: i64,
+        user_id: &str,
+        moderator: bool,
+    ) -> Result<CommentPage> {
+        use crate::schema::{
+            article_comment::dsl as ac, comment_moderation::dsl as cm, user_comment::dsl as uc,
+        };
+        let limit = limit.clamp(1, comment::MAX_LIMIT);
+
+        let moderation = cm::comment_moderation
+            .filter(cm::article_id.eq(article_id))
+            .load::<models::CommentModeration>(&mut self.pool.get()?)?
+            .into_iter()
+            .map(|m| (m.comment_id.clone(), m))
+            .collect::<HashMap<_, _>>();
+        let visible = |c: &Comment| moderator || !moderation.get(&c.id).is_some_and(|m| m.hidden);
+        // pinned comments are on top of the first page rather than in order
+        let excluded = moderation
+            .values()
+            .filter(|m| m.pinned_at > 0 || (m.hidden && !moderator))
+            .map(|m| m.comment_id.clone())
+            .collect::<Vec<_>>();
+
+        // without a replica both connections come from the same pool, so
+        // only one is held at a time
+        let imported = page_query!(
+            ac::article_comment
+                .filter(ac::articleId.eq(article_id))
+                .filter(ac::parentCommentId.is_null())
+                .filter(ac::id.ne_all(&excluded))
+                .into_boxed(),
+            ac::likeCount,
+            ac::createdAt,
+            ac::id,
+            sort,
+            cursor
+        )
+        .limit(limit + 1)
+        .load::<models::ArticleComment>(&mut self.content_conn()?)?;
+        let local = page_query!(
+            uc::user_comment
+                .filter(uc::article_id.eq(article_id))
+                .filter(uc::parent_id.is_null())
+                .filter(uc::id.ne_all(&excluded))
+                .into_boxed(),
+            uc::like_count,
+            uc::created_at,
+            uc::id,
+            sort,
+            cursor
+        )
+        .limit(limit + 1)
+        .load::<models::UserComment>(&mut self.pool.get()?)?;
+
+        // both are in order and at least a page long if there are more
+        let mut comments = imported
+            .into_iter()
+            .map(Comment::from)
+            .chain(local.into_iter().map(Comment::from))
+            .collect::<Vec<_>>();
+        comments.sort_by(|a, b| sort.compare(a, b));
+        let next_cursor = if comments.len() as i64 > limit {
+            comments.truncate(limit as usize);
+            comments.last().map(|c| Cursor {
+                key: sort.key(c),
+                id: c.id.clone(),
+            })
+        } else {
+            None
+        };
+
+        if cursor.is_none() {
+            let mut pinned = self
+                .find_comments_by_ids(
+                    moderation
+                        .values()
+                        .filter(|m| m.pinned_at > 0)
+                        .map(|m| m.comment_id.clone())
+                        .collect(),
+                )?
+                .into_iter()
+                .filter(|c| c.parent_id.is_none() && visible(c))
+                .collect::<Vec<_>>();
+            pinned.sort_by_key(|c| std::cmp::Reverse(moderation[&c.id].pinned_at));
+            comments.splice(0..0, pinned);
+        }
+
+        // one query per level of replies, imported comments only reply to
+        // imported ones while readers may reply to any. Hidden comments are
+        // left out along with their replies.
+        let mut replies = vec![];
+        {
+            let conn = &mut self.content_conn()?;
+            let mut parents = comments
+                .iter()
+                .filter(|c| c.user_id.is_none())
+                .map(|c| c.id.clone())
+                .collect::<Vec<_>>();
+            while !parents.is_empty() {
+                let level = ac::article_comment
+                    .filter(ac::parentCommentId.eq_any(&parents))
+                    .load::<models::ArticleComment>(conn)?
+                    .into_iter()
+                    .map(Comment::from)
+                    .filter(visible)
+                    .collect::<Vec<_>>();
+                parents = level.iter().map(|c| c.id.clone()).collect();
+                replies.extend(level);
+            }
+        }
+        let conn = &mut self.pool.get()?;
+        let mut parents = comments
+            .iter()
+            .chain(&replies)
+            .map(|c| c.id.clone())
+            .collect::<Vec<_>>();
+        while !parents.is_empty() {
+            let level = uc::user_comment
+                .filter(uc::parent_id.eq_any(&parents))
+                .load::<models::UserComment>(conn)?
+                .into_iter()
+                .map(Comment::from)
+                .filter(visible)
+                .collect::<Vec<_>>();
+            parents = level.iter().map(|c| c.id.clone()).collect();
+            replies.extend(level);
+        }
+        replies.sort_by(|a, b| CommentSort::Oldest.compare(a, b));
+
+        for comment in comments.iter_mut().chain(&mut replies) {
+            if let Some(m) = moderation.get(&comment.id) {
+                comment.moderate(m);
+            }
+        }
+        if !user_id.is_empty() {
+            self.mark_liked(conn, user_id, comments.iter_mut().chain(&mut replies))?;
+        }
+
+        Ok(CommentPage {
+            comments,
+            replies,
+            next_cursor,
+        })
+    }
+
+    /// Sets `liked` on the comments `user_id` liked.
+    fn mark_liked<'a>(
+        &self,
+        conn: &mut SqliteConnection,
+        user_id: &str,
+        comments: impl Iterator<Item = &'a mut Comment>,
+    ) -> Result<()> {
+        use crate::schema::comment_like::dsl;
+
+        let mut comments = comments.filter(|c| c.user_id.is_some()).collect::<Vec<_>>();
+        let ids = comments.iter().map(|c| c.id.as_str()).collect::<Vec<_>>();
+        let liked = dsl::comment_like
+            .filter(dsl::user_id.eq(user_id))
+            .filter(dsl::comment_id.eq_any(&ids))
+            .select(dsl::comment_id)
+            .load::<String>(conn)?;
+        for comment in comments.iter_mut() {
+            comment.liked = liked.contains(&comment.id);
+        }
+
+        Ok(())
+    }
+
+    /// Imported or reader's comment by id.
+    pub fn find_comment(&self, id: &str) -> Result<Option<Comment>> {
+        use crate::schema::{article_comment::dsl as ac, user_comment::dsl as uc};
+
+        let local = uc::user_comment
+            .filter(uc::id.eq(id))
+            .first::<models::UserComment>(&mut self.pool.get()?)
+            .optional()?;
+        if let Some(comment) = local {
+            return Ok(Some(comment.into()));
+        }
+        Ok(ac::article_comment
+            .filter(ac::id.eq(id))
+            .first::<models::ArticleComment>(&mut self.content_conn()?)
+            .optional()?
+            .map(Comment::from))
+    }
+
+    /// Imported and readers' comments by id, in no particular order.
+    pub fn find_comments_by_ids(&self, ids: Vec<String>) -> Result<Vec<Comment>> {
+        use crate::schema::{article_comment::dsl as ac, user_comment::dsl as uc};
+
+        if ids.is_empty() {
+            return Ok(vec![]);
+        }
+        let mut comments = ac::article_comment
+            .filter(ac::id.eq_any(&ids))
+            .load::<models::ArticleComment>(&mut self.content_conn()?)?
+            .into_iter()
+            .map(Comment::from)
+            .collect::<Vec<_>>();
+        comments.extend(
+            uc::user_comment
+                .filter(uc::id.eq_any(&ids))
+                .load::<models::UserComment>(&mut self.pool.get()?)?
+                .into_iter()
+                .map(Comment::from),
+        );
+
+        Ok(comments)
+    }
+
+    /// Applies a moderator's `action` to a comment and records it in the
+    /// moderation log, returns whether the comment exists.
+    pub fn moderate_comment(
+        &self,
+        moderator_id: &str,
+        id: &str,
+        action: ModerationAction,
+        reason: &str,
+    ) -> Result<bool> {
+        use crate::schema::{comment_flag, comment_moderation::dsl, comment_moderation_log};
+
+        let Some(comment) = self.find_comment(id)? else {
+            return Ok(false);
+        };
+        match action {
+            ModerationAction::Pin if comment.parent_id.is_some() => {
+                return Err(AppError::BadRequest(
+                    "only top level comments can be pinned".to_owned(),
+                )
+                .into());
+            }
+            ModerationAction::Delete if comment.user_id.is_none() => {
+                return Err(AppError::BadRequest(
+                    "imported comments can be hidden but not deleted".to_owned(),
+                )
+                .into());
+            }
+            _ => {}
+        }
+
+        let now = chrono::Utc::now().timestamp();
+        let conn = &mut self.pool.get()?;
+        conn.immediate_transaction(|conn| {
+            let current = dsl::comment_moderation
+                .filter(dsl::comment_id.eq(id))
+                .first::<models::CommentModeration>(conn)
+                .optional()?;
+            let mut state = current.unwrap_or_else(|| models::CommentModeration {
+                comment_id: id.to_owned(),
+                article_id: comment.article_id.clone().unwrap_or_default(),
+                hidden: false,
+                pinned_at: 0,
+                updated_at: now,
+                updated_by: moderator_id.to_owned(),
+            });
+            match action {
+                ModerationAction::Dismiss => {}
+                ModerationAction::Hide => state.hidden = true,
+                ModerationAction::Restore => state.hidden = false,
+                ModerationAction::Pin => state.pinned_at = now,
+                ModerationAction::Unpin => state.pinned_at = 0,
+                ModerationAction::Delete => {
+                    remove_comment(conn, id, None)?;
+                }
+            }
+            if !matches!(action, ModerationAction::Dismiss | ModerationAction::Delete) {
+                state.updated_at = now;
+                state.updated_by = moderator_id.to_owned();
+                diesel::insert_into(dsl::comment_moderation)
+                    .values(&state)
+                    .on_conflict(dsl::comment_id)
+                    .do_update()
+                    .set(&state)
+                    .execute(conn)?;
+            }
+
+            diesel::delete(comment_flag::table.filter(comment_flag::comment_id.eq(id)))
+                .execute(conn)?;
+            diesel::insert_into(comment_moderation_log::table)
+                .values(&models::NewCommentModerationLog {
+                    comment_id: id.to_owned(),
+                    moderator_id: moderator_id.to_owned(),
+                    action: action.as_str().to_owned(),
+                    reason: reason.to_owned(),
+                    created_at: now,
+                })
+                .execute(conn)?;
+
+            Ok(true)
+        })
+    }
+
+    /// A page of the comments waiting in `queue`, starting after `cursor`.
+    pub fn moderation_queue(
+        &self,
+        queue: ModerationQueue,
+        cursor: Option<&Cursor>,
+        limit: i64,
+    ) -> Result<ModerationPage> {
+        use crate::schema::{comment_flag, comment_moderation, user_comment::dsl as uc};
+        let limit = limit.clamp(1, comment::MAX_LIMIT);
+
+        // (id, flag count, sort key) in the order of the queue, the key is
+        // the time of the latest report or of the comment
+        let mut entries = match queue {
+            ModerationQueue::Flagged => {
+                let (key, id) = cursor.map_or((i64::MAX, ""), |c| (c.key, c.id.as_str()));
+                sql_query(
+                    "SELECT comment_id, COUNT(*) AS flags, MAX(created_at) AS flagged_at \
+                     FROM comment_flag GROUP BY comment_id \
+                     HAVING flagged_at < ? OR (flagged_at = ? AND comment_id > ?) \
+                     ORDER BY flagged_at DESC, comment_id ASC LIMIT ?",
+                )
+                .bind::<BigInt, _>(key)
+                .bind::<BigInt, _>(key)
+                .bind::<VarChar, _>(id)
+                .bind::<BigInt, _>(limit + 1)
+                .load::<models::FlaggedComment>(&mut self.pool.get()?)?
+                .into_iter()
+                .map(|f| (f.comment_id, f.flags, f.flagged_at))
+                .collect::<Vec<_>>()
+            }
+            ModerationQueue::Recent => page_query!(
+                uc::user_comment.into_boxed(),
+                uc::like_count,
+                uc::created_at,
+                uc::id,
+                CommentSort::Newest,
+                cursor
+            )
+            .select((uc::id, uc::created_at))
+            .limit(limit + 1)
+            .load::<(String, i64)>(&mut self.pool.get()?)?
+            .into_iter()
+            .map(|(id, created_at)| (id, 0, created_at))
+            .collect(),
+        };
+
+        let mut next_cursor = None;
+        if entries.len() as i64 > limit {
+            entries.truncate(limit as usize);
+            next_cursor = entries.last().map(|(id, _, key)| Cursor {
+                key: *key,
+                id: id.clone(),
+            });
+        }
+
+        let ids = entries
+            .iter()
+            .map(|(id, _, _)| id.clone())
+            .collect::<Vec<_>>();
+        let mut comments = self
+            .find_comments_by_ids(ids.clone())?
+            .into_iter()
+            .map(|c| (c.id.clone(), c))
+            .collect::<HashMap<_, _>>();
+        let conn = &mut self.pool.get()?;
+        for m in comment_moderation::table
+            .filter(comment_moderation::comment_id.eq_any(&ids))
+            .load::<models::CommentModeration>(conn)?
+        {
+            if let Some(comment) = comments.get_mut(&m.comment_id) {
+                comment.moderate(&m);
+            }
+        }
+        let mut reasons = HashMap::<String, Vec<String>>::new();
+        for (id, reason) in comment_flag::table
+            .filter(comment_flag::comment_id.eq_any(&ids))
+            .filter(comment_flag::reason.ne(""))
+            .order(comment_flag::created_at.desc())
+            .select((comment_flag::comment_id, comment_flag::reason))
+            .load::<(String, String)>(conn)?
+        {
+            reasons.entry(id).or_default().push(reason);
+        }
+
+        // a reported comment may be gone from the content since
+        let items = entries
+            .into_iter()
+            .filter_map(|(id, flag_count, key)| {
+                Some(ModerationItem {
+                    flag_reasons: reasons.remove(&id).unwrap_or_default(),
+                    comment: comments.remove(&id)?,
+                    flag_count,
+                    flagged_at: if flag_count > 0 { key } else { 0 },
+                })
+            })
+            .collect();
+
+        Ok(ModerationPage { items, next_cursor })
+    }
+
+    /// Moderation actions, latest first, with an id below `before` if given.
+    pub fn moderation_log(
+        &self,
+        before: Option<i32>,
+        limit: i64,
+    ) -> Result<Vec<models::CommentModerationLog>> {
+        use crate::schema::comment_moderation_log::dsl;
+        let conn = &mut self.pool.get()?;
+
+        let mut query = dsl::comment_moderation_log.into_boxed();
+        if let Some(before) = before {
+            query = query.filter(dsl::id.lt(before));
+        }
+        Ok(query
+            .order(dsl::id.desc())
+            .limit(limit.clamp(1, comment::MAX_LIMIT))
+            .load::<models::CommentModerationLog>(conn)?)
+    }
+
+    /// Saves a comment of `user_id`, unless they already posted as many as
+    /// the rate limit allows. A reply to another reader's comment notifies
+    /// them, the notification is returned along with the comment.
+    pub fn post_comment(
+        &self,
+        user_id: &str,
+        article_id: &str,
+        parent_id: Option<&str>,
+        nick_name: &str,
+        content: &str,
+    ) -> Result<(Comment, Option<models::Notification>)> {
+        use crate::schema::{comment_post, notification, user_comment::dsl};
+
+        self.check_article(article_id)?;
+        let mut parent_author = None;
+        if let Some(parent_id) = parent_id {
+            let parent = self.find_comment(parent_id)?.ok_or_else(|| {
+                AppError::BadRequest(format!("comment {parent_id} doesn't exist"))
+            })?;
+            if parent.article_id.as_deref() != Some(article_id) {
+                return Err(AppError::BadRequest(format!(
+                    "comment {parent_id} isn't on article {article_id}"
+                ))
+                .into());
+            }
+            if parent.deleted {
+                return Err(
+                    AppError::BadRequest(format!("comment {parent_id} was deleted")).into(),
+                );
+            }
+            parent_author = parent.user_id.filter(|author| author != user_id);
+        }
+
+        let now = chrono::Utc::now().timestamp();
+        let comment = models::UserComment {
+            id: format!("U{:016x}", rand::random::<u64>()),
+            article_id: article_id.to_owned(),
+            parent_id: parent_id.map(str::to_owned),
+            user_id: user_id.to_owned(),
+            nick_name: nick_name.to_owned(),
+            content: content.to_owned(),
+            like_count: 0,
+            created_at: now,
+            updated_at: 0,
+            deleted: false,
+        };
+        let conn = &mut self.pool.get()?;
+        // counted and inserted under the write lock, so concurrent posts
+        // can't both pass the limit. Posts are counted rather than comments,
+        // which may be deleted in between.
+        conn.immediate_transaction(|conn| {
+            let window = self.comment_limit.rate_window.as_secs() as i64;
+            diesel::delete(
+                comment_post::table
+                    .filter(comment_post::user_id.eq(user_id))
+                    .filter(comment_post::created_at.le(now - window)),
+            )
+            .execute(conn)?;
+            let recent = comment_post::table
+                .filter(comment_post::user_id.eq(user_id))
+                .count()
+                .get_result::<i64>(conn)?;
+            if recent >= self.comment_limit.rate_limit {
+                return Err(AppError::TooManyRequests(format!(
+                    "at most {} comments per {window}s",
+                    self.comment_limit.rate_limit
+                ))
+                .into());
+            }
+
+            diesel::insert_into(dsl::user_comment)
+                .values(&comment)
+                .execute(conn)?;
+            diesel::insert_into(comment_post::table)
+                .values((
+                    comment_post::user_id.eq(user_id),
+                    comment_post::created_at.eq(now),
+                ))
+                .execute(conn)?;
+            let notification = parent_author
+                .map(|author| {
+                    diesel::insert_into(notification::table)
+                        .values(&models::NewNotification {
+                            user_id: author,
+                            kind: NotificationKind::Reply.as_str().to_owned(),
+                            course_id: String::new(),
+                            article_id: comment.article_id.clone(),
+                            comment_id: comment.id.clone(),
+                            title: comment.nick_name.clone(),
+                            excerpt: comment
+                                .content
+                                .chars()
+                                .take(crate::notification::EXCERPT_LEN)
+                                .collect(),
+                            article_count: 0,
+                            created_at: now,
+                        })
+                        .get_result::<models::Notification>(conn)
+                })
+                .transpose()?;
+            Ok((comment.into(), notification))
+        })
+    }
+
+    /// Replaces the content of a comment of `user_id`, `None` when they
+    /// have no such comment.
+    pub fn edit_comment(&self, user_id: &str, id: &str, content: &str) -> Result<Option<Comment>> {
+        use crate::schema::user_comment::dsl;
+        let conn = &mut self.pool.get()?;
+
+        let comment = diesel::update(
+            dsl::user_comment
+                .filter(dsl::id.eq(id))
+                .filter(dsl::user_id.eq(user_id))
+                .filter(dsl::deleted.eq(false)),
+        )
+        .set((
+            dsl::content.eq(content),
+            dsl::updated_at.eq(chrono::Utc::now().timestamp()),
+        ))
+        .get_result::<models::UserComment>(conn)
+        .optional()?;
+        let Some(comment) = comment else {
+            return Ok(None);
+        };
+
+        let mut comment = Comment::from(comment);
+        self.mark_liked(conn, user_id, std::iter::once(&mut comment))?;
+        Ok(Some(comment))
+    }
+
+    /// Deletes a comment of `user_id`, returns whether they had one.
+    pub fn delete_comment(&self, user_id: &str, id: &str) -> Result<bool> {
+        let conn = &mut self.pool.get()?;
+        conn.immediate_transaction(|conn| Ok(remove_comment(conn, id, Some(user_id))?))
+    }
+
+    /// Likes or unlikes a reader's comment on behalf of `user_id`, imported
+    /// comments keep the count of their source.
+    pub fn like_comment(&self, user_id: &str, id: &str, like: bool) -> Result<Comment> {
+        use crate::schema::{comment_like, user_comment::dsl};
+        let mut conn = self.pool.get()?;
+
+        let comment = conn.immediate_transaction(|conn| {
+            let found = diesel::select(diesel::dsl::exists(
+                dsl::user_comment
+                    .filter(dsl::id.eq(id))
+                    .filter(dsl::deleted.eq(false)),
+            ))
+            .get_result::<bool>(conn)?;
+            if !found {
+                return Ok::<_, anyhow::Error>(None);
+            }
+
+            let changed = if like {
+                diesel::insert_or_ignore_into(comment_like::table)
+                    .values((
+                        comment_like::comment_id.eq(id),
+                        comment_like::user_id.eq(user_id),
+                        comment_like::created_at.eq(chrono::Utc::now().timestamp()),
+                    ))
+                    .execute(conn)?
+            } else {
+                diesel::delete(
+                    comment_like::table
+                        .filter(comment_like::comment_id.eq(id))
+                        .filter(comment_like::user_id.eq(user_id)),
+                )
+                .execute(conn)?
+            };
+            let delta = if like { 1 } else { -1 } * changed as i32;
+            Ok(Some(
+                diesel::update(dsl::user_comment.filter(dsl::id.eq(id)))
+                    .set(dsl::like_count.eq(dsl::like_count + delta))
+                    .get_result::<models::UserComment>(conn)?,
+            ))
+        })?;
+
+        match comment {
+            Some(comment) => {
+                let mut comment = Comment::from(comment);
+                comment.liked = like;
+                Ok(comment)
+            }
+            None => {
+                drop(conn);
+                Err(match self.find_comment(id)? {
+                    Some(c) if c.user_id.is_none() => {
+                        AppError::BadRequest("imported comments can't be liked".to_owned())
+                    }
+                    _ => AppError::NotFound("comment not found".to_owned()),
+                }
+                .into())
+            }
+        }
+    }
+
+    /// Reports a comment to the moderators, returns whether it exists. A
+    /// second report of the same user replaces the first.
+    pub fn flag_comment(&self, user_id: &str, id: &str, reason: &str) -> Result<bool> {
+        use crate::schema::comment_flag;
+
+        if self.find_comment(id)?.is_none() {
+            return Ok(false);
+        }
+        let conn = &mut self.pool.get()?;
+        diesel::replace_into(comment_flag::table)
+            .values(&models::CommentFlag {
+                comment_id: id.to_owned(),
+                user_id: user_id.to_owned(),
+                reason: reason.to_owned(),
+                created_at: chrono::Utc::now().timestamp(),
+            })
+            .execute(conn)?;
+
+        Ok(true)
+    }
+
+    /// Notifications of `user_id`, latest first, along with how many are
+    /// unread.
+    pub fn find_notifications(
+        &self,
+        user_id: &str,
+        before: Option<i32>,
+        limit: i64,
+        unread_only: bool,
+    ) -> Result<(Vec<models::Notification>, i64)> {
+        use crate::schema::notification::dsl;
+        let conn = &mut self.pool.get()?;
+
+        let mut query = dsl::notification
+            .filter(dsl::user_id.eq(user_id))
+            .into_boxed();
+        if let Some(before) = before {
+            query = query.filter(dsl::id.lt(before));
+        }
+        if unread_only {
+            query = query.filter(dsl::read_at.eq(0));
+        }
+        let notifications = query
+            .order(dsl::id.desc())
+            .limit(limit.clamp(1, notification::MAX_LIMIT))
+            .load::<models::Notification>(conn)?;
+        let unread = dsl::notification
+            .filter(dsl::user_id.eq(user_id))
+            .filter(dsl::read_at.eq(0))
+            .count()
+            .get_result::<i64>(conn)?;
+
+        Ok((notifications, unread))
+    }
+
+    /// Marks notifications of `user_id` as read, all of them when `ids` is
+    /// empty. Returns how many were unread.
+    pub fn read_notifications(&self, user_id: &str, ids: &[i32]) -> Result<usize> {
+        use crate::schema::notification::dsl;
         let conn = &mut self.pool.get()?;
-        let comments = dsl::article_comment
-            .filter(dsl::articleId.eq(article_id))
-            .load::<models::ArticleComment>(conn)?;
 
-        let replies = models::ArticleComment::belonging_to(&comments)
-            .load::<models::ArticleComment>(conn)?
-            .grouped_by(&comments);
+        let mut query = diesel::update(dsl::notification)
+            .filter(dsl::user_id.eq(user_id))
+            .filter(dsl::read_at.eq(0))
+            .into_boxed();
+        if !ids.is_empty() {
+            query = query.filter(dsl::id.eq_any(ids));
+        }
+        Ok(query
+            .set(dsl::read_at.eq(chrono::Utc::now().timestamp()))
+            .execute(conn)?)
+    }
+
+    /// Once an import ran, notifies the readers who studied a course of the
+    /// articles it got since the previous one. Courses seen for the first
+    /// time only have their articles counted.
+    pub fn notify_new_articles(&self) -> Result<Vec<models::Notification>> {
+        use crate::schema::{
+            article, course, course_article_count, notification, section, user_study_info,
+        };
+        use diesel::dsl::count_star;
+
+        let mut check = self.article_check.lock().unwrap();
+        let stamp = import_stamp(&self.storage_path);
+        if stamp.is_none() || stamp == *check {
+            return Ok(vec![]);
+        }
 
-        let data = replies.into_iter().zip(comments).collect::<Vec<_>>();
+        let (counts, titles) = {
+            let conn = &mut self.content_conn()?;
+            let counts = article::table
+                .inner_join(section::table)
+                .group_by(section::courseId)
+                .select((section::courseId, count_star()))
+                .load::<(String, i64)>(conn)?;
+            let titles = course::table
+                .select((course::id, course::title))
+                .load::<(String, String)>(conn)?
+                .into_iter()
+                .collect::<HashMap<_, _>>();
+            (counts, titles)
+        };
 
-        Ok(data)
+        let now = chrono::Utc::now().timestamp();
+        let conn = &mut self.pool.get()?;
+        let notifications = conn.immediate_transaction::<_, anyhow::Error, _>(|conn| {
+            let known = course_article_count::table
+                .select((
+                    course_article_count::course_id,
+                    course_article_count::article_count,
+                ))
+                .load::<(String, i32)>(conn)?
+                .into_iter()
+                .collect::<HashMap<_, _>>();
+
+            let mut grown = HashMap::new();
+            for (course_id, count) in counts {
+                let count = count as i32;
+                match known.get(&course_id) {
+                    Some(&before) if before == count => continue,
+                    Some(&before) if before < count => {
+                        grown.insert(course_id.clone(), count - before);
+                    }
+                    _ => {}
+                }
+                let row = models::CourseArticleCount {
+                    course_id,
+                    article_count: count,
+                    updated_at: now,
+                };
+                diesel::insert_into(course_article_count::table)
+                    .values(&row)
+                    .on_conflict(course_article_count::course_id)
+                    .do_update()
+                    .set(&row)
+                    .execute(conn)?;
+            }
+            if grown.is_empty() {
+                return Ok(vec![]);
+            }
+
+            let readers = user_study_info::table
+                .filter(user_study_info::course_id.eq_any(grown.keys()))
+                .select((user_study_info::course_id, user_study_info::user_id))
+                .distinct()
+                .load::<(String, String)>(conn)?;
+            let mut notifications = Vec::with_capacity(readers.len());
+            for (course_id, user_id) in readers {
+                let new = models::NewNotification {
+                    user_id,
+                    kind: NotificationKind::NewArticles.as_str().to_owned(),
+                    title: titles.get(&course_id).cloned().unwrap_or_default(),
+                    article_count: grown[&course_id],
+                    course_id,
+                    article_id: String::new(),
+                    comment_id: String::new(),
+                    excerpt: String::new(),
+                    created_at: now,
+                };
+                notifications.push(
+                    diesel::insert_into(notification::table)
+                        .values(&new)
+                        .get_result::<models::Notification>(conn)?,
+                );
+            }
+            Ok(notifications)
+        })?;
+
+        *check = stamp;
+        if !notifications.is_empty() {
+            log::info!(
+                "notified readers of new articles in {} courses",
+                notifications.len()
+            );
+        }
+        Ok(notifications)
     }
 
     pub fn find_user_role(&self, user_id: &str) -> Result<models::UserRole> {
@@ -216,15 +1406,19 @@ OFFSET
         let conn = &mut self.pool.get()?;
         let now = chrono::Utc::now().timestamp();
 
+        // the highest of the roles still valid
         let one_role = dsl::user_role
             .filter(dsl::user_id.eq(user_id))
             .filter(dsl::valid_before.gt(now))
+            .order(dsl::role.desc())
             .first::<models::UserRole>(conn)?;
         Ok(one_role)
     }
 
     pub fn save_study_info(&self, new_study_info: &models::UserStudyInfo) -> Result<()> {
         use crate::schema::user_study_info;
+
+        self.check_article(&new_study_info.article_id)?;
         let conn = &mut self.pool.get()?;
         diesel::replace_into(user_study_info::table)
             .values(new_study_info)
@@ -234,21 +1428,11 @@ OFFSET
     }
 
     pub fn test(&self) -> Result<()> {
-        use crate::schema::user_study_info::{self, dsl};
-        let conn = &mut self.pool.get()?;
-        let mut sql = user_study_info::table
-            .filter(dsl::user_id.eq("0698edd5-1ea8-4493-9092-003c4230516a"))
-            .into_boxed();
-
-        let article_id = "G183";
-        let course_id = "G100002201";
-
-        if !article_id.is_empty() {
-            sql = sql.filter(dsl::article_id.eq(article_id));
-        } else {
-            sql = sql.filter(dsl::course_id.eq(course_id));
-        }
-        let res = sql.load::<models::UserStudyInfo>(conn)?;
+        let res = self.find_user_study_info(
+            "0698edd5-1ea8-4493-9092-003c4230516a",
+            "G100002201",
+            "G183",
+        )?;
         println!("{:?}", res);
 
         Ok(())
@@ -277,6 +1461,35 @@ OFFSET
         Ok(res)
     }
 
+    /// Changes whenever the user saves progress or adds or removes a
+    /// bookmark. Bookmark ids only grow; progress is stamped with the time
+    /// it's saved at, and the total tells apart saves within a second.
+    pub fn get_user_watermark(&self, user_id: &str) -> Result<String> {
+        use crate::schema::{article_bookmark, user_study_info};
+        use diesel::dsl::count_star;
+
+        let conn = &mut self.pool.get()?;
+        let (studied, last_study, percents) = user_study_info::table
+            .filter(user_study_info::user_id.eq(user_id))
+            .select((
+                count_star(),
+                diesel::dsl::max(user_study_info::last_study_at),
+                diesel::dsl::sum(user_study_info::study_percent),
+            ))
+            .first::<(i64, Option<i64>, Option<f32>)>(conn)?;
+        let (bookmarks, last_bookmark) = article_bookmark::table
+            .filter(article_bookmark::user_id.eq(user_id))
+            .select((count_star(), diesel::dsl::max(article_bookmark::id)))
+            .first::<(i64, Option<i32>)>(conn)?;
+
+        Ok(format!(
+            "{studied}.{}.{}.{bookmarks}.{}",
+            last_study.unwrap_or_default(),
+            percents.unwrap_or_default(),
+            last_bookmark.unwrap_or_default()
+        ))
+    }
+
     pub fn save_connect_info(&self, new_connect_info: &models::WsConnectInfo) -> Result<()> {
         use crate::schema::ws_connect_info;
 
@@ -319,4 +1532,378 @@ FROM
 
         Ok(res.secs)
     }
+
+    pub fn find_bookmarks(
+        &self,
+        user_id: &str,
+        article_id: &str,
+    ) -> Result<Vec<models::ArticleBookmark>> {
+        use crate::schema::article_bookmark::dsl;
+        let conn = &mut self.pool.get()?;
+
+        Ok(dsl::article_bookmark
+            .filter(dsl::user_id.eq(user_id))
+            .filter(dsl::article_id.eq(article_id))
+            .order(dsl::created_at)
+            .load::<models::ArticleBookmark>(conn)?)
+    }
+
+    pub fn save_bookmark(
+        &self,
+        new_bookmark: &models::NewArticleBookmark,
+    ) -> Result<models::ArticleBookmark> {
+        use crate::schema::article_bookmark;
+
+        validate_bookmark(new_bookmark)?;
+        self.check_article(&new_bookmark.article_id)?;
+        let conn = &mut self.pool.get()?;
+
+        Ok(diesel::insert_into(article_bookmark::table)
+            .values(new_bookmark)
+            .get_result::<models::ArticleBookmark>(conn)?)
+    }
+
+    /// Deletes a bookmark owned by `user_id`, returns whether a row was removed.
+    pub fn delete_bookmark(&self, user_id: &str, id: i32) -> Result<bool> {
+        use crate::schema::article_bookmark::dsl;
+        let conn = &mut self.pool.get()?;
+
+        let n = diesel::delete(
+            dsl::article_bookmark
+                .filter(dsl::id.eq(id))
+                .filter(dsl::user_id.eq(user_id)),
+        )
+        .execute(conn)?;
+
+        Ok(n > 0)
+    }
+
+    /// Bookmark count of every article in a course which has at least one bookmark.
+    pub fn count_bookmarks_by_course(
+        &self,
+        user_id: &str,
+        course_id: &str,
+    ) -> Result<Vec<(String, i64)>> {
+        use crate::schema::{article, article_bookmark, section};
+        let conn = &mut self.pool.get()?;
+
+        Ok(article_bookmark::table
+            .inner_join(article::table.inner_join(section::table))
+            .filter(article_bookmark::user_id.eq(user_id))
+            .filter(section::courseId.eq(course_id))
+            .group_by(article_bookmark::article_id)
+            .select((article_bookmark::article_id, diesel::dsl::count_star()))
+            .load::<(String, i64)>(conn)?)
+    }
+}
+
+/// Async facade over [`Repo`]. Diesel and the storage files block, so every
+/// call is run on the blocking thread pool, never on the async workers or
+/// the actors.
+#[derive(Clone)]
+pub struct AsyncRepo(Arc<Repo>);
+
+impl AsyncRepo {
+    pub fn new(repo: Repo) -> Self {
+        AsyncRepo(Arc::new(repo))
+    }
+
+    pub async fn run<T, F>(&self, f: F) -> Result<T>
+    where
+        T: Send + 'static,
+        F: FnOnce(&Repo) -> Result<T> + Send + 'static,
+    {
+        let repo = self.0.clone();
+        web::block(move || f(&repo)).await?
+    }
+}
+
+/// Every field of a course, for the versions of responses holding it.
+fn course_parts(course: &models::Course) -> Vec<String> {
+    vec![
+        course.id.clone(),
+        course.title.clone(),
+        course.brief.clone(),
+        course.teacher_name.clone(),
+        course.teacher_title.clone(),
+        course.image.clone(),
+        course.article_count.to_string(),
+        course.purchased_count.clone(),
+        course.done.to_string(),
+        course.price.to_string(),
+    ]
+}
+
+/// Hex sha256 of `parts`, each terminated so they can't run into each other.
+fn hash_parts(parts: &[String]) -> String {
+    let mut hasher = Sha256::new();
+    for part in parts {
+        hasher.update(part.as_bytes());
+        hasher.update([0]);
+    }
+    format!("{:x}", hasher.finalize())
+}
+
+fn import_stamp(storage_path: &Path) -> Option<SystemTime> {
+    fs::metadata(storage_path.join(IMPORT_STAMP))
+        .and_then(|m| m.modified())
+        .ok()
+}
+
+/// Writes to a temporary file first, so readers never see a partial file.
+pub fn write_atomic(path: &Path, data: &[u8]) -> Result<()> {
+    if let Some(dir) = path.parent() {
+        fs::create_dir_all(dir)?;
+    }
+    let tmp = path.with_extension(format!("{}.tmp", rand::random::<u32>()));
+    fs::write(&tmp, data)?;
+    fs::rename(&tmp, path)?;
+    Ok(())
+}
+
+#[cfg(test)]
+fn test_db_config(path: &Path) -> DatabaseConfig {
+    DatabaseConfig {
+        url: path.to_string_lossy().into_owned(),
+        pool_size: 2,
+        connection_timeout: Duration::from_secs(5),
+        busy_timeout: Some(Duration::from_secs(1)),
+        enable_wal: true,
+        enable_foreign_keys: true,
+        synchronous: "NORMAL".to_owned(),
+        read_only: false,
+        attach_content: None,
+    }
+}
+
+#[cfg(test)]
+impl Repo {
+    /// A repo over a migrated database in `dir` holding the rows inserted by
+    /// `sql`, with the content files under `dir` and no caches.
+    pub fn for_test(dir: &Path, sql: &str) -> Self {
+        let config = test_db_config(&dir.join("hb.db"));
+        migrations::apply_all(&config.url).unwrap();
+        let pool = build_pool(&config).unwrap();
+        pool.get().unwrap().batch_execute(sql).unwrap();
+        Repo {
+            pool,
+            content_pool: None,
+            storage_path: dir.to_path_buf(),
+            storage: Box::new(storage::local::LocalStorage::open(dir.to_path_buf()).unwrap()),
+            courses: Cache::new("courses", 0, Duration::ZERO),
+            articles: Cache::new("articles", 0, Duration::ZERO),
+            catalog: Cache::new("catalog", 0, Duration::ZERO),
+            import_check: Mutex::new((Instant::now(), None)),
+            article_check: Mutex::new(None),
+            comment_limit: CommentConfig {
+                rate_limit: 100,
+                rate_window: Duration::from_secs(60),
+            },
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use {super::*, crate::testing::TempDir};
+
+    #[test]
+    fn attaches_a_read_only_content_database_in_rollback_mode() {
+        let dir = TempDir::new();
+        let content = dir.join("content.db");
+        let mut conn = SqliteConnection::establish(content.to_str().unwrap()).unwrap();
+        conn.batch_execute("CREATE TABLE course (id TEXT); INSERT INTO course VALUES ('c1');")
+            .unwrap();
+        drop(conn);
+
+        let pool = build_pool(&DatabaseConfig {
+            attach_content: Some(format!("file:{}?mode=ro", content.display())),
+            ..test_db_config(&dir.join("user.db"))
+        })
+        .unwrap();
+
+        let conn = &mut pool.get().unwrap();
+        let count = sql_query("SELECT count(*) AS n FROM content.course")
+            .get_result::<Count>(conn)
+            .unwrap();
+        assert_eq!(count.n, 1);
+        let mode = sql_query("PRAGMA content.journal_mode")
+            .load::<JournalMode>(conn)
+            .unwrap();
+        assert_eq!(mode[0].journal_mode, "delete");
+    }
+
+    #[test]
+    fn pages_through_tied_comments_without_gaps() {
+        let dir = TempDir::new();
+        let repo = Repo::for_test(
+            &dir,
+            r#"
+                INSERT INTO course (id, brief, teacherName, teacherTitle, image, purchasedCount, price, title)
+                    VALUES ('c1', '', '', '', '', '0', 0, 'Course');
+                INSERT INTO section (id, courseId, title) VALUES ('s1', 'c1', 'Section');
+                INSERT INTO article (id, sectionId, title) VALUES ('a1', 's1', 'Article');
+                INSERT INTO article_comment (id, content, likeCount, nickName, articleId, parentCommentId, createdAt) VALUES
+                    ('i1', '', 5, 'n', 'a1', NULL, 100),
+                    ('i2', '', 5, 'n', 'a1', NULL, 100),
+                    ('i3', '', 3, 'n', 'a1', NULL, 200),
+                    ('ir1', '', 0, 'n', 'a1', 'i1', 150);
+                INSERT INTO user_comment (id, article_id, parent_id, user_id, nick_name, content, like_count, created_at) VALUES
+                    ('u1', 'a1', NULL, 'r1', 'n', '', 5, 100),
+                    ('u2', 'a1', NULL, 'r1', 'n', '', 3, 100),
+                    ('u3', 'a1', NULL, 'r2', 'n', '', 5, 300),
+                    ('ur1', 'a1', 'ir1', 'r2', 'n', '', 0, 160),
+                    ('ur2', 'a1', 'u1', 'r2', 'n', '', 0, 170),
+                    ('ur3', 'a1', 'ur1', 'r1', 'n', '', 0, 180);
+                "#,
+        );
+
+        for (sort, expected) in [
+            (CommentSort::Likes, ["i1", "i2", "u1", "u3", "i3", "u2"]),
+            (CommentSort::Newest, ["u3", "i3", "i1", "i2", "u1", "u2"]),
+            (CommentSort::Oldest, ["i1", "i2", "u1", "u2", "i3", "u3"]),
+        ] {
+            let mut listed = vec![];
+            let mut cursor = None;
+            loop {
+                let page = repo
+                    .find_comments_by_article_id("a1", sort, cursor.as_ref(), 2, "", false)
+                    .unwrap();
+                assert!(page.comments.len() <= 2);
+                listed.extend(page.comments.into_iter().map(|c| c.id));
+                cursor = page.next_cursor;
+                if cursor.is_none() {
+                    break;
+                }
+            }
+            assert_eq!(listed, expected, "{sort:?}");
+        }
+
+        // the whole tree under the comments of the page, across both tables
+        let page = repo
+            .find_comments_by_article_id("a1", CommentSort::Likes, None, 2, "", false)
+            .unwrap();
+        let replies = page
+            .replies
+            .iter()
+            .map(|c| (c.id.as_str(), c.parent_id.as_deref().unwrap()))
+            .collect::<Vec<_>>();
+        assert_eq!(replies, [("ir1", "i1"), ("ur1", "ir1"), ("ur3", "ur1")]);
+        let cursor = page.next_cursor.unwrap();
+        assert_eq!(cursor.to_string(), "5.i2");
+        let page = repo
+            .find_comments_by_article_id("a1", CommentSort::Likes, Some(&cursor), 2, "", false)
+            .unwrap();
+        let replies = page
+            .replies
+            .iter()
+            .map(|c| c.id.as_str())
+            .collect::<Vec<_>>();
+        assert_eq!(replies, ["ur2"]);
+
+        // a like count beyond the column doesn't wrap around to 0
+        let cursor = Cursor {
+            key: 1 << 32,
+            id: "x".to_owned(),
+        };
+        let err = repo
+            .find_comments_by_article_id("a1", CommentSort::Likes, Some(&cursor), 2, "", false)
+            .unwrap_err();
+        assert!(
+            matches!(err.downcast_ref(), Some(AppError::BadRequest(_))),
+            "{err}"
+        );
+    }
+
+    #[test]
+    fn deleting_comments_does_not_lift_the_rate_limit() {
+        let dir = TempDir::new();
+        let mut repo = Repo::for_test(
+            &dir,
+            r#"
+                INSERT INTO course (id, brief, teacherName, teacherTitle, image, purchasedCount, price, title)
+                    VALUES ('c1', '', '', '', '', '0', 0, 'Course');
+                INSERT INTO section (id, courseId, title) VALUES ('s1', 'c1', 'Section');
+                INSERT INTO article (id, sectionId, title) VALUES ('a1', 's1', 'Article');
+            "#,
+        );
+        repo.comment_limit.rate_limit = 2;
+
+        let (first, _) = repo.post_comment("r1", "a1", None, "n", "one").unwrap();
+        repo.post_comment("r1", "a1", None, "n", "two").unwrap();
+        assert!(repo.delete_comment("r1", &first.id).unwrap());
+        assert!(repo.find_comment(&first.id).unwrap().is_none());
+
+        let err = repo
+            .post_comment("r1", "a1", None, "n", "three")
+            .unwrap_err();
+        assert!(
+            matches!(err.downcast_ref(), Some(AppError::TooManyRequests(_))),
+            "{err}"
+        );
+        // the limit is per user
+        repo.post_comment("r2", "a1", None, "n", "one").unwrap();
+    }
+
+    #[test]
+    fn rejects_empty_anchors_and_long_bookmarks() {
+        let bookmark = |anchor: &str, label: &str| models::NewArticleBookmark {
+            user_id: "r1".to_owned(),
+            article_id: "a1".to_owned(),
+            anchor: anchor.to_owned(),
+            label: label.to_owned(),
+            created_at: 0,
+        };
+        let long = |n| "字".repeat(n);
+
+        assert!(validate_bookmark(&bookmark("p-3", "")).is_ok());
+        assert!(validate_bookmark(&bookmark(&long(255), &long(100))).is_ok());
+        for (anchor, label) in [("", ""), (" ", "x"), (&long(256), ""), ("p-3", &long(101))] {
+            assert!(
+                matches!(
+                    validate_bookmark(&bookmark(anchor, label)),
+                    Err(AppError::BadRequest(_))
+                ),
+                "{anchor:?} {label:?}"
+            );
+        }
+    }
+
+    #[test]
+    fn only_maps_downloaded_image_srcs() {
+        let dir = TempDir::new();
+        fs::create_dir_all(dir.join("images")).unwrap();
+        fs::write(dir.join("images/image.png"), "png").unwrap();
+        let repo = Repo::for_test(&dir, "");
+
+        let file = dir.join("images/image.png");
+        for src in [
+            "/images/image.png",
+            "./images/image.png?v=1",
+            "/api/images/image.png",
+        ] {
+            assert_eq!(repo.find_local_image(src).as_ref(), Some(&file), "{src}");
+        }
+        for src in [
+            "https://cdn-a/x/image.png",
+            "image.png",
+            "/images/",
+            "/images/../images/image.png",
+            "/static/images/image.png",
+        ] {
+            assert_eq!(repo.find_local_image(src), None, "{src}");
+        }
+    }
+
+    #[derive(QueryableByName)]
+    struct Count {
+        #[diesel(sql_type = BigInt)]
+        n: i64,
+    }
+
+    #[derive(QueryableByName)]
+    struct JournalMode {
+        #[diesel(sql_type = diesel::sql_types::Text)]
+        journal_mode: String,
+    }
 }
@@ -0,0 +1,219 @@
+use {
+    crate::html,
+    ammonia::{Builder, UrlRelative},
+    std::collections::HashSet,
+};
+
+/// Bumped whenever the output changes, so cached articles are regenerated.
+pub const VERSION: u32 = 3;
+
+const TAGS: &[&str] = &[
+    "h1",
+    "h2",
+    "h3",
+    "h4",
+    "h5",
+    "h6",
+    "p",
+    "div",
+    "span",
+    "br",
+    "hr",
+    "b",
+    "strong",
+    "i",
+    "em",
+    "u",
+    "s",
+    "del",
+    "ins",
+    "mark",
+    "sup",
+    "sub",
+    "small",
+    "a",
+    "img",
+    "ul",
+    "ol",
+    "li",
+    "blockquote",
+    "pre",
+    "code",
+    "kbd",
+    "table",
+    "thead",
+    "tbody",
+    "tfoot",
+    "tr",
+    "th",
+    "td",
+    "caption",
+    "figure",
+    "figcaption",
+    "dl",
+    "dt",
+    "dd",
+];
+
+/// Elements which keep their `id`, so `href="#..."` links and bookmarks to
+/// a heading or paragraph still land somewhere.
+const ANCHOR_TAGS: &[&str] = &[
+    "h1",
+    "h2",
+    "h3",
+    "h4",
+    "h5",
+    "h6",
+    "p",
+    "div",
+    "span",
+    "a",
+    "li",
+    "blockquote",
+    "pre",
+    "table",
+    "figure",
+    "dt",
+];
+
+/// Query parameters only used for tracking, removed from links and images.
+/// Generic keys such as `from` or `ref` stay, sites use them for real
+/// content (`?ref=main` on GitHub).
+const TRACKING_PARAMS: &[&str] = &[
+    "spm",
+    "fbclid",
+    "gclid",
+    "share_token",
+    "share_source",
+    "scene",
+];
+
+/// Cleans crawled article html: code blocks are normalized, then everything
+/// outside the allow-list is dropped (scripts, event handlers, styles,
+/// `data-*` attributes) and tracking parameters are stripped from urls.
+pub fn sanitize(html: &str) -> String {
+    let normalized = html::to_xhtml(html, |src| Some(src.to_owned()));
+    builder().clean(&normalized).to_string()
+}
+
+fn builder() -> Builder<'static> {
+    let mut builder = Builder::empty();
+    builder
+        .add_tags(TAGS)
+        .clean_content_tags(HashSet::from(["script", "style"]))
+        .add_tag_attributes("a", &["href", "title", "name"])
+        .add_tag_attributes("img", &["src", "alt", "title", "width", "height"])
+        .add_tag_attributes("code", &["class"])
+        .add_tag_attributes("td", &["colspan", "rowspan"])
+        .add_tag_attributes("th", &["colspan", "rowspan"])
+        .add_tag_attributes("ol", &["start"])
+        .url_schemes(HashSet::from(["http", "https", "mailto"]))
+        .url_relative(UrlRelative::PassThrough)
+        .link_rel(Some("noopener noreferrer"))
+        .strip_comments(true)
+        .attribute_filter(|element, attribute, value| match (element, attribute) {
+            ("code", "class") => value
+                .split_whitespace()
+                .find(|c| c.starts_with("language-"))
+                .map(Into::into),
+            (_, "href") | (_, "src") => Some(strip_tracking_params(value).into()),
+            _ => Some(value.into()),
+        });
+    for tag in ANCHOR_TAGS {
+        builder.add_tag_attributes(tag, &["id"]);
+    }
+    builder
+}
+
+fn strip_tracking_params(url: &str) -> String {
+    let (url, fragment) = match url.split_once('#') {
+        Some((url, fragment)) => (url, Some(fragment)),
+        None => (url, None),
+    };
+    let Some((base, query)) = url.split_once('?') else {
+        return fragment.map_or(url.to_owned(), |f| format!("{url}#{f}"));
+    };
+
+    let query = query
+        .split('&')
+        .filter(|pair| {
+            let key = pair.split('=').next().unwrap_or_default();
+            !key.is_empty() && !key.starts_with("utm_") && !TRACKING_PARAMS.contains(&key)
+        })
+        .collect::<Vec<_>>()
+        .join("&");
+
+    let mut out = base.to_owned();
+    if !query.is_empty() {
+        out.push('?');
+        out.push_str(&query);
+    }
+    if let Some(fragment) = fragment {
+        out.push('#');
+        out.push_str(fragment);
+    }
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn keeps_anchor_targets() {
+        let html = sanitize(
+            r##"<h2 id="intro" class="x">Intro</h2><a name="fn1"></a><a href="#intro">up</a>"##,
+        );
+        assert_eq!(
+            html,
+            r##"<h2 id="intro">Intro</h2><a name="fn1" rel="noopener noreferrer"></a><a href="#intro" rel="noopener noreferrer">up</a>"##
+        );
+    }
+
+    #[test]
+    fn removes_scripts_and_event_handlers() {
+        let html = sanitize(
+            r#"<p onclick="steal()" style="color:red">a<script>steal()</script><img src="/images/a.png" onerror="steal()"></p><style>p{}</style>"#,
+        );
+        assert_eq!(html, r#"<p>a<img src="/images/a.png" alt=""></p>"#);
+    }
+
+    #[test]
+    fn drops_javascript_links() {
+        let html =
+            sanitize(r#"<a href="javascript:alert(1)">x</a><a href="JaVaScRiPt:alert(1)">y</a>"#);
+        assert_eq!(
+            html,
+            r#"<a rel="noopener noreferrer">x</a><a rel="noopener noreferrer">y</a>"#
+        );
+    }
+
+    #[test]
+    fn strips_tracking_params_only() {
+        assert_eq!(
+            strip_tracking_params("https://a.com/p?utm_source=x&id=1&spm=2&utm_medium=y#top"),
+            "https://a.com/p?id=1#top"
+        );
+        assert_eq!(
+            strip_tracking_params("https://github.com/o/r?ref=main&from=docs"),
+            "https://github.com/o/r?ref=main&from=docs"
+        );
+        assert_eq!(
+            strip_tracking_params("https://a.com/?fbclid=1"),
+            "https://a.com/"
+        );
+        let html = sanitize(r#"<img src="https://cdn.com/a.png?utm_campaign=x&w=200">"#);
+        assert_eq!(html, r#"<img src="https://cdn.com/a.png?w=200" alt="">"#);
+    }
+
+    #[test]
+    fn normalizes_code_classes() {
+        let html = sanitize(
+            r#"<pre class="brush"><code class="hljs lang-Rust">fn main() {}</code></pre><code class="inline">x</code>"#,
+        );
+        assert_eq!(
+            html,
+            r#"<pre><code class="language-rust">fn main() {}</code></pre><code>x</code>"#
+        );
+    }
+}
@@ -10,6 +10,17 @@ diesel::table! {
     }
 }
 
+diesel::table! {
+    article_bookmark (id) {
+        id -> Integer,
+        user_id -> Text,
+        article_id -> Text,
+        anchor -> Text,
+        label -> Text,
+        created_at -> BigInt,
+    }
+}
+
 diesel::table! {
     article_comment (id) {
         id -> Text,
@@ -18,6 +29,54 @@ diesel::table! {
         nickName -> Text,
         articleId -> Nullable<Text>,
         parentCommentId -> Nullable<Text>,
+        createdAt -> BigInt,
+    }
+}
+
+diesel::table! {
+    comment_flag (comment_id, user_id) {
+        comment_id -> Text,
+        user_id -> Text,
+        reason -> Text,
+        created_at -> BigInt,
+    }
+}
+
+diesel::table! {
+    comment_like (comment_id, user_id) {
+        comment_id -> Text,
+        user_id -> Text,
+        created_at -> BigInt,
+    }
+}
+
+diesel::table! {
+    comment_moderation (comment_id) {
+        comment_id -> Text,
+        article_id -> Text,
+        hidden -> Bool,
+        pinned_at -> BigInt,
+        updated_at -> BigInt,
+        updated_by -> Text,
+    }
+}
+
+diesel::table! {
+    comment_moderation_log (id) {
+        id -> Integer,
+        comment_id -> Text,
+        moderator_id -> Text,
+        action -> Text,
+        reason -> Text,
+        created_at -> BigInt,
+    }
+}
+
+diesel::table! {
+    comment_post (id) {
+        id -> Integer,
+        user_id -> Text,
+        created_at -> BigInt,
     }
 }
 
@@ -45,6 +104,30 @@ diesel::table! {
     }
 }
 
+diesel::table! {
+    course_article_count (course_id) {
+        course_id -> Text,
+        article_count -> Integer,
+        updated_at -> BigInt,
+    }
+}
+
+diesel::table! {
+    notification (id) {
+        id -> Integer,
+        user_id -> Text,
+        kind -> Text,
+        course_id -> Text,
+        article_id -> Text,
+        comment_id -> Text,
+        title -> Text,
+        excerpt -> Text,
+        article_count -> Integer,
+        created_at -> BigInt,
+        read_at -> BigInt,
+    }
+}
+
 diesel::table! {
     section (id) {
         id -> Text,
@@ -60,6 +143,21 @@ diesel::table! {
     }
 }
 
+diesel::table! {
+    user_comment (id) {
+        id -> Text,
+        article_id -> Text,
+        parent_id -> Nullable<Text>,
+        user_id -> Text,
+        nick_name -> Text,
+        content -> Text,
+        like_count -> Integer,
+        created_at -> BigInt,
+        updated_at -> BigInt,
+        deleted -> Bool,
+    }
+}
+
 diesel::table! {
     user_role (id) {
         user_id -> Text,
@@ -91,17 +189,28 @@ diesel::table! {
 }
 
 diesel::joinable!(article -> section (sectionId));
+diesel::joinable!(article_bookmark -> article (article_id));
 diesel::joinable!(article_comment -> article (articleId));
+diesel::joinable!(user_comment -> article (article_id));
 diesel::joinable!(user_study_info -> article (article_id));
 diesel::joinable!(user_study_info -> course (course_id));
 
 diesel::allow_tables_to_appear_in_same_query!(
     article,
+    article_bookmark,
     article_comment,
+    comment_flag,
+    comment_like,
+    comment_moderation,
+    comment_moderation_log,
+    comment_post,
     course,
+    course_article_count,
     course_tend,
+    notification,
     section,
     user,
+    user_comment,
     user_role,
     user_study_info,
     ws_connect_info,
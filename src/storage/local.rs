@@ -0,0 +1,143 @@
+use {
+    super::Storage,
+    anyhow::{anyhow, Context, Result},
+    log::*,
+    std::{
+        fs, io,
+        path::{Path, PathBuf},
+        time::UNIX_EPOCH,
+    },
+    zstd::zstd_safe,
+};
+
+/// Dictionary shared by the compressed articles, under `STORAGE_PATH`.
+pub const DICTIONARY: &str = "contents.dict";
+const DICTIONARY_SIZE: usize = 112 * 1024;
+const LEVEL: i32 = 19;
+
+/// Files under `STORAGE_PATH`, as written by the crawler. A file may be
+/// replaced by a zstd compressed `{name}.zst`, which is decompressed on read.
+pub struct LocalStorage {
+    root: PathBuf,
+    dictionary: Option<Vec<u8>>,
+}
+
+impl LocalStorage {
+    pub fn open(root: PathBuf) -> Result<Self> {
+        let dictionary = match fs::read(root.join(DICTIONARY)) {
+            Ok(dict) => Some(dict),
+            Err(e) if e.kind() == io::ErrorKind::NotFound => None,
+            Err(e) => return Err(e).context(format!("failed to read {DICTIONARY}")),
+        };
+        Ok(LocalStorage { root, dictionary })
+    }
+
+    /// The file at `key` as stored, compressed or not.
+    fn path(&self, key: &str) -> Result<(PathBuf, bool)> {
+        let plain = self.root.join(key);
+        if plain.is_file() {
+            return Ok((plain, false));
+        }
+        let compressed = self.root.join(format!("{key}.zst"));
+        if compressed.is_file() {
+            return Ok((compressed, true));
+        }
+        Err(io::Error::new(
+            io::ErrorKind::NotFound,
+            format!("{} not exist", plain.to_string_lossy()),
+        )
+        .into())
+    }
+
+    fn decompress(&self, data: &[u8]) -> Result<Vec<u8>> {
+        let Some(id) = zstd_safe::get_dict_id_from_frame(data) else {
+            return Ok(zstd::decode_all(data)?);
+        };
+        match &self.dictionary {
+            Some(dict) if zstd_safe::get_dict_id_from_dict(dict) == Some(id) => {
+                let mut out = vec![];
+                io::copy(&mut zstd::Decoder::with_dictionary(data, dict)?, &mut out)?;
+                Ok(out)
+            }
+            _ => Err(anyhow!(
+                "needs zstd dictionary {id}, {DICTIONARY} is missing or a different one"
+            )),
+        }
+    }
+}
+
+impl Storage for LocalStorage {
+    fn read(&self, key: &str) -> Result<Vec<u8>> {
+        let (path, compressed) = self.path(key)?;
+        let data = fs::read(&path).with_context(|| format!("failed to read {}", path.display()))?;
+        if !compressed {
+            return Ok(data);
+        }
+        self.decompress(&data)
+            .with_context(|| format!("failed to decompress {}", path.display()))
+    }
+
+    fn version(&self, key: &str) -> Result<String> {
+        let (path, _) = self.path(key)?;
+        let meta = fs::metadata(&path)?;
+        let modified = meta.modified()?.duration_since(UNIX_EPOCH)?;
+        Ok(format!("{}-{}", modified.as_nanos(), meta.len()))
+    }
+}
+
+/// Replaces every `contents/{id}.html` under `root` by a compressed
+/// `{id}.html.zst`, with a dictionary trained on the articles when
+/// `with_dictionary` and none exists yet. Every file is decompressed and
+/// compared to the original before the original is removed.
+pub fn compress_contents(root: &Path, with_dictionary: bool) -> Result<()> {
+    let dir = root.join("contents");
+    let mut files = vec![];
+    for entry in fs::read_dir(&dir).with_context(|| format!("can't read {}", dir.display()))? {
+        let path = entry?.path();
+        if path.extension().is_some_and(|ext| ext == "html") && path.is_file() {
+            files.push(path);
+        }
+    }
+    files.sort();
+
+    let dict_path = root.join(DICTIONARY);
+    if with_dictionary && !dict_path.is_file() {
+        info!("training a dictionary on {} articles", files.len());
+        let dict = zstd::dict::from_files(&files, DICTIONARY_SIZE)?;
+        crate::repo::write_atomic(&dict_path, &dict)?;
+    }
+    // reopened so the verification reads through the same code as the server
+    let storage = LocalStorage::open(root.to_owned())?;
+    let mut compressor = match &storage.dictionary {
+        Some(dict) => zstd::bulk::Compressor::with_dictionary(LEVEL, dict)?,
+        None => zstd::bulk::Compressor::new(LEVEL)?,
+    };
+
+    let (mut before, mut after) = (0, 0);
+    for path in &files {
+        let name = path
+            .file_name()
+            .and_then(|n| n.to_str())
+            .unwrap_or_default();
+        let key = format!("contents/{name}");
+        let data = fs::read(path)?;
+        let compressed = compressor.compress(&data)?;
+        crate::repo::write_atomic(&root.join(format!("{key}.zst")), &compressed)?;
+
+        let zst = fs::read(root.join(format!("{key}.zst")))?;
+        if storage.decompress(&zst)? != data {
+            return Err(anyhow!(
+                "{key} doesn't match after compression, kept the original"
+            ));
+        }
+        fs::remove_file(path)?;
+        before += data.len();
+        after += compressed.len();
+    }
+
+    info!(
+        "compressed {} articles from {before} to {after} bytes",
+        files.len()
+    );
+    Ok(())
+}
@@ -0,0 +1,48 @@
+use {
+    crate::config::{Config, StorageConfig},
+    anyhow::Result,
+};
+
+pub mod local;
+pub mod pack;
+pub mod s3;
+
+/// Where the crawled content files live, addressed by keys relative to
+/// `STORAGE_PATH` such as `contents/{id}.html` or `courses/{id}.html`.
+pub trait Storage: Send + Sync {
+    /// Content of the file at `key`. A missing file fails with an
+    /// `io::ErrorKind::NotFound` error, which is answered with a 404.
+    fn read(&self, key: &str) -> Result<Vec<u8>>;
+
+    /// Opaque tag which changes whenever the file does, used to invalidate
+    /// files derived from it.
+    fn version(&self, key: &str) -> Result<String>;
+
+    fn read_to_string(&self, key: &str) -> Result<String> {
+        Ok(String::from_utf8(self.read(key)?)?)
+    }
+}
+
+pub fn open(config: &Config) -> Result<Box<dyn Storage>> {
+    Ok(match &config.storage {
+        StorageConfig::Local => Box::new(local::LocalStorage::open(config.storage_path.clone())?),
+        StorageConfig::Pack { path } => Box::new(pack::PackStorage::open(path)?),
+        StorageConfig::S3(s3) => Box::new(s3::S3Storage::new(s3.clone())),
+    })
+}
+
+/// Keys of the content files, the ones copied into a pack.
+pub const CONTENT_DIRS: &[&str] = &["contents", "courses"];
+
+/// Whether `e` is the error of a missing file.
+pub fn is_not_found(e: &anyhow::Error) -> bool {
+    e.chain().any(|cause| {
+        cause
+            .downcast_ref::<std::io::Error>()
+            .is_some_and(|e| e.kind() == std::io::ErrorKind::NotFound)
+    })
+}
+
+fn not_found(key: &str) -> anyhow::Error {
+    std::io::Error::new(std::io::ErrorKind::NotFound, format!("{key} not exist")).into()
+}
@@ -0,0 +1,165 @@
+//! Content files in a single archive, each compressed on its own so any of
+//! them can be read without touching the others:
+//!
+//! ```text
+//! MAGIC | zstd frame of every file | zstd compressed json index | footer
+//! ```
+//!
+//! The footer holds the offset and length of the index as little endian
+//! u64s, followed by `MAGIC` again.
+
+use {
+    super::{local::LocalStorage, not_found, Storage, CONTENT_DIRS},
+    anyhow::{anyhow, Context, Result},
+    serde::{Deserialize, Serialize},
+    sha2::{Digest, Sha256},
+    std::{
+        collections::HashMap,
+        fs::{self, File},
+        io::{BufWriter, Read, Seek, SeekFrom, Write},
+        path::Path,
+        sync::Mutex,
+    },
+};
+
+const MAGIC: &[u8; 8] = b"HBPACK01";
+const FOOTER_LEN: u64 = 8 + 8 + MAGIC.len() as u64;
+const LEVEL: i32 = 19;
+
+#[derive(Debug, Serialize, Deserialize)]
+struct Entry {
+    offset: u64,
+    len: u64,
+    /// Hex sha256 of the uncompressed file.
+    hash: String,
+}
+
+pub struct PackStorage {
+    /// Seeked by every read, a positional read isn't portable.
+    file: Mutex<File>,
+    index: HashMap<String, Entry>,
+}
+
+impl PackStorage {
+    pub fn open(path: &Path) -> Result<Self> {
+        let mut file =
+            File::open(path).with_context(|| format!("failed to open {}", path.display()))?;
+        let size = file.metadata()?.len();
+        if size < MAGIC.len() as u64 + FOOTER_LEN {
+            return Err(anyhow!("{} is not a content pack", path.display()));
+        }
+
+        let mut footer = [0u8; FOOTER_LEN as usize];
+        read_exact_at(&mut file, &mut footer, size - FOOTER_LEN)?;
+        if &footer[16..] != MAGIC {
+            return Err(anyhow!("{} is not a content pack", path.display()));
+        }
+        let offset = u64::from_le_bytes(footer[..8].try_into()?);
+        let len = u64::from_le_bytes(footer[8..16].try_into()?);
+        let mut index = vec![0u8; len as usize];
+        read_exact_at(&mut file, &mut index, offset)?;
+        let index = serde_json::from_slice(&zstd::decode_all(&index[..])?)
+            .with_context(|| format!("invalid index in {}", path.display()))?;
+
+        Ok(PackStorage {
+            file: Mutex::new(file),
+            index,
+        })
+    }
+}
+
+fn read_exact_at(file: &mut File, buf: &mut [u8], offset: u64) -> std::io::Result<()> {
+    file.seek(SeekFrom::Start(offset))?;
+    file.read_exact(buf)
+}
+
+impl Storage for PackStorage {
+    fn read(&self, key: &str) -> Result<Vec<u8>> {
+        let entry = self.index.get(key).ok_or_else(|| not_found(key))?;
+        let mut data = vec![0u8; entry.len as usize];
+        {
+            let mut file = self
+                .file
+                .lock()
+                .map_err(|_| anyhow!("pack file lock poisoned"))?;
+            read_exact_at(&mut file, &mut data, entry.offset)?;
+        }
+        Ok(zstd::decode_all(&data[..])?)
+    }
+
+    fn version(&self, key: &str) -> Result<String> {
+        let entry = self.index.get(key).ok_or_else(|| not_found(key))?;
+        Ok(entry.hash.clone())
+    }
+}
+
+/// Packs the content files under `root` into `out`, returns the number of
+/// files written.
+pub fn write(root: &Path, out: &Path) -> Result<usize> {
+    // decompresses the files `compress-contents` replaced
+    let source = LocalStorage::open(root.to_owned())?;
+    let tmp = out.with_extension(format!("{}.tmp", rand::random::<u32>()));
+    let mut writer = BufWriter::new(File::create(&tmp)?);
+    writer.write_all(MAGIC)?;
+    let mut offset = MAGIC.len() as u64;
+    let mut index = HashMap::new();
+
+    for dir in CONTENT_DIRS {
+        let Ok(entries) = fs::read_dir(root.join(dir)) else {
+            continue;
+        };
+        for entry in entries {
+            let entry = entry?;
+            if !entry.file_type()?.is_file() {
+                continue;
+            }
+            let Some(name) = entry.file_name().to_str().map(str::to_owned) else {
+                continue;
+            };
+            let name = name.strip_suffix(".zst").unwrap_or(&name);
+            let data = source.read(&format!("{dir}/{name}"))?;
+            let compressed = zstd::encode_all(&data[..], LEVEL)?;
+            writer.write_all(&compressed)?;
+            index.insert(
+                format!("{dir}/{name}"),
+                Entry {
+                    offset,
+                    len: compressed.len() as u64,
+                    hash: format!("{:x}", Sha256::digest(&data)),
+                },
+            );
+            offset += compressed.len() as u64;
+        }
+    }
+
+    let index_data = zstd::encode_all(&serde_json::to_vec(&index)?[..], LEVEL)?;
+    writer.write_all(&index_data)?;
+    writer.write_all(&offset.to_le_bytes())?;
+    writer.write_all(&(index_data.len() as u64).to_le_bytes())?;
+    writer.write_all(MAGIC)?;
+    writer.into_inner()?.sync_all()?;
+    fs::rename(&tmp, out)?;
+
+    Ok(index.len())
+}
+
+#[cfg(test)]
+mod tests {
+    use {super::*, crate::testing::TempDir};
+
+    #[test]
+    fn reads_back_packed_files() {
+        let root = TempDir::new();
+        fs::create_dir_all(root.join("contents")).unwrap();
+        fs::create_dir_all(root.join("courses")).unwrap();
+        fs::write(root.join("contents/a.html"), "<p>a</p>").unwrap();
+        fs::write(root.join("courses/c.html"), "<p>c</p>").unwrap();
+
+        let out = root.join("content.pack");
+        assert_eq!(write(&root, &out).unwrap(), 2);
+        let pack = PackStorage::open(&out).unwrap();
+        assert_eq!(pack.read_to_string("contents/a.html").unwrap(), "<p>a</p>");
+        assert_eq!(pack.read_to_string("courses/c.html").unwrap(), "<p>c</p>");
+        assert!(pack.read("contents/b.html").is_err());
+    }
+}
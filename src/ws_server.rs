@@ -1,10 +1,14 @@
 use {
-    crate::{models, pb, repo::Repo},
+    crate::{
+        format::{self, ContentFormat},
+        models, pb,
+        repo::{AsyncRepo, Repo, IMPORT_CHECK_INTERVAL},
+    },
     actix::prelude::*,
     actix_web_actors::ws::CloseReason,
     anyhow::Result,
     rand::{self, rngs::ThreadRng, Rng},
-    std::{collections::HashMap, sync::Arc},
+    std::collections::HashMap,
 };
 
 #[derive(Message)]
@@ -12,6 +16,7 @@ use {
 pub enum ServerMessage {
     // Text(String),
     Close(Option<CloseReason>),
+    Notification(pb::Notification),
 }
 
 /// New chat session is created
@@ -36,10 +41,45 @@ pub struct Disconnect {
 #[rtype(result = "Result<pb::Article>")]
 pub struct GetArticleDetail {
     pub article_id: String,
+    pub format: ContentFormat,
     pub session_id: usize,
     pub user_id: String,
 }
 
+#[derive(Message)]
+#[rtype(result = "Result<pb::BookmarkList>")]
+pub struct ListBookmarks {
+    pub article_id: String,
+    pub session_id: usize,
+    pub user_id: String,
+}
+
+#[derive(Message)]
+#[rtype(result = "Result<pb::Bookmark>")]
+pub struct AddBookmark {
+    pub article_id: String,
+    pub anchor: String,
+    pub label: String,
+    pub session_id: usize,
+    pub user_id: String,
+}
+
+#[derive(Message)]
+#[rtype(result = "Result<bool>")]
+pub struct DeleteBookmark {
+    pub id: i32,
+    pub session_id: usize,
+    pub user_id: String,
+}
+
+/// Pushes a notification to the sessions of `user_id`, if connected.
+#[derive(Message)]
+#[rtype(result = "()")]
+pub struct Notify {
+    pub user_id: String,
+    pub notification: pb::Notification,
+}
+
 #[derive(Debug)]
 struct SessionInfo {
     user_id: String,
@@ -49,17 +89,33 @@ struct SessionInfo {
 pub struct WsServer {
     sessions: HashMap<usize, SessionInfo>,
     rng: ThreadRng,
-    repo: Arc<Repo>,
+    repo: AsyncRepo,
 }
 
 impl WsServer {
-    pub fn new(repo: Arc<Repo>) -> WsServer {
+    pub fn new(repo: AsyncRepo) -> WsServer {
         WsServer {
             sessions: HashMap::new(),
             rng: rand::thread_rng(),
             repo,
         }
     }
+
+    fn notify(&self, user_id: &str, notification: pb::Notification) {
+        for session in self.sessions.values().filter(|s| s.user_id == user_id) {
+            session
+                .addr
+                .do_send(ServerMessage::Notification(notification.clone()));
+        }
+    }
+
+    fn check_session(&self, session_id: usize) -> Result<()> {
+        if self.sessions.contains_key(&session_id) {
+            Ok(())
+        } else {
+            Err(anyhow::anyhow!("Invalid session id"))
+        }
+    }
 }
 
 /// Make actor from `WsServer`
@@ -67,6 +123,35 @@ impl Actor for WsServer {
     /// We are going to use simple Context, we just need ability to communicate
     /// with other actors.
     type Context = Context<Self>;
+
+    /// Looks for new articles after every import, to notify the readers
+    /// studying their courses.
+    fn started(&mut self, ctx: &mut Context<Self>) {
+        ctx.run_interval(IMPORT_CHECK_INTERVAL, |act, ctx| {
+            let repo = act.repo.clone();
+            async move { repo.run(|repo| repo.notify_new_articles()).await }
+                .into_actor(act)
+                .map(|res, act, _| match res {
+                    Ok(notifications) => {
+                        for n in notifications {
+                            let user_id = n.user_id.clone();
+                            act.notify(&user_id, n.into());
+                        }
+                    }
+                    Err(e) => log::warn!("failed to notify of new articles: {e}"),
+                })
+                .spawn(ctx);
+        });
+    }
+}
+
+/// Handler for Notify message.
+impl Handler<Notify> for WsServer {
+    type Result = ();
+
+    fn handle(&mut self, msg: Notify, _: &mut Context<Self>) {
+        self.notify(&msg.user_id, msg.notification);
+    }
 }
 
 /// Handler for Connect message.
@@ -116,49 +201,121 @@ impl Handler<Disconnect> for WsServer {
             println!("Removed session");
             println!("login count: {}", self.sessions.len());
 
-            let _ = self
-                .repo
-                .save_connect_info(&models::WsConnectInfo {
-                    id: 0,
-                    user_id: session.user_id,
-                    start_at: msg.start_at.timestamp(),
-                    end_at: chrono::Utc::now().timestamp(),
-                })
-                .inspect_err(|e| eprintln!("failed to save connect info {e}"));
+            let info = models::WsConnectInfo {
+                id: 0,
+                user_id: session.user_id,
+                start_at: msg.start_at.timestamp(),
+                end_at: chrono::Utc::now().timestamp(),
+            };
+            let repo = self.repo.clone();
+            actix::spawn(async move {
+                let _ = repo
+                    .run(move |repo| repo.save_connect_info(&info))
+                    .await
+                    .inspect_err(|e| eprintln!("failed to save connect info {e}"));
+            });
         }
     }
 }
 
-/// Handler for Disconnect message.
+/// Article with its content, section and course, without per-user state.
+pub fn article_detail(repo: &Repo, article_id: &str, format: ContentFormat) -> Result<pb::Article> {
+    let (article, content) = repo.get_article_detail(article_id)?;
+    let section = repo.find_section_by_id(&article.section_id)?;
+    let course = repo.find_course_by_id(&section.course_id)?;
+    let mut res: pb::Article = article.into();
+    res.content = format::convert(&content, format);
+    res.format = pb::ContentFormat::from(format) as i32;
+    res.section = Some(section.into());
+    res.course = Some(course.into());
+
+    Ok(res)
+}
+
+/// Handler for GetArticleDetail message.
 impl Handler<GetArticleDetail> for WsServer {
-    type Result = Result<pb::Article>;
+    type Result = ResponseFuture<Result<pb::Article>>;
 
     fn handle(&mut self, msg: GetArticleDetail, _: &mut Context<Self>) -> Self::Result {
-        if self
-            .sessions
-            .iter()
-            .find(|(&session_id, _)| session_id == msg.session_id)
-            .is_some()
-        {
-            let (article, content) = self.repo.get_article_detail(&msg.article_id)?;
-            let section = self.repo.find_section_by_id(&article.section_id)?;
-            let course = self.repo.find_course_by_id(&section.course_id)?;
-            let mut res: pb::Article = article.into();
-            res.content = content;
-            res.section = Some(section.into());
-            res.course = Some(course.into());
-            if let Ok(info) = self.repo.find_user_study_info(&msg.user_id, "", &res.id) {
-                res.study_info = info.first().and_then(|info| {
-                    Some(pb::StudyInfo {
+        let checked = self.check_session(msg.session_id);
+        let repo = self.repo.clone();
+
+        Box::pin(async move {
+            checked?;
+            repo.run(move |repo| {
+                let mut res = article_detail(repo, &msg.article_id, msg.format)?;
+                if let Ok(info) = repo.find_user_study_info(&msg.user_id, "", &res.id) {
+                    res.study_info = info.first().map(|info| pb::StudyInfo {
                         percent: info.study_percent,
                         last_study_at: info.last_study_at as u64,
-                    })
-                });
-            }
+                    });
+                }
+                if let Ok(bookmarks) = repo.find_bookmarks(&msg.user_id, &res.id) {
+                    res.bookmark_count = bookmarks.len() as u32;
+                }
 
-            Ok(res)
-        } else {
-            Err(anyhow::anyhow!("Invalid session id"))
-        }
+                Ok(res)
+            })
+            .await
+        })
+    }
+}
+
+/// Handler for ListBookmarks message.
+impl Handler<ListBookmarks> for WsServer {
+    type Result = ResponseFuture<Result<pb::BookmarkList>>;
+
+    fn handle(&mut self, msg: ListBookmarks, _: &mut Context<Self>) -> Self::Result {
+        let checked = self.check_session(msg.session_id);
+        let repo = self.repo.clone();
+
+        Box::pin(async move {
+            checked?;
+            let bookmarks = repo
+                .run(move |repo| repo.find_bookmarks(&msg.user_id, &msg.article_id))
+                .await?;
+            Ok(pb::BookmarkList {
+                bookmarks: bookmarks.into_iter().map(|b| b.into()).collect(),
+            })
+        })
+    }
+}
+
+/// Handler for AddBookmark message.
+impl Handler<AddBookmark> for WsServer {
+    type Result = ResponseFuture<Result<pb::Bookmark>>;
+
+    fn handle(&mut self, msg: AddBookmark, _: &mut Context<Self>) -> Self::Result {
+        let checked = self.check_session(msg.session_id);
+        let repo = self.repo.clone();
+        let bookmark = models::NewArticleBookmark {
+            user_id: msg.user_id,
+            article_id: msg.article_id,
+            anchor: msg.anchor,
+            label: msg.label,
+            created_at: chrono::Utc::now().timestamp(),
+        };
+
+        Box::pin(async move {
+            checked?;
+            let saved = repo.run(move |repo| repo.save_bookmark(&bookmark)).await?;
+            Ok(saved.into())
+        })
+    }
+}
+
+/// Handler for DeleteBookmark message.
+impl Handler<DeleteBookmark> for WsServer {
+    type Result = ResponseFuture<Result<bool>>;
+
+    fn handle(&mut self, msg: DeleteBookmark, _: &mut Context<Self>) -> Self::Result {
+        let checked = self.check_session(msg.session_id);
+        let repo = self.repo.clone();
+
+        Box::pin(async move {
+            checked?;
+            repo.run(move |repo| repo.delete_bookmark(&msg.user_id, msg.id))
+                .await
+        })
     }
 }
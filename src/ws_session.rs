@@ -1,9 +1,15 @@
 use {
-    crate::ws_server,
+    crate::{format::ContentFormat, ws_server},
     actix::prelude::*,
     actix_web_actors::ws,
+    flate2::{write::GzEncoder, Compression},
     prost::Message,
-    std::time::{Duration, Instant},
+    serde::Deserialize,
+    std::{
+        io::Write as _,
+        time::{Duration, Instant},
+    },
+    utoipa::ToSchema,
 };
 
 /// How often heartbeat pings are sent
@@ -12,6 +18,51 @@ const HEARTBEAT_INTERVAL: Duration = Duration::from_secs(5);
 /// How long before lack of client response causes a timeout
 const CLIENT_TIMEOUT: Duration = Duration::from_secs(10);
 
+/// Smaller messages are sent as is, compressing them saves next to nothing.
+const MIN_COMPRESS_LEN: usize = 512;
+
+/// Set in the flag byte of a frame whose message is compressed.
+const FLAG_COMPRESSED: u8 = 1;
+/// Set in the flag byte of a frame pushed by the server, holding a
+/// `pb::Notification`, rather than answering a command.
+const FLAG_PUSH: u8 = 2;
+
+/// Compression of binary frames, asked for with `?compress=` when connecting.
+///
+/// Once given every binary frame starts with a flag byte: bit 1 (`0x01`) is
+/// set when the rest of the frame is compressed, bit 2 (`0x02`) when it's a
+/// pushed `Notification` rather than the answer to a command. Without it the
+/// frames are the bare messages, as before, and notifications aren't pushed.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize, ToSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum FrameCompression {
+    /// Flag bytes only, messages are never compressed.
+    None,
+    /// Decompressable by browsers with `DecompressionStream("gzip")`.
+    Gzip,
+    Zstd,
+}
+
+impl FrameCompression {
+    /// `data` compressed after the flag byte, `None` when it isn't worth it.
+    fn compress(self, data: &[u8], flags: u8) -> Option<std::io::Result<Vec<u8>>> {
+        if data.len() < MIN_COMPRESS_LEN {
+            return None;
+        }
+        let mut out = vec![flags | FLAG_COMPRESSED];
+        match self {
+            FrameCompression::None => None,
+            FrameCompression::Gzip => {
+                let mut encoder = GzEncoder::new(out, Compression::default());
+                Some(encoder.write_all(data).and_then(|_| encoder.finish()))
+            }
+            FrameCompression::Zstd => {
+                Some(zstd::stream::copy_encode(data, &mut out, 0).map(|_| out))
+            }
+        }
+    }
+}
+
 #[derive(Debug)]
 pub struct WsSession {
     /// unique session id
@@ -27,9 +78,46 @@ pub struct WsSession {
     pub user_id: String,
 
     pub start_at: chrono::DateTime<chrono::Utc>,
+
+    pub compression: Option<FrameCompression>,
 }
 
 impl WsSession {
+    /// Sends `msg` in a binary frame, compressed when the client asked for it.
+    fn send(&self, ctx: &mut ws::WebsocketContext<Self>, msg: &impl Message) {
+        let data = msg.encode_to_vec();
+        let Some(compression) = self.compression else {
+            return ctx.binary(data);
+        };
+        self.send_frame(ctx, compression, &data, 0);
+    }
+
+    /// Pushes a notification, to clients which get flag bytes only: without
+    /// them it couldn't be told apart from the answer to a command.
+    fn push(&self, ctx: &mut ws::WebsocketContext<Self>, msg: &impl Message) {
+        if let Some(compression) = self.compression {
+            self.send_frame(ctx, compression, &msg.encode_to_vec(), FLAG_PUSH);
+        }
+    }
+
+    fn send_frame(
+        &self,
+        ctx: &mut ws::WebsocketContext<Self>,
+        compression: FrameCompression,
+        data: &[u8],
+        flags: u8,
+    ) {
+        match compression.compress(data, flags) {
+            Some(Ok(frame)) => return ctx.binary(frame),
+            Some(Err(e)) => log::warn!("failed to compress a frame: {e}"),
+            None => {}
+        }
+        let mut frame = Vec::with_capacity(data.len() + 1);
+        frame.push(flags);
+        frame.extend_from_slice(data);
+        ctx.binary(frame);
+    }
+
     /// helper method that sends ping to client every 5 seconds (HEARTBEAT_INTERVAL).
     ///
     /// also this method checks heartbeats from client
@@ -113,13 +201,14 @@ impl Handler<ws_server::ServerMessage> for WsSession {
                 if let Some(reason) = &reason {
                     // js 无法获取close的reason
                     if let Some(desc) = &reason.description {
-                        if desc != "" {
+                        if !desc.is_empty() {
                             ctx.text(desc.as_ref());
                         }
                     }
                 }
                 ctx.close(reason);
-            } // ws_server::ServerMessage::Binary(bin) => ctx.binary(bin),
+            }
+            ws_server::ServerMessage::Notification(notification) => self.push(ctx, &notification), // ws_server::ServerMessage::Binary(bin) => ctx.binary(bin),
         }
     }
 }
@@ -151,20 +240,51 @@ impl StreamHandler<Result<ws::Message, ws::ProtocolError>> for WsSession {
                     let v: Vec<&str> = m.splitn(2, ' ').collect();
                     match v[0] {
                         "/article_detail" => {
+                            // /article_detail <article_id> [html|markdown|plaintext]
+                            let args: Vec<&str> = m.split_whitespace().skip(1).collect();
+                            let format = match args.get(1).map(|f| f.parse::<ContentFormat>()) {
+                                None => Ok(ContentFormat::default()),
+                                Some(format) => format,
+                            };
+                            match (args.first(), format) {
+                                (Some(article_id), Ok(format)) => {
+                                    // send message to chat server
+                                    self.addr
+                                        .send(ws_server::GetArticleDetail {
+                                            article_id: article_id.to_string(),
+                                            format,
+                                            session_id: self.id,
+                                            user_id: self.user_id.to_owned(),
+                                        })
+                                        .into_actor(self)
+                                        .then(|res, act, ctx| {
+                                            if let Ok(Ok(res)) = res {
+                                                act.send(ctx, &res);
+                                            } else {
+                                                ctx.stop();
+                                            }
+                                            fut::ready(())
+                                        })
+                                        .wait(ctx);
+                                }
+                                (Some(_), Err(e)) => ctx.text(format!("!!! {e}")),
+                                (None, _) => ctx.text("!!! article id is required"),
+                            }
+                        }
+                        "/bookmarks" => {
                             if v.len() == 2 {
-                                // send message to chat server
                                 self.addr
-                                    .send(ws_server::GetArticleDetail {
+                                    .send(ws_server::ListBookmarks {
                                         article_id: v[1].to_owned(),
                                         session_id: self.id,
                                         user_id: self.user_id.to_owned(),
                                     })
                                     .into_actor(self)
-                                    .then(|res, _act, ctx| {
-                                        if let Ok(Ok(res)) = res {
-                                            ctx.binary(res.encode_to_vec());
-                                        } else {
-                                            ctx.stop();
+                                    .then(|res, act, ctx| {
+                                        match res {
+                                            Ok(Ok(res)) => act.send(ctx, &res),
+                                            Ok(Err(e)) => ctx.text(format!("!!! {e}")),
+                                            Err(_) => ctx.stop(),
                                         }
                                         fut::ready(())
                                     })
@@ -173,6 +293,58 @@ impl StreamHandler<Result<ws::Message, ws::ProtocolError>> for WsSession {
                                 ctx.text("!!! article id is required");
                             }
                         }
+                        "/add_bookmark" => {
+                            // /add_bookmark <article_id> <anchor> [label]
+                            let args: Vec<&str> = m.splitn(4, ' ').skip(1).collect();
+                            if args.len() >= 2 {
+                                self.addr
+                                    .send(ws_server::AddBookmark {
+                                        article_id: args[0].to_owned(),
+                                        anchor: args[1].to_owned(),
+                                        label: args.get(2).unwrap_or(&"").to_string(),
+                                        session_id: self.id,
+                                        user_id: self.user_id.to_owned(),
+                                    })
+                                    .into_actor(self)
+                                    .then(|res, act, ctx| {
+                                        match res {
+                                            Ok(Ok(res)) => act.send(ctx, &res),
+                                            Ok(Err(e)) => ctx.text(format!("!!! {e}")),
+                                            Err(_) => ctx.stop(),
+                                        }
+                                        fut::ready(())
+                                    })
+                                    .wait(ctx);
+                            } else {
+                                ctx.text("!!! article id and anchor are required");
+                            }
+                        }
+                        "/delete_bookmark" => match v.get(1).map(|id| id.parse::<i32>()) {
+                            Some(Ok(id)) => {
+                                self.addr
+                                    .send(ws_server::DeleteBookmark {
+                                        id,
+                                        session_id: self.id,
+                                        user_id: self.user_id.to_owned(),
+                                    })
+                                    .into_actor(self)
+                                    .then(move |res, _act, ctx| {
+                                        match res {
+                                            Ok(Ok(true)) => {
+                                                ctx.text(format!("bookmark {id} deleted"))
+                                            }
+                                            Ok(Ok(false)) => {
+                                                ctx.text(format!("!!! bookmark {id} not found"))
+                                            }
+                                            Ok(Err(e)) => ctx.text(format!("!!! {e}")),
+                                            Err(_) => ctx.stop(),
+                                        }
+                                        fut::ready(())
+                                    })
+                                    .wait(ctx);
+                            }
+                            _ => ctx.text("!!! bookmark id is required"),
+                        },
                         _ => ctx.text(format!("!!! unknown command: {m:?}")),
                     }
                 } else {
@@ -191,3 +363,25 @@ impl StreamHandler<Result<ws::Message, ws::ProtocolError>> for WsSession {
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn flags_compressed_and_pushed_frames() {
+        let data = vec![b'x'; MIN_COMPRESS_LEN];
+        let frame = FrameCompression::Zstd
+            .compress(&data, FLAG_PUSH)
+            .unwrap()
+            .unwrap();
+        assert_eq!(frame[0], FLAG_PUSH | FLAG_COMPRESSED);
+        assert_eq!(zstd::decode_all(&frame[1..]).unwrap(), data);
+
+        let frame = FrameCompression::Gzip.compress(&data, 0).unwrap().unwrap();
+        assert_eq!(frame[0], FLAG_COMPRESSED);
+
+        assert!(FrameCompression::None.compress(&data, 0).is_none());
+        assert!(FrameCompression::Gzip.compress(&data[1..], 0).is_none());
+    }
+}
@@ -0,0 +1,5 @@
+-- This file should undo anything in `up.sql`
+DROP TABLE "user_role";
+DROP TABLE "user_study_info";
+DROP TABLE "ws_connect_info";
+DROP TABLE "article_bookmark";
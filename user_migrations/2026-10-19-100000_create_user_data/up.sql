@@ -0,0 +1,40 @@
+-- User data kept apart from the crawled content, see USER_DATABASE_URL.
+-- SQLite can't enforce foreign keys across attached databases, the server
+-- checks that referenced articles exist instead.
+
+CREATE TABLE IF NOT EXISTS "user_role" (
+	"user_id" VARCHAR(255) NOT NULL  ,
+	"role" INTEGER NOT NULL  ,
+	"created_at" BIGINT NOT NULL  ,
+	"valid_before" BIGINT NOT NULL  ,
+	"id" INTEGER NOT NULL PRIMARY KEY AUTOINCREMENT
+);
+CREATE INDEX IF NOT EXISTS "user_role_user_id_IDX" ON "user_role" ("user_id");
+CREATE TABLE IF NOT EXISTS "user_study_info" (
+	"id" INTEGER NOT NULL  PRIMARY KEY AUTOINCREMENT,
+	"course_id" VARCHAR(255) NOT NULL  ,
+	"article_id" VARCHAR(255) NOT NULL  ,
+	"last_study_at" BIGINT NOT NULL DEFAULT '0' ,
+	"study_percent" FLOAT NOT NULL DEFAULT '0' ,
+	"user_id" VARCHAR(255) NOT NULL
+);
+CREATE UNIQUE INDEX IF NOT EXISTS "uniq_key" ON "user_study_info" ("user_id", "article_id");
+CREATE INDEX IF NOT EXISTS "user_study_info_FK" ON "user_study_info" ("course_id");
+CREATE INDEX IF NOT EXISTS "user_study_info_FK_1" ON "user_study_info" ("article_id");
+CREATE INDEX IF NOT EXISTS "user_study_info_user_id_IDX" ON "user_study_info" ("user_id", "last_study_at");
+CREATE TABLE IF NOT EXISTS "ws_connect_info" (
+	"id" INTEGER NOT NULL PRIMARY KEY AUTOINCREMENT,
+	"user_id" VARCHAR(255) NOT NULL  ,
+	"start_at" BIGINT NOT NULL  ,
+	"end_at" BIGINT NOT NULL DEFAULT '0'
+);
+CREATE INDEX IF NOT EXISTS "ws_connect_info_user_id_start_at_index" ON "ws_connect_info" ("user_id", "start_at");
+CREATE TABLE IF NOT EXISTS "article_bookmark" (
+	"id" INTEGER NOT NULL PRIMARY KEY AUTOINCREMENT,
+	"user_id" VARCHAR(255) NOT NULL  ,
+	"article_id" VARCHAR(255) NOT NULL  ,
+	"anchor" VARCHAR(255) NOT NULL  ,
+	"label" VARCHAR(255) NOT NULL DEFAULT '' ,
+	"created_at" BIGINT NOT NULL
+);
+CREATE INDEX IF NOT EXISTS "article_bookmark_user_id_article_id_IDX" ON "article_bookmark" ("user_id", "article_id");
@@ -0,0 +1,4 @@
+-- This file should undo anything in `up.sql`
+DROP TABLE "comment_flag";
+DROP TABLE "comment_like";
+DROP TABLE "user_comment";
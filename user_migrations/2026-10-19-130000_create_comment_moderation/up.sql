@@ -0,0 +1,23 @@
+-- Your SQL goes here
+
+-- state set by moderators on imported and user comments alike
+CREATE TABLE IF NOT EXISTS "comment_moderation" (
+	"comment_id" VARCHAR(255) NOT NULL PRIMARY KEY,
+	"article_id" VARCHAR(255) NOT NULL  ,
+	"hidden" TINYINT NOT NULL DEFAULT '0' ,
+	-- 0 unless pinned
+	"pinned_at" BIGINT NOT NULL DEFAULT '0' ,
+	"updated_at" BIGINT NOT NULL  ,
+	"updated_by" VARCHAR(255) NOT NULL
+);
+CREATE INDEX IF NOT EXISTS "comment_moderation_article_id_IDX" ON "comment_moderation" ("article_id");
+-- every moderation action, kept after the comment is gone
+CREATE TABLE IF NOT EXISTS "comment_moderation_log" (
+	"id" INTEGER NOT NULL PRIMARY KEY AUTOINCREMENT,
+	"comment_id" VARCHAR(255) NOT NULL  ,
+	"moderator_id" VARCHAR(255) NOT NULL  ,
+	"action" VARCHAR(255) NOT NULL  ,
+	"reason" VARCHAR(255) NOT NULL DEFAULT '' ,
+	"created_at" BIGINT NOT NULL
+);
+CREATE INDEX IF NOT EXISTS "comment_moderation_log_comment_id_IDX" ON "comment_moderation_log" ("comment_id");